@@ -0,0 +1,331 @@
+//! Cumulative opportunities: how many shops, schools, jobs, etc. you can get to from a place,
+//! counting nearer ones more. This answers "how much can I reach in 30 minutes" instead of "how
+//! long does it take to get downtown".
+use crate::graphics::Point2DData;
+use crate::gtfs::Seconds;
+use crate::isochrone::TravelTimeField;
+use crate::lat_lon_to_x_y;
+use crate::routing::{Heading, Snap, StreetGraph};
+use crate::table::{Columns, TableError};
+use crate::transit::{StationWalks, Timetable, NEVER};
+use geo_types::Point;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+/// Something worth getting to. The weight is e.g. the number of jobs there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opportunity {
+    pub location_x_y: Point2DData,
+    pub weight: f64,
+}
+
+/// Tagged OSM nodes, e.g. everything with an `amenity` or `shop` tag, each counting once
+pub fn from_osm<'a, I, P>(nodes: I, select: P) -> Vec<Opportunity>
+where
+    I: IntoIterator<Item = (&'a HashMap<String, String>, Point2DData)>,
+    P: Fn(&HashMap<String, String>) -> bool,
+{
+    nodes
+        .into_iter()
+        .filter(|(tags, _)| select(tags))
+        .map(|(_, location_x_y)| Opportunity {
+            location_x_y,
+            weight: 1.0,
+        })
+        .collect()
+}
+
+/// A CSV with `lat` and `lon` columns, and optionally `weight`, which defaults to 1
+pub fn load_csv<R: Read>(reader: R, centroid: &Point<f64>) -> Result<Vec<Opportunity>, TableError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let columns = Columns::new(reader.headers()?);
+    let lat_column = columns.required("lat")?;
+    let lon_column = columns.required("lon")?;
+    let weight_column = columns.optional("weight");
+
+    let mut opportunities = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let lat = columns.parse(&record, lat_column)?;
+        let lon = columns.parse(&record, lon_column)?;
+        let weight = columns
+            .parse_optional(&record, weight_column)?
+            .unwrap_or(1.0);
+        opportunities.push(Opportunity {
+            location_x_y: lat_lon_to_x_y(centroid, (lat, lon)),
+            weight,
+        });
+    }
+    Ok(opportunities)
+}
+
+/// How much an opportunity counts, given how many minutes away it is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decay {
+    /// Everything within this many minutes counts fully, and nothing farther counts at all
+    Threshold(f64),
+    /// Counts less and less, until nothing at this many minutes
+    Linear(f64),
+    /// Counts half as much every this many minutes
+    Exponential { half_life: f64 },
+}
+
+impl Decay {
+    pub fn factor(self, minutes: f64) -> f64 {
+        match self {
+            Decay::Threshold(max_minutes) => {
+                if minutes <= max_minutes {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Decay::Linear(max_minutes) => (1.0 - minutes / max_minutes).max(0.0),
+            Decay::Exponential { half_life } => 0.5_f64.powf(minutes / half_life),
+        }
+    }
+}
+
+/// Opportunities snapped to the walking network once, so that each place only needs a short
+/// search out to the farthest anything counts
+pub struct Accessibility {
+    graph: Arc<StreetGraph>,
+    opportunities: Vec<(Snap, f64)>,
+    max_minutes: f64,
+    transit: Option<Transit>,
+}
+
+/// Riding to opportunities, leaving at one time
+struct Transit {
+    timetable: Timetable,
+    walks: Arc<StationWalks>,
+    departure: Seconds,
+    /// For each opportunity, the stops it's within `max_minutes` of and the minutes to walk there
+    egress: Vec<Vec<(usize, f64)>>,
+}
+
+impl Accessibility {
+    /// Nothing more than `max_minutes` away counts, whatever the decay, e.g. the longest threshold
+    pub fn new(graph: Arc<StreetGraph>, opportunities: &[Opportunity], max_minutes: f64) -> Self {
+        let opportunities = opportunities
+            .iter()
+            .filter_map(|opportunity| {
+                graph
+                    .snap(opportunity.location_x_y)
+                    .map(|snap| (snap, opportunity.weight))
+            })
+            .collect();
+        Accessibility {
+            graph,
+            opportunities,
+            max_minutes,
+            transit: None,
+        }
+    }
+
+    /// Door to door over a schedule, like the scenarios: walk to a station, ride, and walk from the
+    /// last one. Walking the whole way still counts.
+    pub fn with_transit(
+        self,
+        timetable: Timetable,
+        walks: Arc<StationWalks>,
+        departure: Seconds,
+    ) -> Self {
+        let egress = self
+            .opportunities
+            .iter()
+            .map(|(snap, _)| {
+                walks
+                    .stops_near(snap, Heading::Outbound)
+                    .into_iter()
+                    .filter(|&(_, minutes)| minutes <= self.max_minutes)
+                    .collect()
+            })
+            .collect();
+        Accessibility {
+            transit: Some(Transit {
+                timetable,
+                walks,
+                departure,
+                egress,
+            }),
+            ..self
+        }
+    }
+
+    /// The weighted count of opportunities that can be reached from this place, for each decay
+    pub fn at(&self, origin: Point2DData, decays: &[Decay]) -> Vec<f64> {
+        let mut totals = vec![0.0; decays.len()];
+        let snap = match self.graph.snap(origin) {
+            Some(snap) => snap,
+            None => return totals,
+        };
+        let walk = TravelTimeField::within(
+            self.graph.clone(),
+            &[(origin, 0.0)],
+            Heading::Outbound,
+            self.max_minutes,
+        );
+        let mut minutes: Vec<f64> = self
+            .opportunities
+            .iter()
+            .map(|(snap, _)| walk.sample_snap(snap).unwrap_or(f64::INFINITY))
+            .collect();
+
+        if let Some(transit) = &self.transit {
+            let to_seconds = |minutes: f64| (minutes * 60.0).round() as Seconds;
+            let access: Vec<(usize, Seconds)> = transit
+                .walks
+                .stops_near(&snap, Heading::Inbound)
+                .into_iter()
+                .filter(|&(_, minutes)| minutes <= self.max_minutes)
+                .map(|(stop, minutes)| (stop, transit.departure + to_seconds(minutes)))
+                .collect();
+            let arrivals = transit
+                .timetable
+                .earliest_arrivals(&access, transit.departure + to_seconds(self.max_minutes));
+            for (minutes, egress) in minutes.iter_mut().zip(&transit.egress) {
+                for &(stop, walk_minutes) in egress {
+                    if arrivals[stop] != NEVER {
+                        let ride_minutes = f64::from(arrivals[stop] - transit.departure) / 60.0;
+                        *minutes = minutes.min(ride_minutes + walk_minutes);
+                    }
+                }
+            }
+        }
+
+        for ((_, weight), &minutes) in self.opportunities.iter().zip(&minutes) {
+            if minutes <= self.max_minutes {
+                for (total, decay) in totals.iter_mut().zip(decays) {
+                    *total += weight * decay.factor(minutes);
+                }
+            }
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gtfs::Station;
+    use crate::routing::WALKING_METERS_PER_MINUTE;
+    use crate::testing::{tags, two_stop_feed};
+    use crate::MyNode;
+
+    #[test]
+    fn test_decay() {
+        assert_eq!(Decay::Threshold(30.0).factor(30.0), 1.0);
+        assert_eq!(Decay::Threshold(30.0).factor(31.0), 0.0);
+        assert_eq!(Decay::Linear(30.0).factor(15.0), 0.5);
+        assert_eq!(Decay::Linear(30.0).factor(45.0), 0.0);
+        assert_eq!(Decay::Exponential { half_life: 10.0 }.factor(20.0), 0.25);
+    }
+
+    #[test]
+    fn test_load_csv() {
+        let centroid = Point::new(42.386755, -71.098472);
+        let csv = "name,lat,lon,weight\nCity Hall,42.386755,-71.098472,250\n";
+        let opportunities = load_csv(csv.as_bytes(), &centroid).unwrap();
+        assert_eq!(opportunities.len(), 1);
+        assert_eq!(opportunities[0].weight, 250.0);
+        assert!(opportunities[0].location_x_y.to_vector().length() < 1e-6);
+
+        let unweighted = load_csv("lat,lon\n42.38,-71.09\n".as_bytes(), &centroid).unwrap();
+        assert_eq!(unweighted[0].weight, 1.0);
+
+        match load_csv("lat,lon\n42.38,-71.09\n42.38,west\n".as_bytes(), &centroid) {
+            Err(TableError::Parse { line, column, .. }) => {
+                assert_eq!((line, column.as_str()), (3, "lon"));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(matches!(
+            load_csv("lat\n42.38\n".as_bytes(), &centroid),
+            Err(TableError::MissingColumn(_))
+        ));
+    }
+
+    #[test]
+    fn test_accessibility() {
+        // One street going east, with shops every kilometer
+        let tags = tags(&[("highway", "footway")]);
+        let nodes: Vec<MyNode> = (0..4)
+            .map(|i| MyNode {
+                x_y_meters: [0.0, f64::from(i) * 1000.0],
+                node_id: i64::from(i),
+            })
+            .collect();
+        let graph = Arc::new(StreetGraph::new(vec![(&tags, nodes)]));
+        let opportunities: Vec<Opportunity> = (1..4)
+            .map(|i| Opportunity {
+                location_x_y: Point2DData::new(0.0, f64::from(i) * 1000.0),
+                weight: 1.0,
+            })
+            .collect();
+        let accessibility = Accessibility::new(graph, &opportunities, 60.0);
+
+        let minutes_per_km = 1000.0 / WALKING_METERS_PER_MINUTE;
+        let totals = accessibility.at(
+            Point2DData::new(0.0, 0.0),
+            &[
+                Decay::Threshold(minutes_per_km * 2.0 + 0.1),
+                Decay::Linear(minutes_per_km * 4.0),
+            ],
+        );
+        assert_eq!(totals[0], 2.0);
+        assert!((totals[1] - (0.75 + 0.5 + 0.25)).abs() < 1e-9);
+    }
+
+    /// A shop at the far end of a long street, which is too far to walk but not to ride to
+    #[test]
+    fn test_accessibility_by_transit() {
+        let tags = tags(&[("highway", "footway")]);
+        let nodes: Vec<MyNode> = (0..10)
+            .map(|i| MyNode {
+                x_y_meters: [0.0, f64::from(i) * 1000.0],
+                node_id: i64::from(i),
+            })
+            .collect();
+        let graph = Arc::new(StreetGraph::new(vec![(&tags, nodes)]));
+        let far_end = Point2DData::new(0.0, 9000.0);
+        let shop = [Opportunity {
+            location_x_y: far_end,
+            weight: 1.0,
+        }];
+        let decays = [Decay::Threshold(10.0)];
+        let walking = Accessibility::new(graph.clone(), &shop, 10.0);
+        assert_eq!(walking.at(Point2DData::new(0.0, 0.0), &decays), vec![0.0]);
+
+        // A train every 10 minutes, taking 2 minutes between the ends of the street
+        let feed = two_stop_feed(8 * 3600, 9 * 3600);
+        let timetable = Timetable::from_feed(&feed, 20191104, &[0]);
+        let station = |id: &str, location_x_y: Point2DData| Station {
+            id: id.to_string(),
+            name: id.to_string(),
+            location_x_y,
+            stop_ids: vec![id.to_string()],
+            route_ids: vec![],
+        };
+        let walks = Arc::new(StationWalks::new(
+            graph,
+            &timetable,
+            &[
+                station("a", Point2DData::new(0.0, 0.0)),
+                station("b", far_end),
+            ],
+        ));
+        let riding = walking.with_transit(timetable, walks, 8 * 3600);
+        assert_eq!(riding.at(Point2DData::new(0.0, 0.0), &decays), vec![1.0]);
+        // Just missed the train, and the next one is too late
+        let riding = Accessibility {
+            transit: riding.transit.map(|transit| Transit {
+                departure: 8 * 3600 + 60,
+                ..transit
+            }),
+            ..riding
+        };
+        assert_eq!(riding.at(Point2DData::new(0.0, 0.0), &decays), vec![0.0]);
+    }
+}
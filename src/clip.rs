@@ -0,0 +1,250 @@
+//! Clipping lines and polygons to a box, so that we only tessellate what's in the viewport.
+use crate::graphics::{Box2DData, Point2DData};
+
+/// Clip the segment from a to b to the box with the Liang–Barsky algorithm. This returns the part
+/// of the segment inside the box, if any, even if neither end is inside.
+pub fn clip_segment(
+    a: Point2DData,
+    b: Point2DData,
+    box2d: &Box2DData,
+) -> Option<(Point2DData, Point2DData)> {
+    let delta = b - a;
+    let mut t_min: f64 = 0.0;
+    let mut t_max: f64 = 1.0;
+
+    // Each edge of the box, as p * t <= q
+    let edges = [
+        (-delta.x, a.x - box2d.min.x),
+        (delta.x, box2d.max.x - a.x),
+        (-delta.y, a.y - box2d.min.y),
+        (delta.y, box2d.max.y - a.y),
+    ];
+    for &(p, q) in &edges {
+        if p == 0.0 {
+            // Parallel to this edge, so it's either all outside or it doesn't matter
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t_min = t_min.max(t);
+            } else {
+                t_max = t_max.min(t);
+            }
+        }
+    }
+
+    if t_min > t_max {
+        None
+    } else {
+        Some((a + delta * t_min, a + delta * t_max))
+    }
+}
+
+/// Clip a polyline to the box. A line that leaves and re-enters the box becomes several lines.
+pub fn clip_polyline(points: &[Point2DData], box2d: &Box2DData) -> Vec<Vec<Point2DData>> {
+    let mut polylines: Vec<Vec<Point2DData>> = vec![];
+    let mut current: Vec<Point2DData> = vec![];
+    for segment in points.windows(2) {
+        match clip_segment(segment[0], segment[1], box2d) {
+            Some((start, end)) => {
+                if current.last() != Some(&start) {
+                    if current.len() >= 2 {
+                        polylines.push(current);
+                    }
+                    current = vec![start];
+                }
+                current.push(end);
+            }
+            None => {
+                if current.len() >= 2 {
+                    polylines.push(current);
+                }
+                current = vec![];
+            }
+        }
+    }
+    if current.len() >= 2 {
+        polylines.push(current);
+    }
+    polylines
+}
+
+/// One edge of a clipping box
+#[derive(Clone, Copy)]
+enum Edge {
+    MinX(f64),
+    MaxX(f64),
+    MinY(f64),
+    MaxY(f64),
+}
+
+impl Edge {
+    fn inside(self, point: Point2DData) -> bool {
+        match self {
+            Edge::MinX(x) => point.x >= x,
+            Edge::MaxX(x) => point.x <= x,
+            Edge::MinY(y) => point.y >= y,
+            Edge::MaxY(y) => point.y <= y,
+        }
+    }
+
+    /// Where the segment from a to b crosses this edge
+    fn intersection(self, a: Point2DData, b: Point2DData) -> Point2DData {
+        match self {
+            Edge::MinX(x) | Edge::MaxX(x) => {
+                Point2DData::new(x, a.y + (b.y - a.y) * (x - a.x) / (b.x - a.x))
+            }
+            Edge::MinY(y) | Edge::MaxY(y) => {
+                Point2DData::new(a.x + (b.x - a.x) * (y - a.y) / (b.y - a.y), y)
+            }
+        }
+    }
+}
+
+/// Clip a polygon to the box with the Sutherland–Hodgman algorithm. Concave polygons that leave
+/// and re-enter the box stay in one piece, connected by zero-area slivers along the edge of the
+/// box, which fill to nothing. Returns None if there is nothing left.
+pub fn clip_polygon(points: &[Point2DData], box2d: &Box2DData) -> Option<Vec<Point2DData>> {
+    let edges = [
+        Edge::MinX(box2d.min.x),
+        Edge::MaxX(box2d.max.x),
+        Edge::MinY(box2d.min.y),
+        Edge::MaxY(box2d.max.y),
+    ];
+
+    let mut output = points.to_vec();
+    for &edge in &edges {
+        let input = output;
+        output = vec![];
+        let mut previous = *input.last()?;
+        for &point in &input {
+            match (edge.inside(previous), edge.inside(point)) {
+                (true, true) => output.push(point),
+                (true, false) => output.push(edge.intersection(previous, point)),
+                (false, true) => {
+                    output.push(edge.intersection(previous, point));
+                    output.push(point);
+                }
+                (false, false) => {}
+            }
+            previous = point;
+        }
+    }
+
+    if output.len() >= 3 {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Box2DData {
+        Box2DData::new(Point2DData::new(0.0, 0.0), Point2DData::new(1.0, 1.0))
+    }
+
+    #[test]
+    fn test_clip_segment_inside() {
+        let a = Point2DData::new(0.25, 0.25);
+        let b = Point2DData::new(0.75, 0.5);
+        assert_eq!(clip_segment(a, b, &unit_box()), Some((a, b)));
+    }
+
+    /// Neither end is inside, but the middle is
+    #[test]
+    fn test_clip_segment_crossing() {
+        let clipped = clip_segment(
+            Point2DData::new(-1.0, 0.5),
+            Point2DData::new(2.0, 0.5),
+            &unit_box(),
+        );
+        assert_eq!(
+            clipped,
+            Some((Point2DData::new(0.0, 0.5), Point2DData::new(1.0, 0.5)))
+        );
+    }
+
+    #[test]
+    fn test_clip_segment_outside() {
+        // Parallel to an edge
+        assert_eq!(
+            clip_segment(
+                Point2DData::new(-1.0, 2.0),
+                Point2DData::new(2.0, 2.0),
+                &unit_box(),
+            ),
+            None
+        );
+        // Passes by a corner
+        assert_eq!(
+            clip_segment(
+                Point2DData::new(0.5, 2.0),
+                Point2DData::new(2.0, 0.5),
+                &unit_box(),
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clip_polyline_reenters() {
+        let polylines = clip_polyline(
+            &[
+                Point2DData::new(0.25, 0.5),
+                Point2DData::new(2.0, 0.5),
+                Point2DData::new(2.0, 0.75),
+                Point2DData::new(0.5, 0.75),
+                Point2DData::new(0.5, 0.9),
+            ],
+            &unit_box(),
+        );
+        assert_eq!(
+            polylines,
+            vec![
+                vec![Point2DData::new(0.25, 0.5), Point2DData::new(1.0, 0.5)],
+                vec![
+                    Point2DData::new(1.0, 0.75),
+                    Point2DData::new(0.5, 0.75),
+                    Point2DData::new(0.5, 0.9),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clip_polygon() {
+        // A big diamond around the whole box gets clipped to an octagon
+        let clipped = clip_polygon(
+            &[
+                Point2DData::new(0.5, -0.25),
+                Point2DData::new(1.25, 0.5),
+                Point2DData::new(0.5, 1.25),
+                Point2DData::new(-0.25, 0.5),
+            ],
+            &unit_box(),
+        )
+        .unwrap();
+        assert_eq!(clipped.len(), 8);
+        for point in clipped {
+            assert!(unit_box().contains(&point) || point.x == 1.0 || point.y == 1.0);
+        }
+
+        // Entirely outside
+        assert_eq!(
+            clip_polygon(
+                &[
+                    Point2DData::new(2.0, 2.0),
+                    Point2DData::new(3.0, 2.0),
+                    Point2DData::new(3.0, 3.0),
+                ],
+                &unit_box(),
+            ),
+            None
+        );
+    }
+}
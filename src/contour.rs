@@ -0,0 +1,428 @@
+//! Contour lines and filled bands over a scalar field, e.g. the 15, 20 and 25 minute boundaries
+//! of a travel time map, which read better than blocky grid cells. This is marching squares with
+//! each cell cut into four triangles around its center. The value is linear within a triangle,
+//! so lines and bands agree exactly, and saddles are settled by the average of the corners.
+use crate::graphics::{Box2DData, Point2DData};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Values sampled on a regular grid
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    /// Where the southwest sample is
+    pub origin: Point2DData,
+    /// Between neighboring samples
+    pub spacing: f64,
+    pub n_cols: usize,
+    pub n_rows: usize,
+    /// Row by row from the south. None where there's no value, e.g. away from the streets.
+    pub values: Vec<Option<f64>>,
+}
+
+impl Raster {
+    /// Samples covering the viewport, at multiples of the spacing like `FnGrid`'s cell corners
+    pub fn sample<F>(viewport: Box2DData, spacing: f64, field: F) -> Self
+    where
+        F: Fn(Point2DData) -> Option<f64> + Sync,
+    {
+        let min_x = (viewport.min.x / spacing).floor() as isize;
+        let min_y = (viewport.min.y / spacing).floor() as isize;
+        let max_x = (viewport.max.x / spacing).ceil() as isize;
+        let max_y = (viewport.max.y / spacing).ceil() as isize;
+        let n_cols = (max_x - min_x + 1) as usize;
+        let n_rows = (max_y - min_y + 1) as usize;
+        let origin = Point2DData::new(min_x as f64 * spacing, min_y as f64 * spacing);
+        let values = (0..n_cols * n_rows)
+            .into_par_iter()
+            .map(|i| {
+                field(Point2DData::new(
+                    origin.x + (i % n_cols) as f64 * spacing,
+                    origin.y + (i / n_cols) as f64 * spacing,
+                ))
+            })
+            .collect();
+        Raster {
+            origin,
+            spacing,
+            n_cols,
+            n_rows,
+            values,
+        }
+    }
+
+    fn corner(&self, col: usize, row: usize) -> Option<(Point2DData, f64)> {
+        self.values[row * self.n_cols + col].map(|value| {
+            (
+                Point2DData::new(
+                    self.origin.x + col as f64 * self.spacing,
+                    self.origin.y + row as f64 * self.spacing,
+                ),
+                value,
+            )
+        })
+    }
+
+    /// The corners of each cell that has values at all four, counterclockwise from the southwest
+    fn cells(&self) -> Vec<[(Point2DData, f64); 4]> {
+        let mut cells = vec![];
+        for row in 0..self.n_rows.saturating_sub(1) {
+            for col in 0..self.n_cols.saturating_sub(1) {
+                if let (Some(sw), Some(se), Some(ne), Some(nw)) = (
+                    self.corner(col, row),
+                    self.corner(col + 1, row),
+                    self.corner(col + 1, row + 1),
+                    self.corner(col, row + 1),
+                ) {
+                    cells.push([sw, se, ne, nw]);
+                }
+            }
+        }
+        cells
+    }
+
+    /// Lines where the field crosses the threshold. Lines that close up repeat their first point.
+    pub fn isolines(&self, threshold: f64) -> Vec<Vec<Point2DData>> {
+        let segments: Vec<(Point2DData, Point2DData)> = self
+            .cells()
+            .iter()
+            .flat_map(triangles)
+            .filter_map(|triangle| {
+                let crossings: Vec<Point2DData> = (0..3)
+                    .filter_map(|i| {
+                        let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                        if (a.1 >= threshold) != (b.1 >= threshold) {
+                            Some(crossing(a, b, threshold))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                match crossings.as_slice() {
+                    [a, b] if a != b => Some((*a, *b)),
+                    _ => None,
+                }
+            })
+            .collect();
+        join_segments(segments)
+    }
+
+    /// Where the field is at least `lower` and below `upper`, which can be infinite, as rings that
+    /// don't repeat their first point. Outer rings go counterclockwise and holes clockwise, and
+    /// filling them all with the even-odd rule, like `Geom::MultiPolygon` does, gives the band.
+    pub fn isobands(&self, lower: f64, upper: f64) -> Vec<Vec<Point2DData>> {
+        let inside = |value: f64| value >= lower && value < upper;
+        // Counterclockwise, like the cells and their triangles
+        let mut pieces = vec![];
+        for cell in self.cells() {
+            let (_, center_value) = center(&cell);
+            // Most cells are all in or all out, and don't need cutting up
+            if cell.iter().all(|&(_, value)| inside(value)) && inside(center_value) {
+                pieces.push(cell.iter().map(|&(point, _)| point).collect());
+                continue;
+            }
+            for triangle in triangles(&cell) {
+                let clipped = clip(&clip(&triangle, lower, true), upper, false);
+                if clipped.len() >= 3 {
+                    pieces.push(clipped.into_iter().map(|(point, _)| point).collect());
+                }
+            }
+        }
+        join_pieces(pieces)
+    }
+}
+
+fn center(cell: &[(Point2DData, f64); 4]) -> (Point2DData, f64) {
+    let (sw, ne) = (cell[0].0, cell[2].0);
+    let value = cell.iter().map(|&(_, value)| value).sum::<f64>() / 4.0;
+    (sw.lerp(ne, 0.5), value)
+}
+
+fn triangles(cell: &[(Point2DData, f64); 4]) -> Vec<[(Point2DData, f64); 3]> {
+    let center = center(cell);
+    (0..4)
+        .map(|i| [cell[i], cell[(i + 1) % 4], center])
+        .collect()
+}
+
+/// Where the threshold is along the edge. Always from the lower end, so both triangles on an edge
+/// get exactly the same point.
+fn crossing(a: (Point2DData, f64), b: (Point2DData, f64), threshold: f64) -> Point2DData {
+    let ((low, low_value), (high, high_value)) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+    low.lerp(high, (threshold - low_value) / (high_value - low_value))
+}
+
+/// Sutherland–Hodgman against a threshold instead of a line, keeping what's above it or below it
+fn clip(
+    polygon: &[(Point2DData, f64)],
+    threshold: f64,
+    keep_above: bool,
+) -> Vec<(Point2DData, f64)> {
+    let inside = |value: f64| (value >= threshold) == keep_above;
+    let mut clipped = vec![];
+    for (i, &current) in polygon.iter().enumerate() {
+        let next = polygon[(i + 1) % polygon.len()];
+        if inside(current.1) {
+            clipped.push(current);
+        }
+        if inside(current.1) != inside(next.1) {
+            clipped.push((crossing(current, next, threshold), threshold));
+        }
+    }
+    clipped
+}
+
+/// Points that are exactly the same have the same key
+type Key = (u64, u64);
+
+fn key(point: Point2DData) -> Key {
+    (point.x.to_bits(), point.y.to_bits())
+}
+
+/// Segments joined end to end into as few lines as possible
+fn join_segments(segments: Vec<(Point2DData, Point2DData)>) -> Vec<Vec<Point2DData>> {
+    let mut by_end: HashMap<Key, Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        by_end.entry(key(*a)).or_default().push(i);
+        by_end.entry(key(*b)).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    // The other end of an unused segment that ends at this point, if there is one
+    let next = |point: Point2DData, used: &mut Vec<bool>| -> Option<Point2DData> {
+        let i = *by_end[&key(point)].iter().find(|&&i| !used[i])?;
+        used[i] = true;
+        let (a, b) = segments[i];
+        Some(if a == point { b } else { a })
+    };
+
+    let mut lines = vec![];
+    for i in 0..segments.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let (a, b) = segments[i];
+        let mut line = vec![a, b];
+        while let Some(point) = next(*line.last().unwrap(), &mut used) {
+            line.push(point);
+        }
+        // Lines that aren't loops can go on from the other end too
+        if line.first() != line.last() {
+            let mut back = vec![];
+            while let Some(point) = next(*back.last().unwrap_or(&a), &mut used) {
+                back.push(point);
+            }
+            back.reverse();
+            back.extend(line);
+            line = back;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Counterclockwise pieces merged into rings around them. An edge between two pieces goes opposite
+/// ways in each, and `crossing` puts their points in exactly the same place, so those edges cancel
+/// out. What's left is the outline, which is traced into rings like `join_segments` does.
+fn join_pieces(pieces: Vec<Vec<Point2DData>>) -> Vec<Vec<Point2DData>> {
+    let mut edges: Vec<(Point2DData, Point2DData)> = vec![];
+    let mut used = vec![];
+    // Edges that haven't been canceled out yet, by their ends
+    let mut open: HashMap<(Key, Key), Vec<usize>> = HashMap::new();
+    for piece in &pieces {
+        for (i, &a) in piece.iter().enumerate() {
+            let b = piece[(i + 1) % piece.len()];
+            if a == b {
+                continue;
+            }
+            match open.get_mut(&(key(b), key(a))).and_then(Vec::pop) {
+                Some(opposite) => used[opposite] = true,
+                None => {
+                    open.entry((key(a), key(b))).or_default().push(edges.len());
+                    edges.push((a, b));
+                    used.push(false);
+                }
+            }
+        }
+    }
+    let mut by_start: HashMap<Key, Vec<usize>> = HashMap::new();
+    for (i, (a, _)) in edges.iter().enumerate() {
+        if !used[i] {
+            by_start.entry(key(*a)).or_default().push(i);
+        }
+    }
+
+    let mut rings = vec![];
+    for i in 0..edges.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let (start, mut end) = edges[i];
+        let mut ring = vec![start];
+        // Every point has as many edges out as in, so this gets back to the start
+        while end != start {
+            ring.push(end);
+            match by_start[&key(end)].iter().find(|&&j| !used[j]) {
+                Some(&j) => {
+                    used[j] = true;
+                    end = edges[j].1;
+                }
+                None => break,
+            }
+        }
+        let ring = drop_collinear(ring);
+        if ring.len() >= 3 {
+            rings.push(ring);
+        }
+    }
+    rings
+}
+
+/// Without the points in the middle of straight runs, e.g. the grid points along the edge of a
+/// band that fills whole cells
+fn drop_collinear(ring: Vec<Point2DData>) -> Vec<Point2DData> {
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let (previous, point, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            (point - previous).cross(next - point) != 0.0
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Where to put labels along a line: every `spacing` starting half a spacing in, or just the
+/// middle of a line that's shorter than that
+pub fn label_points(line: &[Point2DData], spacing: f64) -> Vec<Point2DData> {
+    let length: f64 = line.windows(2).map(|w| (w[1] - w[0]).length()).sum();
+    let mut targets: Vec<f64> = (0..)
+        .map(|i| (i as f64 + 0.5) * spacing)
+        .take_while(|&distance| distance < length)
+        .collect();
+    if targets.is_empty() && length > 0.0 {
+        targets.push(length / 2.0);
+    }
+
+    let mut points = vec![];
+    let mut so_far = 0.0;
+    let mut targets = targets.into_iter().peekable();
+    for w in line.windows(2) {
+        let segment = (w[1] - w[0]).length();
+        while let Some(&target) = targets.peek() {
+            if target > so_far + segment {
+                break;
+            }
+            points.push(w[0].lerp(w[1], (target - so_far) / segment));
+            targets.next();
+        }
+        so_far += segment;
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::polygon_area;
+
+    /// The distance from the origin, sampled every 10 meters out to 100 in each direction
+    fn cone() -> Raster {
+        Raster::sample(
+            Box2DData::new(
+                Point2DData::new(-100.0, -100.0),
+                Point2DData::new(100.0, 100.0),
+            ),
+            10.0,
+            |point| Some(point.to_vector().length()),
+        )
+    }
+
+    #[test]
+    fn test_isolines() {
+        let raster = cone();
+        assert_eq!((raster.n_cols, raster.n_rows), (21, 21));
+        let lines = raster.isolines(50.0);
+        // One closed ring, close to the circle
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].first(), lines[0].last());
+        for point in &lines[0] {
+            assert!((point.to_vector().length() - 50.0).abs() < 1.0);
+        }
+        assert!(raster.isolines(500.0).is_empty());
+    }
+
+    /// Positive for counterclockwise rings and negative for clockwise ones
+    fn signed_area(ring: &[Point2DData]) -> f64 {
+        ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .map(|(a, b)| a.x * b.y - b.x * a.y)
+            .sum::<f64>()
+            / 2.0
+    }
+
+    #[test]
+    fn test_isobands() {
+        let raster = cone();
+        // Holes go the other way, so they take away from the area
+        let area = |lower, upper| -> f64 {
+            raster
+                .isobands(lower, upper)
+                .iter()
+                .map(|ring| signed_area(ring))
+                .sum()
+        };
+        let pi = std::f64::consts::PI;
+        assert!((area(0.0, 50.0) - pi * 50.0 * 50.0).abs() < 100.0);
+        assert!((area(50.0, 80.0) - pi * (80.0 * 80.0 - 50.0 * 50.0)).abs() < 150.0);
+        // Together, the bands cover the whole raster exactly once
+        let total = area(0.0, 50.0) + area(50.0, 80.0) + area(80.0, f64::INFINITY);
+        assert!((total - 200.0 * 200.0).abs() < 1e-6);
+
+        // One ring for the disk, and two for the ring around it
+        assert_eq!(raster.isobands(0.0, 50.0).len(), 1);
+        let annulus = raster.isobands(50.0, 80.0);
+        assert_eq!(annulus.len(), 2);
+        let hole = annulus.iter().find(|ring| signed_area(ring) < 0.0).unwrap();
+        assert!((polygon_area(hole) - pi * 50.0 * 50.0).abs() < 100.0);
+
+        // A band that fills whole cells is just their outline
+        let flat = Raster {
+            origin: Point2DData::new(0.0, 0.0),
+            spacing: 10.0,
+            n_cols: 4,
+            n_rows: 3,
+            values: vec![Some(1.0); 12],
+        };
+        assert_eq!(
+            flat.isobands(0.0, 2.0),
+            vec![vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(30.0, 0.0),
+                Point2DData::new(30.0, 20.0),
+                Point2DData::new(0.0, 20.0),
+            ]]
+        );
+
+        // Nothing where there are no values
+        let mut holey = raster.clone();
+        holey.values = vec![None; holey.values.len()];
+        assert!(holey.isobands(0.0, 50.0).is_empty());
+    }
+
+    #[test]
+    fn test_label_points() {
+        let line = [
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(100.0, 0.0),
+            Point2DData::new(100.0, 100.0),
+        ];
+        assert_eq!(
+            label_points(&line, 80.0),
+            vec![Point2DData::new(40.0, 0.0), Point2DData::new(100.0, 20.0)]
+        );
+        assert_eq!(
+            label_points(&line, 500.0),
+            vec![Point2DData::new(100.0, 0.0)]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Places people want to get to, like downtown, Harvard, MIT or Kendall, and how to combine the
+//! travel times to several of them into one number for accessibility maps.
+use crate::graphics::Point2DData;
+use crate::gtfs::Station;
+use std::collections::HashMap;
+
+/// A named place. Getting to any of its points will do, e.g. either of two stations, or any
+/// corner of a campus.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Destination {
+    pub name: String,
+    /// How much this destination counts in a weighted average
+    pub weight: f64,
+    pub points: Vec<Point2DData>,
+}
+
+impl Destination {
+    pub fn point(name: &str, weight: f64, location_x_y: Point2DData) -> Self {
+        Destination {
+            name: name.to_string(),
+            weight,
+            points: vec![location_x_y],
+        }
+    }
+
+    /// Stations from a feed, e.g. "place-pktrm" and "place-dwnxg" for Park Street or Downtown
+    /// Crossing. None if the feed has none of them, since nobody could get there.
+    pub fn from_stations(
+        name: &str,
+        weight: f64,
+        stations: &[Station],
+        ids: &[&str],
+    ) -> Option<Self> {
+        let points: Vec<Point2DData> = stations
+            .iter()
+            .filter(|station| ids.contains(&station.id.as_str()))
+            .map(|station| station.location_x_y)
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        Some(Destination {
+            name: name.to_string(),
+            weight,
+            points,
+        })
+    }
+
+    /// Every point of the ways selected by their OSM tags, e.g. `name=Harvard Yard`. For an area,
+    /// that's its outline, which is close enough to wherever inside it you're going.
+    pub fn from_osm<'a, I, P>(name: &str, weight: f64, ways: I, select: P) -> Self
+    where
+        I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<Point2DData>)>,
+        P: Fn(&HashMap<String, String>) -> bool,
+    {
+        Destination {
+            name: name.to_string(),
+            weight,
+            points: ways
+                .into_iter()
+                .filter(|(tags, _)| select(tags))
+                .flat_map(|(_, points)| points)
+                .collect(),
+        }
+    }
+}
+
+/// How to combine the travel times to several destinations
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    /// The closest destination, e.g. any job center will do
+    Min,
+    /// Each destination counted by its weight. Unreachable if any destination is.
+    WeightedAverage,
+}
+
+impl Aggregate {
+    /// `minutes` has the travel time to each of the destinations, in the same order
+    pub fn apply(self, destinations: &[Destination], minutes: &[Option<f64>]) -> Option<f64> {
+        assert_eq!(destinations.len(), minutes.len());
+        match self {
+            Aggregate::Min => minutes
+                .iter()
+                .filter_map(|&minutes| minutes)
+                .fold(None, |best: Option<f64>, minutes| {
+                    Some(best.map_or(minutes, |best| best.min(minutes)))
+                }),
+            Aggregate::WeightedAverage => {
+                let mut total = 0.0;
+                let mut total_weight = 0.0;
+                for (destination, minutes) in destinations.iter().zip(minutes) {
+                    total += destination.weight * (*minutes)?;
+                    total_weight += destination.weight;
+                }
+                if total_weight > 0.0 {
+                    Some(total / total_weight)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tags;
+
+    fn destinations() -> Vec<Destination> {
+        vec![
+            Destination::point("Downtown", 3.0, Point2DData::new(0.0, 0.0)),
+            Destination::point("Harvard", 1.0, Point2DData::new(1000.0, 0.0)),
+        ]
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let destinations = destinations();
+        let minutes = [Some(20.0), Some(40.0)];
+        assert_eq!(Aggregate::Min.apply(&destinations, &minutes), Some(20.0));
+        assert_eq!(
+            Aggregate::WeightedAverage.apply(&destinations, &minutes),
+            Some(25.0)
+        );
+
+        let unreachable = [Some(20.0), None];
+        assert_eq!(
+            Aggregate::Min.apply(&destinations, &unreachable),
+            Some(20.0)
+        );
+        assert_eq!(
+            Aggregate::WeightedAverage.apply(&destinations, &unreachable),
+            None
+        );
+        assert_eq!(Aggregate::Min.apply(&destinations, &[None, None]), None);
+    }
+
+    #[test]
+    fn test_from_stations() {
+        let stations = [Station {
+            id: String::from("place-pktrm"),
+            name: String::from("Park Street"),
+            location_x_y: Point2DData::new(10.0, 0.0),
+            stop_ids: vec![String::from("place-pktrm")],
+            route_ids: vec![],
+        }];
+        let downtown =
+            Destination::from_stations("Downtown", 1.0, &stations, &["place-pktrm", "place-dwnxg"])
+                .unwrap();
+        assert_eq!(downtown.points, vec![Point2DData::new(10.0, 0.0)]);
+        assert_eq!(
+            Destination::from_stations("Downtown", 1.0, &stations, &["place-dwnxg"]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_osm() {
+        let campus = tags(&[("name", "Harvard Yard")]);
+        let road = tags(&[]);
+        let ways = vec![
+            (
+                &campus,
+                vec![Point2DData::new(0.0, 0.0), Point2DData::new(10.0, 0.0)],
+            ),
+            (&road, vec![Point2DData::new(50.0, 0.0)]),
+        ];
+
+        let destination = Destination::from_osm("Harvard", 1.0, ways, |tags| {
+            tags.get("name").map(String::as_str) == Some("Harvard Yard")
+        });
+        assert_eq!(destination.points.len(), 2);
+    }
+}
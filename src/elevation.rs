@@ -0,0 +1,230 @@
+//! Ground elevation from a DEM raster, for how steep streets are. Rasters are read as ESRI ASCII
+//! grids: https://desktop.arcgis.com/en/arcmap/latest/manage-data/raster-and-images/esri-ascii-raster-format.htm
+//! Reading GeoTIFFs directly is out of scope, since their georeferencing takes a projection library
+//! to get right. One can be converted with `gdal_translate -of AAIGrid dem.tif dem.asc`. The grid
+//! has to be in longitude and latitude, e.g. after `gdalwarp -t_srs EPSG:4326`.
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+#[derive(Debug)]
+pub enum DemError {
+    Io(std::io::Error),
+    MissingHeader(String),
+    /// The line is 1-based, like in a text editor
+    Parse {
+        line: usize,
+        value: String,
+    },
+    WrongSize {
+        expected: usize,
+        found: usize,
+    },
+    /// `ncols` or `nrows` is 0
+    Empty,
+}
+
+impl fmt::Display for DemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DemError::Io(error) => write!(f, "{}", error),
+            DemError::MissingHeader(key) => write!(f, "missing header {}", key),
+            DemError::Parse { line, value } => write!(f, "line {}: can't parse {:?}", line, value),
+            DemError::WrongSize { expected, found } => {
+                write!(f, "expected {} cells, found {}", expected, found)
+            }
+            DemError::Empty => write!(f, "the grid has no cells"),
+        }
+    }
+}
+
+impl std::error::Error for DemError {}
+
+impl From<std::io::Error> for DemError {
+    fn from(error: std::io::Error) -> Self {
+        DemError::Io(error)
+    }
+}
+
+/// Elevations in meters on a regular grid of longitude and latitude
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dem {
+    n_cols: usize,
+    n_rows: usize,
+    /// The center of the lower left cell, in degrees
+    west: f64,
+    south: f64,
+    cell_size: f64,
+    /// Row by row from the north, like the file. None where there's no data.
+    elevations: Vec<Option<f64>>,
+}
+
+const HEADER_KEYS: &[&str] = &[
+    "ncols",
+    "nrows",
+    "xllcorner",
+    "yllcorner",
+    "xllcenter",
+    "yllcenter",
+    "cellsize",
+    "nodata_value",
+];
+
+impl Dem {
+    pub fn load_ascii_grid<R: Read>(reader: R) -> Result<Self, DemError> {
+        let mut header: Vec<(String, f64)> = vec![];
+        let mut elevations = vec![];
+        let mut nodata = None;
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let parse = |value: &str| -> Result<f64, DemError> {
+                value.parse().map_err(|_| DemError::Parse {
+                    line: i + 1,
+                    value: value.to_string(),
+                })
+            };
+            let mut words = line.split_whitespace().peekable();
+            // Headers come first. Data can start with a letter too, e.g. "nan".
+            match words.peek().map(|word| word.to_lowercase()) {
+                Some(key) if elevations.is_empty() && HEADER_KEYS.contains(&key.as_str()) => {
+                    words.next();
+                    let value = parse(words.next().unwrap_or(""))?;
+                    if key == "nodata_value" {
+                        nodata = Some(value);
+                    } else {
+                        header.push((key, value));
+                    }
+                }
+                _ => {
+                    for word in words {
+                        // GDAL writes NaN where there's no data if that's the nodata value
+                        let elevation = parse(word)?;
+                        elevations.push(if Some(elevation) == nodata || !elevation.is_finite() {
+                            None
+                        } else {
+                            Some(elevation)
+                        });
+                    }
+                }
+            }
+        }
+
+        let get = |key: &str| header.iter().find(|(k, _)| k == key).map(|&(_, v)| v);
+        let require = |key: &str| get(key).ok_or_else(|| DemError::MissingHeader(key.to_string()));
+        let n_cols = require("ncols")? as usize;
+        let n_rows = require("nrows")? as usize;
+        if n_cols == 0 || n_rows == 0 {
+            return Err(DemError::Empty);
+        }
+        let cell_size = require("cellsize")?;
+        // Either the corner of the lower left cell or its center
+        let (west, south) = match (get("xllcenter"), get("yllcenter")) {
+            (Some(x), Some(y)) => (x, y),
+            _ => (
+                require("xllcorner")? + cell_size / 2.0,
+                require("yllcorner")? + cell_size / 2.0,
+            ),
+        };
+        if elevations.len() != n_cols * n_rows {
+            return Err(DemError::WrongSize {
+                expected: n_cols * n_rows,
+                found: elevations.len(),
+            });
+        }
+        Ok(Dem {
+            n_cols,
+            n_rows,
+            west,
+            south,
+            cell_size,
+            elevations,
+        })
+    }
+
+    fn cell(&self, col: usize, row_from_south: usize) -> Option<f64> {
+        self.elevations[(self.n_rows - 1 - row_from_south) * self.n_cols + col]
+    }
+
+    /// Interpolated between the four nearest cells. None outside the grid or next to a cell with
+    /// no data.
+    pub fn elevation(&self, lat: f64, lon: f64) -> Option<f64> {
+        let x = (lon - self.west) / self.cell_size;
+        let y = (lat - self.south) / self.cell_size;
+        let max_x = (self.n_cols - 1) as f64;
+        let max_y = (self.n_rows - 1) as f64;
+        if !(0.0..=max_x).contains(&x) || !(0.0..=max_y).contains(&y) {
+            return None;
+        }
+        let (col, row) = (
+            x.floor().min(max_x - 1.0).max(0.0),
+            y.floor().min(max_y - 1.0).max(0.0),
+        );
+        let (fx, fy) = (x - col, y - row);
+        let (col, row) = (col as usize, row as usize);
+        // A grid that's one cell wide or tall only has the one cell to interpolate along
+        let next_col = (col + 1).min(self.n_cols - 1);
+        let next_row = (row + 1).min(self.n_rows - 1);
+        let south = self.cell(col, row)? * (1.0 - fx) + self.cell(next_col, row)? * fx;
+        let north = self.cell(col, next_row)? * (1.0 - fx) + self.cell(next_col, next_row)? * fx;
+        Some(south * (1.0 - fy) + north * fy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_ascii_grid() {
+        // Rising 10 meters per cell to the east, and with a hole in the northeast
+        let grid = "ncols 3\n\
+                    nrows 2\n\
+                    xllcorner -71.2\n\
+                    yllcorner 42.3\n\
+                    cellsize 0.1\n\
+                    NODATA_value -9999\n\
+                    0 10 -9999\n\
+                    0 10 20\n";
+        let dem = Dem::load_ascii_grid(grid.as_bytes()).unwrap();
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-9;
+        assert!(close(dem.elevation(42.35, -71.15), 0.0));
+        assert!(close(dem.elevation(42.35, -71.1), 5.0));
+        assert!(close(dem.elevation(42.37, -71.1), 5.0));
+        assert_eq!(dem.elevation(42.45, -71.0), None);
+        assert_eq!(dem.elevation(43.0, -71.15), None);
+
+        assert!(matches!(
+            Dem::load_ascii_grid(
+                "ncols 2\nnrows 1\ncellsize 1\nxllcorner 0\nyllcorner 0\n1\n".as_bytes()
+            ),
+            Err(DemError::WrongSize {
+                expected: 2,
+                found: 1
+            })
+        ));
+        // NaN as nodata, starting off the data
+        let dem = Dem::load_ascii_grid(
+            "ncols 2\nnrows 2\ncellsize 1\nxllcorner 0\nyllcorner 0\nnodata_value nan\n\
+             nan 1\n2 inf\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(dem.elevations, vec![None, Some(1.0), Some(2.0), None]);
+
+        assert!(matches!(
+            Dem::load_ascii_grid(
+                "ncols 0\nnrows 0\ncellsize 1\nxllcorner 0\nyllcorner 0\n".as_bytes()
+            ),
+            Err(DemError::Empty)
+        ));
+        assert!(matches!(
+            Dem::load_ascii_grid("ncols 1\nnrows 1\n1\n".as_bytes()),
+            Err(DemError::MissingHeader(_))
+        ));
+        match Dem::load_ascii_grid(
+            "ncols 2\nnrows 1\ncellsize 1\nxllcorner 0\nyllcorner 0\n1 x\n".as_bytes(),
+        ) {
+            Err(DemError::Parse { line, value }) => assert_eq!((line, value.as_str()), (6, "x")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -6,7 +6,11 @@ use either::Either;
 use euclid::{Point2D, TypedPoint2D};
 use lyon::tessellation::*;
 
+use crate::clip;
 use crate::graphics;
+use crate::grid::GridCell;
+use crate::projection::{Crs, CrsMismatch};
+use crate::simplify;
 
 use euclid::*;
 
@@ -60,8 +64,11 @@ pub fn scale_chroma(mut scalar: f32, n_chunks: f32) -> [f32; 4] {
 /// This unit refers to "data space," i.e. the most raw version of the coordinates
 pub enum DataUnit {}
 
-pub type Point2DData = TypedPoint2D<f32, DataUnit>;
-pub type Box2DData = TypedBox2D<f32, DataUnit>;
+/// Data space is double precision. Latitude and longitude in f32 are only good to about a meter, so
+/// we stay in f64 from decoding onwards and only drop to f32 once everything is relative to the
+/// viewport.
+pub type Point2DData = TypedPoint2D<f64, DataUnit>;
+pub type Box2DData = TypedBox2D<f64, DataUnit>;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -132,7 +139,7 @@ pub struct FnGrid<F, G> {
     pub viewport: Option<Box2DData>,
 
     /// The side length of a cell, in data space
-    pub cell_size: f32,
+    pub cell_size: f64,
 
     /// Given the center of a grid cell, return the color to paint this grid cell
     pub color_fn: F,
@@ -151,9 +158,9 @@ impl<F: Fn(Point2DData) -> [f32; 4], G: Fn(Point2DData) -> String> Render for Fn
 
         let mut cells = vec![];
         for x in min_x..=max_x {
-            let cell_x_min = x as f32 * self.cell_size;
+            let cell_x_min = x as f64 * self.cell_size;
             for y in min_y..=max_y {
-                let cell_y_min = y as f32 * self.cell_size;
+                let cell_y_min = y as f64 * self.cell_size;
                 cells.push(Z {
                     t: StyledGeom {
                         geom: Geom::Polygon(vec![
@@ -186,9 +193,9 @@ impl<F: Fn(Point2DData) -> [f32; 4], G: Fn(Point2DData) -> String> Render for Fn
 
         let mut cells = vec![];
         for x in min_x..=max_x {
-            let cell_x_min = x as f32 * self.cell_size;
+            let cell_x_min = x as f64 * self.cell_size;
             for y in min_y..=max_y {
-                let cell_y_min = y as f32 * self.cell_size;
+                let cell_y_min = y as f64 * self.cell_size;
                 let location = Point2DData::new(
                     cell_x_min + self.cell_size * 0.5,
                     cell_y_min + self.cell_size * 0.5,
@@ -216,9 +223,9 @@ impl<F: Fn(Point2DData) -> [f32; 4], G: Fn(Point2DData) -> String> Render2 for F
 
         let mut cells = vec![];
         for x in min_x..=max_x {
-            let cell_x_min = x as f32 * self.cell_size;
+            let cell_x_min = x as f64 * self.cell_size;
             for y in min_y..=max_y {
-                let cell_y_min = y as f32 * self.cell_size;
+                let cell_y_min = y as f64 * self.cell_size;
                 cells.push(StyledGeom {
                     geom: Geom::Polygon(vec![
                         Point2DData::new(cell_x_min, cell_y_min),
@@ -245,9 +252,9 @@ impl<F: Fn(Point2DData) -> [f32; 4], G: Fn(Point2DData) -> String> Render2 for F
 
         let mut cells = vec![];
         for x in min_x..=max_x {
-            let cell_x_min = x as f32 * self.cell_size;
+            let cell_x_min = x as f64 * self.cell_size;
             for y in min_y..=max_y {
-                let cell_y_min = y as f32 * self.cell_size;
+                let cell_y_min = y as f64 * self.cell_size;
                 let location = Point2DData::new(
                     cell_x_min + self.cell_size * 0.5,
                     cell_y_min + self.cell_size * 0.5,
@@ -262,6 +269,137 @@ impl<F: Fn(Point2DData) -> [f32; 4], G: Fn(Point2DData) -> String> Render2 for F
     }
 }
 
+/// Areas, e.g. wards, each filled with a color for its value
+pub struct Choropleth<F> {
+    pub areas: Vec<ChoroplethArea>,
+
+    /// Given an area's value, return the color to fill it with
+    pub color_fn: F,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChoroplethArea {
+    /// Outer rings and holes together, which don't repeat their first point. They're filled with
+    /// the even-odd rule, so the holes are cut out.
+    pub rings: Vec<Vec<Point2DData>>,
+    /// None where there's nothing to show, e.g. an area nobody can get to
+    pub value: Option<f64>,
+    pub label: Text,
+}
+
+impl<F: Fn(Option<f64>) -> [f32; 4]> Render for Choropleth<F> {
+    fn styled_geoms(&self, z_0: f32) -> Vec<Z<StyledGeom>> {
+        self.areas
+            .iter()
+            .map(|area| Z {
+                t: StyledGeom {
+                    geom: Geom::MultiPolygon(area.rings.clone()),
+                    color: (self.color_fn)(area.value),
+                },
+                z: z_0,
+            })
+            .collect()
+    }
+
+    fn texts(&self, z_0: f32) -> Vec<Z<Text>> {
+        self.areas
+            .iter()
+            .map(|area| Z {
+                t: area.label.clone(),
+                z: z_0 + 1.0,
+            })
+            .collect()
+    }
+}
+
+impl<F: Fn(Option<f64>) -> [f32; 4]> Render2 for Choropleth<F> {
+    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
+        self.areas
+            .iter()
+            .flat_map(|area| {
+                let color = (self.color_fn)(area.value);
+                Geom::MultiPolygon(area.rings.clone())
+                    .clip(&viewport)
+                    .into_iter()
+                    .map(move |geom| StyledGeom { geom, color })
+            })
+            .collect()
+    }
+
+    fn texts(&self, viewport: Box2DData) -> Vec<Text> {
+        self.areas
+            .iter()
+            .flat_map(|area| Render2::texts(&area.label, viewport))
+            .collect()
+    }
+}
+
+/// Cells that were each sampled once, e.g. from `grid::hex_cells` or `grid::quadtree_cells`, so
+/// unlike `FnGrid` the color and label don't each compute the value again
+pub struct SampledGrid<V, F, G> {
+    pub cells: Vec<GridCell<V>>,
+
+    /// Given a cell's value, return the color to paint this cell
+    pub color_fn: F,
+
+    /// Given a cell's value, return the label of this cell
+    pub label_fn: G,
+}
+
+impl<V, F: Fn(&V) -> [f32; 4], G: Fn(&V) -> String> Render for SampledGrid<V, F, G> {
+    fn styled_geoms(&self, z_0: f32) -> Vec<Z<StyledGeom>> {
+        self.cells
+            .iter()
+            .map(|cell| Z {
+                t: StyledGeom {
+                    geom: Geom::Polygon(cell.polygon.clone()),
+                    color: (self.color_fn)(&cell.value),
+                },
+                z: z_0,
+            })
+            .collect()
+    }
+
+    fn texts(&self, z_0: f32) -> Vec<Z<Text>> {
+        self.cells
+            .iter()
+            .map(|cell| Z {
+                t: Text {
+                    text: (self.label_fn)(&cell.value),
+                    location: cell.center,
+                },
+                z: z_0 + 1.0,
+            })
+            .collect()
+    }
+}
+
+impl<V, F: Fn(&V) -> [f32; 4], G: Fn(&V) -> String> Render2 for SampledGrid<V, F, G> {
+    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
+        self.cells
+            .iter()
+            .flat_map(|cell| {
+                let color = (self.color_fn)(&cell.value);
+                Geom::Polygon(cell.polygon.clone())
+                    .clip(&viewport)
+                    .into_iter()
+                    .map(move |geom| StyledGeom { geom, color })
+            })
+            .collect()
+    }
+
+    fn texts(&self, viewport: Box2DData) -> Vec<Text> {
+        self.cells
+            .iter()
+            .filter(|cell| viewport.contains(&cell.center))
+            .map(|cell| Text {
+                text: (self.label_fn)(&cell.value),
+                location: cell.center,
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct StyledGeom {
     pub geom: Geom,
@@ -283,8 +421,8 @@ impl Render for StyledGeom {
 
 pub fn slice_box2d(box2d: Box2DData, n_slices: usize) -> impl Iterator<Item = Box2DData> {
     (0..n_slices).map(move |i| {
-        let ratio_min = i as f32 / n_slices as f32;
-        let ratio_max = ((i as f32) + 1.0) / n_slices as f32;
+        let ratio_min = i as f64 / n_slices as f64;
+        let ratio_max = ((i as f64) + 1.0) / n_slices as f64;
         Box2DData::new(
             Point2DData::new(
                 box2d.min.x,
@@ -298,20 +436,42 @@ pub fn slice_box2d(box2d: Box2DData, n_slices: usize) -> impl Iterator<Item = Bo
     })
 }
 
-//impl Render2 for StyledGeom {
-//    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
-//        if self.geom.is_in(viewport) {
-//            vec![self.clone()]
-//        } else {
-//            vec![]
-//        }
-//    }
-//
-//    fn texts(&self, viewport: Box2DData) -> Vec<Text> {
-//        vec![]
-//    }
-//}
-//
+/// The centers of the grid cells that `FnGrid` would draw over this viewport, column by column
+pub fn grid_cell_centers(viewport: Box2DData, cell_size: f64) -> Vec<Point2DData> {
+    let min_x = (viewport.min.x / cell_size).floor() as isize;
+    let min_y = (viewport.min.y / cell_size).floor() as isize;
+    let max_x = (viewport.max.x / cell_size).floor() as isize;
+    let max_y = (viewport.max.y / cell_size).floor() as isize;
+
+    let mut centers = vec![];
+    for x in min_x..=max_x {
+        for y in min_y..=max_y {
+            centers.push(Point2DData::new(
+                (x as f64 + 0.5) * cell_size,
+                (y as f64 + 0.5) * cell_size,
+            ));
+        }
+    }
+    centers
+}
+
+impl Render2 for StyledGeom {
+    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
+        self.geom
+            .clip(&viewport)
+            .into_iter()
+            .map(|geom| StyledGeom {
+                geom,
+                color: self.color,
+            })
+            .collect()
+    }
+
+    fn texts(&self, _viewport: Box2DData) -> Vec<Text> {
+        vec![]
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Text {
     pub text: String,
@@ -332,7 +492,7 @@ impl Render for Text {
 }
 
 impl Render2 for Text {
-    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
+    fn styled_geoms(&self, _viewport: Box2DData) -> Vec<StyledGeom> {
         vec![]
     }
 
@@ -391,14 +551,78 @@ impl<R: Render> Render for Layer<R> {
     }
 }
 
+impl<R: Render2> Render2 for Layer<R> {
+    fn styled_geoms(&self, viewport: Box2DData) -> Vec<StyledGeom> {
+        self.0
+            .iter()
+            .flat_map(|r| r.styled_geoms(viewport))
+            .collect()
+    }
+
+    fn texts(&self, viewport: Box2DData) -> Vec<Text> {
+        self.0.iter().flat_map(|r| r.texts(viewport)).collect()
+    }
+}
+
+impl<R> Layer<R> {
+    /// Build a layer out of things that must all be in the given CRS
+    pub fn from_projected(
+        crs: Crs,
+        projecteds: Vec<Projected<R>>,
+    ) -> Result<Projected<Self>, CrsMismatch> {
+        let mut rs = Vec::with_capacity(projecteds.len());
+        for projected in projecteds {
+            crs.check(&projected.crs)?;
+            rs.push(projected.t);
+        }
+        Ok(Projected { t: Layer(rs), crs })
+    }
+}
+
+/// Something in data space, tagged with the CRS that its coordinates are in
+#[derive(Clone, Debug)]
+pub struct Projected<T> {
+    pub t: T,
+    pub crs: Crs,
+}
+
+impl<R: Render> Render for Projected<R> {
+    fn styled_geoms(&self, z_0: f32) -> Vec<Z<StyledGeom>> {
+        self.t.styled_geoms(z_0)
+    }
+
+    fn texts(&self, z_0: f32) -> Vec<Z<Text>> {
+        self.t.texts(z_0)
+    }
+}
+
+impl Projected<StyledGeom> {
+    /// Convert the geometry into another CRS, keeping the style
+    pub fn to_crs(&self, crs: Crs) -> Self {
+        Projected {
+            t: StyledGeom {
+                geom: self.t.geom.map_points(|point| {
+                    let x_y = self.crs.convert(&crs, [point.x, point.y]);
+                    Point2DData::new(x_y[0], x_y[1])
+                }),
+                color: self.t.color,
+            },
+            crs,
+        }
+    }
+}
+
 /// This attempts to represent the underlying data
 #[derive(Clone, Debug)]
 pub enum Geom {
     Point(Point2DData),
     Lines {
         points: Vec<Point2DData>,
-        width: f32,
+        width: f64,
     },
+    /// Rings like `Polygon`'s, filled with the even-odd rule so that holes can be listed alongside
+    /// the outer rings
+    MultiPolygon(Vec<Vec<Point2DData>>),
     Polygon(Vec<Point2DData>), // don't repeat the first point
                                //    Text(String), // This seems def. not a geom in the tidy data sense
 }
@@ -412,22 +636,82 @@ impl Geom {
             Point2DData::new(box2d.min.x, box2d.max.y),
         ])
     }
+
+    /// Cut this down to the part that's inside the box. A line can be cut into several lines, and
+    /// nothing is left of geometry that's entirely outside. Lines are clipped to a slightly larger
+    /// box so that their ends aren't visibly squared off at the edge of the viewport.
+    pub fn clip(&self, box2d: &Box2DData) -> Vec<Geom> {
+        match self {
+            Geom::Point(point) => {
+                if box2d.contains(point) {
+                    vec![self.clone()]
+                } else {
+                    vec![]
+                }
+            }
+            Geom::Lines { points, width } => {
+                clip::clip_polyline(points, &box2d.inflate(*width, *width))
+                    .into_iter()
+                    .map(|points| Geom::Lines {
+                        points,
+                        width: *width,
+                    })
+                    .collect()
+            }
+            Geom::Polygon(points) => clip::clip_polygon(points, box2d)
+                .into_iter()
+                .map(Geom::Polygon)
+                .collect(),
+            Geom::MultiPolygon(rings) => {
+                // A ring and the box overlap where the clipped ring is, so the parity of any point
+                // in the box is unchanged
+                let rings: Vec<Vec<Point2DData>> = rings
+                    .iter()
+                    .filter_map(|ring| clip::clip_polygon(ring, box2d))
+                    .collect();
+                if rings.is_empty() {
+                    vec![]
+                } else {
+                    vec![Geom::MultiPolygon(rings)]
+                }
+            }
+        }
+    }
+
+    /// Apply a function to every point, e.g. to change coordinate systems
+    pub fn map_points<F: Fn(Point2DData) -> Point2DData>(&self, f: F) -> Self {
+        match self {
+            Geom::Point(point) => Geom::Point(f(*point)),
+            Geom::Lines { points, width } => Geom::Lines {
+                points: points.iter().cloned().map(f).collect(),
+                width: *width,
+            },
+            Geom::Polygon(points) => Geom::Polygon(points.iter().cloned().map(f).collect()),
+            Geom::MultiPolygon(rings) => Geom::MultiPolygon(
+                rings
+                    .iter()
+                    .map(|ring| ring.iter().cloned().map(&f).collect())
+                    .collect(),
+            ),
+        }
+    }
 }
 
 pub enum PointStyle {
     Circle { radius: f32 },
 }
 
-/// Transform this point from data space into drawing space coordinates
+/// Transform this point from data space into drawing space coordinates. This is where we drop to
+/// single precision, after the point is already relative to the viewport.
 fn transform_viewport(point: &Point2DData, viewport: &Box2DData) -> Point2D<f32> {
     Point2D::new(
-        2.0 * (point.x - viewport.min.x) / (viewport.max.x - viewport.min.x) - 1.0,
-        2.0 * (point.y - viewport.min.y) / (viewport.max.y - viewport.min.y) - 1.0,
+        (2.0 * (point.x - viewport.min.x) / (viewport.max.x - viewport.min.x) - 1.0) as f32,
+        (2.0 * (point.y - viewport.min.y) / (viewport.max.y - viewport.min.y) - 1.0) as f32,
     )
 }
 
-fn transform_viewport_1d(len: f32, viewport: &Box2DData) -> f32 {
-    2.0 * len / (viewport.max.y - viewport.min.y)
+fn transform_viewport_1d(len: f64, viewport: &Box2DData) -> f32 {
+    (2.0 * len / (viewport.max.y - viewport.min.y)) as f32
 }
 
 fn geom_to_path(geom: Geom, viewport: Box2DData, screen: Vector2D<usize>) -> MyPath {
@@ -468,9 +752,49 @@ fn geom_to_path(geom: Geom, viewport: Box2DData, screen: Vector2D<usize>) -> MyP
             builder.close();
             MyPath::Filled(builder.build())
         }
+        Geom::MultiPolygon(rings) => {
+            for ring in rings {
+                debug_assert!(ring.len() >= 3);
+                builder.move_to(transform_viewport(&ring[0], &viewport));
+                for point in &ring[1..] {
+                    builder.line_to(transform_viewport(&point, &viewport));
+                }
+                builder.close();
+            }
+            MyPath::Filled(builder.build())
+        }
     }
 }
 
+/// Drop vertices and whole geoms that would be smaller than a pixel
+fn simplify_styled_geoms(styled_geoms: Vec<Z<StyledGeom>>, tolerance: f64) -> Vec<Z<StyledGeom>> {
+    let simplifier = simplify::Simplifier::new(
+        styled_geoms
+            .iter()
+            .map(|z_styled_geom| &z_styled_geom.t.geom),
+        tolerance,
+    );
+    let n_before = styled_geoms.len();
+    let simplified: Vec<Z<StyledGeom>> = styled_geoms
+        .into_iter()
+        .filter_map(|z_styled_geom| {
+            simplifier.simplify(&z_styled_geom.t.geom).map(|geom| Z {
+                t: StyledGeom {
+                    geom,
+                    color: z_styled_geom.t.color,
+                },
+                z: z_styled_geom.z,
+            })
+        })
+        .collect();
+    info!(
+        "{} of {} geoms are big enough to see",
+        simplified.len(),
+        n_before
+    );
+    simplified
+}
+
 fn create_vertices(
     styled_geoms: Vec<Z<StyledGeom>>,
     screen: Vector2D<usize>,
@@ -483,6 +807,7 @@ fn create_vertices(
     let mut stroke_tessellator = StrokeTessellator::new();
 
     let tolerance = 0.1;
+    // The default fill rule is even-odd, which cuts out the holes of a `Geom::MultiPolygon`
     let fill_options = FillOptions::DEFAULT
         .with_normals(false)
         .with_tolerance(tolerance);
@@ -564,8 +889,13 @@ pub fn capture<R: Render>(render: R, viewport: Box2DData, path: std::path::PathB
     // Farthest from the (orthographic?) camera
     let z_0 = 1.0;
 
-    let (vertex_data, index_data) = create_vertices(
+    let styled_geoms = simplify_styled_geoms(
         render.styled_geoms(z_0),
+        simplify::meters_per_pixel(&viewport, size),
+    );
+
+    let (vertex_data, index_data) = create_vertices(
+        styled_geoms,
         Vector2D::new(size as usize, size as usize),
         viewport,
     );
@@ -1064,7 +1394,7 @@ mod tests {
                 (0..n)
                     .map(|i| {
                         let ratio = (i as f32) / (n as f32);
-                        let angle = ratio * 2.0 * std::f32::consts::PI;
+                        let angle = f64::from(ratio) * 2.0 * std::f64::consts::PI;
                         StyledGeom {
                             geom: Geom::Lines {
                                 points: vec![
@@ -1157,6 +1487,95 @@ mod tests {
         );
     }
 
+    /// A centimeter should still be visible when zoomed in on a point far from the centroid
+    #[test]
+    fn test_transform_viewport_far_from_centroid() {
+        let viewport = Box2DData::new(
+            Point2DData::new(20_000.0, 20_000.0),
+            Point2DData::new(20_001.0, 20_001.0),
+        );
+        let a = transform_viewport(&Point2DData::new(20_000.50, 20_000.5), &viewport);
+        let b = transform_viewport(&Point2DData::new(20_000.51, 20_000.5), &viewport);
+        assert!((b.x - a.x - 0.02).abs() < 1e-4, "{:?} {:?}", a, b);
+    }
+
+    #[test]
+    fn test_layer_from_projected() {
+        let city_hall = Crs::centered_on((42.386755, -71.098472));
+        let park_street = Crs::centered_on((42.356395, -71.062424));
+        let styled_geom = StyledGeom {
+            geom: Geom::Point(Point2DData::new(0.0, 0.0)),
+            color: [0.0, 0.0, 0.0, 1.0],
+        };
+        let in_city_hall = Projected {
+            t: styled_geom.clone(),
+            crs: city_hall,
+        };
+        let in_park_street = Projected {
+            t: styled_geom,
+            crs: park_street,
+        };
+
+        assert!(Layer::from_projected(
+            city_hall,
+            vec![in_city_hall.clone(), in_park_street.clone()]
+        )
+        .is_err());
+
+        let layer = Layer::from_projected(
+            city_hall,
+            vec![in_city_hall, in_park_street.to_crs(city_hall)],
+        )
+        .unwrap();
+        assert_eq!(layer.t.0.len(), 2);
+        match layer.t.0[1].geom {
+            Geom::Point(point) => assert!(point.to_vector().length() > 4_000.0),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_render2_clips() {
+        let viewport = Box2DData::new(Point2DData::new(0.0, 0.0), Point2DData::new(1.0, 1.0));
+        let layer = Layer(vec![
+            StyledGeom {
+                geom: Geom::Lines {
+                    points: vec![Point2DData::new(-4.0, 0.5), Point2DData::new(4.0, 0.5)],
+                    width: 1.0,
+                },
+                color: [0.0, 0.0, 0.0, 1.0],
+            },
+            StyledGeom {
+                geom: Geom::from_box2d(&Box2DData::new(
+                    Point2DData::new(2.0, 2.0),
+                    Point2DData::new(3.0, 3.0),
+                )),
+                color: [0.0, 0.0, 0.0, 1.0],
+            },
+        ]);
+
+        let clipped = Render2::styled_geoms(&layer, viewport);
+        assert_eq!(clipped.len(), 1);
+        match &clipped[0].geom {
+            Geom::Lines { points, .. } => assert_eq!(
+                points,
+                &vec![Point2DData::new(-1.0, 0.5), Point2DData::new(2.0, 0.5)]
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_grid_cell_centers() {
+        let centers = grid_cell_centers(
+            Box2DData::new(Point2DData::new(-1.0, 0.0), Point2DData::new(0.5, 1.5)),
+            1.0,
+        );
+        assert_eq!(centers.len(), 4);
+        assert_eq!(centers[0], Point2DData::new(-0.5, 0.5));
+        assert_eq!(centers[3], Point2DData::new(0.5, 1.5));
+    }
+
     #[test]
     fn test_slice_box2d() {
         let expected = vec![
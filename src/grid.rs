@@ -0,0 +1,233 @@
+//! Grid cells besides `FnGrid`'s squares: hexagons, which look less blocky and are the same
+//! distance from all six neighbors, and a quadtree, which only spends small cells where values
+//! change quickly. Each cell is sampled once, so its color and label come from the same value.
+use crate::graphics::{Box2DData, Point2DData};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridCell<V> {
+    /// Doesn't repeat its first point, like `Geom::Polygon`
+    pub polygon: Vec<Point2DData>,
+    /// Where the value was sampled
+    pub center: Point2DData,
+    pub value: V,
+}
+
+/// Pointy-topped hexagons covering the viewport, anchored at the origin like `FnGrid`'s squares.
+/// The cell size is the distance between neighboring centers, and has to be positive.
+pub fn hex_cells<V, S>(viewport: Box2DData, cell_size: f64, sample: S) -> Vec<GridCell<V>>
+where
+    V: Send,
+    S: Fn(Point2DData) -> V + Sync,
+{
+    assert!(cell_size > 0.0, "bad cell size {}", cell_size);
+    // From the center to a corner
+    let radius = cell_size / 3f64.sqrt();
+    let row_height = radius * 1.5;
+    let min_row = (viewport.min.y / row_height).floor() as isize - 1;
+    let max_row = (viewport.max.y / row_height).ceil() as isize + 1;
+    let min_col = (viewport.min.x / cell_size).floor() as isize - 1;
+    let max_col = (viewport.max.x / cell_size).ceil() as isize + 1;
+    // Hexagons whose bounding boxes overlap the viewport
+    let reach = viewport.inflate(cell_size / 2.0, radius);
+
+    let centers: Vec<Point2DData> = (min_row..=max_row)
+        .flat_map(|row| {
+            // Every other row is shifted over by half a cell
+            let offset = if row.rem_euclid(2) == 1 {
+                cell_size / 2.0
+            } else {
+                0.0
+            };
+            (min_col..=max_col).map(move |col| {
+                Point2DData::new(col as f64 * cell_size + offset, row as f64 * row_height)
+            })
+        })
+        .filter(|center| reach.contains(center))
+        .collect();
+    centers
+        .into_par_iter()
+        .map(|center| GridCell {
+            polygon: (0..6)
+                .map(|i| {
+                    let angle = PI / 6.0 + i as f64 * PI / 3.0;
+                    Point2DData::new(
+                        center.x + radius * angle.cos(),
+                        center.y + radius * angle.sin(),
+                    )
+                })
+                .collect(),
+            center,
+            value: sample(center),
+        })
+        .collect()
+}
+
+fn square(x: isize, y: isize, cell_size: f64) -> Vec<Point2DData> {
+    let (min_x, min_y) = (x as f64 * cell_size, y as f64 * cell_size);
+    vec![
+        Point2DData::new(min_x, min_y),
+        Point2DData::new(min_x + cell_size, min_y),
+        Point2DData::new(min_x + cell_size, min_y + cell_size),
+        Point2DData::new(min_x, min_y + cell_size),
+    ]
+}
+
+/// Squares that start at `max_cell_size`, anchored at the origin like `FnGrid`'s, and split in
+/// four wherever a cell's value differs from one of its four neighbors of the same size, down to
+/// `min_cell_size`, which has to be positive. Cells aren't compared with bigger neighbors, since
+/// those already matched all of theirs.
+pub fn quadtree_cells<V, S, D>(
+    viewport: Box2DData,
+    max_cell_size: f64,
+    min_cell_size: f64,
+    sample: S,
+    differ: D,
+) -> Vec<GridCell<V>>
+where
+    V: Send,
+    S: Fn(Point2DData) -> V + Sync,
+    D: Fn(&V, &V) -> bool,
+{
+    assert!(
+        0.0 < min_cell_size && min_cell_size <= max_cell_size,
+        "bad cell sizes {} to {}",
+        min_cell_size,
+        max_cell_size
+    );
+    let min_x = (viewport.min.x / max_cell_size).floor() as isize;
+    let min_y = (viewport.min.y / max_cell_size).floor() as isize;
+    let max_x = (viewport.max.x / max_cell_size).floor() as isize;
+    let max_y = (viewport.max.y / max_cell_size).floor() as isize;
+    let mut indexes: Vec<(isize, isize)> = (min_x..=max_x)
+        .flat_map(|x| (min_y..=max_y).map(move |y| (x, y)))
+        .collect();
+    let mut cell_size = max_cell_size;
+
+    let mut cells = vec![];
+    while !indexes.is_empty() {
+        let values: BTreeMap<(isize, isize), V> = indexes
+            .into_par_iter()
+            .map(|(x, y)| {
+                let center =
+                    Point2DData::new((x as f64 + 0.5) * cell_size, (y as f64 + 0.5) * cell_size);
+                ((x, y), sample(center))
+            })
+            .collect();
+        let can_split = cell_size / 2.0 >= min_cell_size;
+        let split: Vec<(isize, isize)> = values
+            .iter()
+            .filter(|&(&(x, y), value)| {
+                can_split
+                    && [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                        .iter()
+                        .filter_map(|neighbor| values.get(neighbor))
+                        .any(|neighbor| differ(value, neighbor))
+            })
+            .map(|(&index, _)| index)
+            .collect();
+
+        for ((x, y), value) in values {
+            if split.binary_search(&(x, y)).is_err() {
+                cells.push(GridCell {
+                    polygon: square(x, y, cell_size),
+                    center: Point2DData::new(
+                        (x as f64 + 0.5) * cell_size,
+                        (y as f64 + 0.5) * cell_size,
+                    ),
+                    value,
+                });
+            }
+        }
+        indexes = split
+            .iter()
+            .flat_map(|&(x, y)| {
+                vec![
+                    (2 * x, 2 * y),
+                    (2 * x + 1, 2 * y),
+                    (2 * x, 2 * y + 1),
+                    (2 * x + 1, 2 * y + 1),
+                ]
+            })
+            .collect();
+        cell_size /= 2.0;
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::polygon_area;
+    use crate::spatial::polygon_contains;
+
+    #[test]
+    fn test_hex_cells() {
+        let viewport = Box2DData::new(
+            Point2DData::new(-100.0, -100.0),
+            Point2DData::new(100.0, 100.0),
+        );
+        let cells = hex_cells(viewport, 20.0, |point| point);
+        assert!(cells.iter().all(|cell| cell.value == cell.center));
+
+        // Every point is in the hexagon with the nearest center
+        for i in 0..20 {
+            for j in 0..20 {
+                let point = Point2DData::new(-99.0 + i as f64 * 10.3, -99.0 + j as f64 * 10.3);
+                let nearest = cells
+                    .iter()
+                    .min_by(|a, b| {
+                        let a = (a.center - point).length();
+                        let b = (b.center - point).length();
+                        a.partial_cmp(&b).unwrap()
+                    })
+                    .unwrap();
+                assert!(polygon_contains(&nearest.polygon, point));
+            }
+        }
+
+        let neighbor = cells
+            .iter()
+            .map(|cell| (cell.center - cells[0].center).length())
+            .filter(|&meters| meters > 0.0)
+            .fold(f64::INFINITY, f64::min);
+        assert!((neighbor - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_quadtree_cells() {
+        // A step at x = 0
+        let viewport = Box2DData::new(
+            Point2DData::new(-100.0, -100.0),
+            Point2DData::new(100.0, 100.0),
+        );
+        let cells = quadtree_cells(
+            viewport,
+            50.0,
+            12.5,
+            |point| if point.x < 0.0 { 0.0 } else { 10.0 },
+            |a: &f64, b: &f64| (a - b).abs() > 1.0,
+        );
+
+        // Still covering the same squares as the biggest cells, without overlapping
+        let area: f64 = cells.iter().map(|cell| polygon_area(&cell.polygon)).sum();
+        assert_eq!(area, 250.0 * 250.0);
+        for cell in &cells {
+            let size = polygon_area(&cell.polygon).sqrt();
+            if cell.center.x.abs() < 12.5 {
+                assert_eq!(size, 12.5);
+            } else if cell.center.x.abs() > 50.0 {
+                assert_eq!(size, 50.0);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bad cell sizes")]
+    fn test_quadtree_cells_without_a_smallest_size() {
+        let viewport = Box2DData::new(Point2DData::new(0.0, 0.0), Point2DData::new(1.0, 1.0));
+        quadtree_cells(viewport, 50.0, 0.0, |point| point.x, |a, b| a != b);
+    }
+}
@@ -0,0 +1,960 @@
+//! Reading static GTFS feeds, e.g. MBTA's published schedule, from a zip file or a directory.
+//!
+//! Spec: https://developers.google.com/transit/gtfs/reference
+use crate::graphics::Point2DData;
+use crate::lat_lon_to_x_y;
+use crate::table::{Columns, TableError};
+use geo_types::Point;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum GtfsError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Csv(csv::Error),
+    MissingFile(&'static str),
+    MissingColumn {
+        file: &'static str,
+        column: &'static str,
+    },
+    Parse {
+        file: &'static str,
+        line: u64,
+        column: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for GtfsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GtfsError::Io(error) => write!(f, "{}", error),
+            GtfsError::Zip(error) => write!(f, "{}", error),
+            GtfsError::Csv(error) => write!(f, "{}", error),
+            GtfsError::MissingFile(file) => write!(f, "{} is missing from the feed", file),
+            GtfsError::MissingColumn { file, column } => {
+                write!(f, "{} has no {} column", file, column)
+            }
+            GtfsError::Parse {
+                file,
+                line,
+                column,
+                value,
+            } => write!(f, "{} line {}: bad {}: {:?}", file, line, column, value),
+        }
+    }
+}
+
+impl std::error::Error for GtfsError {}
+
+impl From<std::io::Error> for GtfsError {
+    fn from(error: std::io::Error) -> Self {
+        GtfsError::Io(error)
+    }
+}
+
+impl From<zip::result::ZipError> for GtfsError {
+    fn from(error: zip::result::ZipError) -> Self {
+        GtfsError::Zip(error)
+    }
+}
+
+impl From<csv::Error> for GtfsError {
+    fn from(error: csv::Error) -> Self {
+        GtfsError::Csv(error)
+    }
+}
+
+/// Seconds since midnight at the start of the service day. This can be more than 24 hours for
+/// trips that run past midnight.
+pub type Seconds = u32;
+
+/// "HH:MM:SS", where HH may be more than 23
+pub fn parse_time(time: &str) -> Option<Seconds> {
+    let mut parts = time.trim().split(':').map(|part| part.parse::<u32>().ok());
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// Dates are YYYYMMDD as in the feed, e.g. 20191104, which also sorts correctly
+pub type Date = u32;
+
+/// In the month of the date
+fn days_in_month(date: Date) -> Date {
+    let (year, month) = (date / 10000, date / 100 % 100);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// "YYYYMMDD", for a day that exists
+pub fn parse_date(date: &str) -> Option<Date> {
+    let date = date.trim();
+    if date.len() != 8 || !date.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let date: Date = date.parse().ok()?;
+    let (year, month, day) = (date / 10000, date / 100 % 100, date % 100);
+    if year == 0 || month == 0 || month > 12 || day == 0 || day > days_in_month(date) {
+        return None;
+    }
+    Some(date)
+}
+
+/// Monday is 0, like `Calendar::days`
+pub fn weekday(date: Date) -> usize {
+    // Sakamoto's method, which counts from Sunday
+    let offsets = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let (mut year, month, day) = (date / 10000, date / 100 % 100, date % 100);
+    if month < 3 {
+        year -= 1;
+    }
+    let from_sunday =
+        (year + year / 4 - year / 100 + year / 400 + offsets[month as usize - 1] + day) % 7;
+    (from_sunday as usize + 6) % 7
+}
+
+/// The day after, e.g. 20200301 after 20200229
+pub fn next_day(date: Date) -> Date {
+    let (year, month, day) = (date / 10000, date / 100 % 100, date % 100);
+    if day < days_in_month(date) {
+        date + 1
+    } else if month < 12 {
+        year * 10000 + (month + 1) * 100 + 1
+    } else {
+        (year + 1) * 10000 + 101
+    }
+}
+
+/// A stop, platform, or station. Platforms point to their station with `parent_station`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// 0 or missing is a stop or platform, 1 is a station, 2 is an entrance
+    pub location_type: u8,
+    pub parent_station: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub id: String,
+    pub short_name: String,
+    pub long_name: String,
+    /// 0 is light rail, 1 is subway, 2 is commuter rail, 3 is bus, etc.
+    pub route_type: u16,
+    /// Hex without the #, e.g. "00843D" for the Green Line
+    pub color: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopTime {
+    pub stop_id: String,
+    pub arrival: Seconds,
+    pub departure: Seconds,
+    pub stop_sequence: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trip {
+    pub id: String,
+    pub route_id: String,
+    pub service_id: String,
+    pub direction_id: Option<u8>,
+    /// In order of `stop_sequence`
+    pub stop_times: Vec<StopTime>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calendar {
+    pub service_id: String,
+    /// Monday first
+    pub days: [bool; 7],
+    pub start_date: Date,
+    pub end_date: Date,
+}
+
+impl Calendar {
+    pub fn runs_on(&self, date: Date) -> bool {
+        self.start_date <= date && date <= self.end_date && self.days[weekday(date)]
+    }
+}
+
+/// An exception to the calendar, e.g. a holiday
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalendarDate {
+    pub service_id: String,
+    pub date: Date,
+    /// 1 if the service runs on this date after all, 2 if it doesn't
+    pub exception_type: u8,
+}
+
+/// A trip that repeats every `headway` from `start` until `end`. The trip's own stop times give
+/// the spacing between stops.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frequency {
+    pub trip_id: String,
+    pub start: Seconds,
+    pub end: Seconds,
+    pub headway: Seconds,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub from_stop_id: String,
+    pub to_stop_id: String,
+    /// 0 is recommended, 1 is timed, 2 needs `min_transfer_time`, 3 is impossible
+    pub transfer_type: u8,
+    pub min_transfer_time: Option<Seconds>,
+}
+
+/// A station, with its platforms, as we'd put it on a map
+#[derive(Clone, Debug, PartialEq)]
+pub struct Station {
+    pub id: String,
+    pub name: String,
+    pub location_x_y: Point2DData,
+    /// Where the trips actually stop, which includes the station itself
+    pub stop_ids: Vec<String>,
+    pub route_ids: Vec<String>,
+}
+
+/// The files that a feed is made of, wherever they're stored
+enum Source {
+    Dir(PathBuf),
+    Zip(zip::ZipArchive<File>),
+}
+
+impl Source {
+    /// None if the file isn't in the feed
+    fn read(&mut self, file: &'static str) -> Result<Option<Vec<u8>>, GtfsError> {
+        let mut bytes = vec![];
+        match self {
+            Source::Dir(dir) => {
+                let path = dir.join(file);
+                if !path.exists() {
+                    return Ok(None);
+                }
+                File::open(path)?.read_to_end(&mut bytes)?;
+            }
+            Source::Zip(archive) => match archive.by_name(file) {
+                Ok(mut zip_file) => {
+                    zip_file.read_to_end(&mut bytes)?;
+                }
+                Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+                Err(error) => return Err(error.into()),
+            },
+        }
+        Ok(Some(bytes))
+    }
+}
+
+/// One row of a GTFS file, with columns looked up by name since feeds order them differently
+struct Row<'a> {
+    file: &'static str,
+    columns: &'a Columns,
+    record: &'a csv::StringRecord,
+}
+
+impl<'a> Row<'a> {
+    /// With the file, and the column as GTFS names it
+    fn table_error(&self, column: &'static str, error: TableError) -> GtfsError {
+        match error {
+            TableError::Csv(error) => GtfsError::Csv(error),
+            TableError::MissingColumn(_) => GtfsError::MissingColumn {
+                file: self.file,
+                column,
+            },
+            TableError::Parse { line, value, .. } => GtfsError::Parse {
+                file: self.file,
+                line,
+                column,
+                value,
+            },
+        }
+    }
+
+    fn index(&self, column: &'static str) -> Result<usize, GtfsError> {
+        self.columns
+            .required(column)
+            .map_err(|error| self.table_error(column, error))
+    }
+
+    /// None if the column is missing or empty, which GTFS treats the same
+    fn optional(&self, column: &'static str) -> Option<&'a str> {
+        self.columns
+            .optional(column)
+            .map(|i| self.columns.value(self.record, i))
+            .filter(|value| !value.is_empty())
+    }
+
+    fn get(&self, column: &'static str) -> Result<&'a str, GtfsError> {
+        let i = self.index(column)?;
+        Ok(self.columns.value(self.record, i))
+    }
+
+    fn parse<T: FromStr>(&self, column: &'static str) -> Result<T, GtfsError> {
+        let i = self.index(column)?;
+        self.columns
+            .parse(self.record, i)
+            .map_err(|error| self.table_error(column, error))
+    }
+
+    fn parse_optional<T: FromStr>(&self, column: &'static str) -> Result<Option<T>, GtfsError> {
+        self.columns
+            .parse_optional(self.record, self.columns.optional(column))
+            .map_err(|error| self.table_error(column, error))
+    }
+
+    /// Parsed some other way than `FromStr`
+    fn convert<T, F: Fn(&str) -> Option<T>>(
+        &self,
+        column: &'static str,
+        convert: F,
+    ) -> Result<T, GtfsError> {
+        let i = self.index(column)?;
+        convert(self.columns.value(self.record, i))
+            .ok_or_else(|| self.table_error(column, self.columns.error(self.record, i)))
+    }
+
+    fn time(&self, column: &'static str) -> Result<Seconds, GtfsError> {
+        self.convert(column, parse_time)
+    }
+
+    fn date(&self, column: &'static str) -> Result<Date, GtfsError> {
+        self.convert(column, parse_date)
+    }
+}
+
+/// A row of stop_times.txt. Only timepoints have to have times.
+struct StopTimeRow {
+    trip_id: String,
+    stop_id: String,
+    arrival: Option<Seconds>,
+    departure: Option<Seconds>,
+    stop_sequence: u32,
+    shape_dist_traveled: Option<f64>,
+}
+
+/// Times for the stops between timepoints, spread out by `shape_dist_traveled` if every stop
+/// has it, or evenly by stop otherwise. The rows must be in order of `stop_sequence`. Stops
+/// before the first timepoint or after the last can't be timed, so they're left out.
+fn interpolate_times(rows: &[StopTimeRow]) -> Vec<StopTime> {
+    let timepoints: Vec<usize> = (0..rows.len())
+        .filter(|&i| rows[i].arrival.is_some())
+        .collect();
+    let by_distance = rows.iter().all(|row| row.shape_dist_traveled.is_some());
+    let mut stop_times = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let (arrival, departure) = match (row.arrival, row.departure) {
+            (Some(arrival), Some(departure)) => (arrival, departure),
+            _ => {
+                let after = timepoints.iter().position(|&timepoint| timepoint > i);
+                let (before, after) = match after {
+                    Some(0) | None => continue,
+                    Some(after) => (timepoints[after - 1], timepoints[after]),
+                };
+                let fraction = match (
+                    row.shape_dist_traveled,
+                    rows[before].shape_dist_traveled,
+                    rows[after].shape_dist_traveled,
+                ) {
+                    (Some(here), Some(start), Some(end)) if by_distance && end > start => {
+                        (here - start) / (end - start)
+                    }
+                    _ => (i - before) as f64 / (after - before) as f64,
+                };
+                let start = rows[before].departure.unwrap() as f64;
+                let end = rows[after].arrival.unwrap() as f64;
+                let time = (start + fraction * (end - start)).round() as Seconds;
+                (time, time)
+            }
+        };
+        stop_times.push(StopTime {
+            stop_id: row.stop_id.clone(),
+            arrival,
+            departure,
+            stop_sequence: row.stop_sequence,
+        });
+    }
+    stop_times
+}
+
+/// Parse every row of a file, or return an empty Vec if an optional file isn't there
+fn read_table<T, F: Fn(&Row) -> Result<T, GtfsError>>(
+    source: &mut Source,
+    file: &'static str,
+    required: bool,
+    parse: F,
+) -> Result<Vec<T>, GtfsError> {
+    let bytes = match source.read(file)? {
+        Some(bytes) => bytes,
+        None if required => return Err(GtfsError::MissingFile(file)),
+        None => return Ok(vec![]),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(bytes.as_slice());
+    let columns = Columns::new(reader.headers()?);
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record?;
+        rows.push(parse(&Row {
+            file,
+            columns: &columns,
+            record: &record,
+        })?);
+    }
+    Ok(rows)
+}
+
+/// A whole static feed. Everything is keyed by its ID.
+#[derive(Clone, Debug, Default)]
+pub struct Feed {
+    pub stops: HashMap<String, Stop>,
+    pub routes: HashMap<String, Route>,
+    pub trips: HashMap<String, Trip>,
+    pub calendars: HashMap<String, Calendar>,
+    pub calendar_dates: Vec<CalendarDate>,
+    pub frequencies: Vec<Frequency>,
+    pub transfers: Vec<Transfer>,
+}
+
+impl Feed {
+    /// Read a feed from a zip file like the ones agencies publish, or from a directory of the
+    /// unzipped files
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, GtfsError> {
+        let path = path.as_ref();
+        let mut source = if path.is_dir() {
+            Source::Dir(path.to_path_buf())
+        } else {
+            Source::Zip(zip::ZipArchive::new(File::open(path)?)?)
+        };
+        Feed::read(&mut source)
+    }
+
+    fn read(source: &mut Source) -> Result<Self, GtfsError> {
+        let stops = read_table(source, "stops.txt", true, |row| {
+            Ok(Stop {
+                id: row.get("stop_id")?.to_string(),
+                name: row.get("stop_name")?.to_string(),
+                lat: row.parse("stop_lat")?,
+                lon: row.parse("stop_lon")?,
+                location_type: row.parse_optional("location_type")?.unwrap_or(0),
+                parent_station: row.optional("parent_station").map(String::from),
+            })
+        })?;
+
+        let routes = read_table(source, "routes.txt", true, |row| {
+            Ok(Route {
+                id: row.get("route_id")?.to_string(),
+                short_name: row.optional("route_short_name").unwrap_or("").to_string(),
+                long_name: row.optional("route_long_name").unwrap_or("").to_string(),
+                route_type: row.parse("route_type")?,
+                color: row.optional("route_color").map(String::from),
+            })
+        })?;
+
+        let mut trips: HashMap<String, Trip> = read_table(source, "trips.txt", true, |row| {
+            Ok(Trip {
+                id: row.get("trip_id")?.to_string(),
+                route_id: row.get("route_id")?.to_string(),
+                service_id: row.get("service_id")?.to_string(),
+                direction_id: row.parse_optional("direction_id")?,
+                stop_times: vec![],
+            })
+        })?
+        .into_iter()
+        .map(|trip| (trip.id.clone(), trip))
+        .collect();
+
+        let stop_times = read_table(source, "stop_times.txt", true, |row| {
+            // Only timepoints need times, so either can be blank, but not only one of them
+            let time = |column| match row.optional(column) {
+                Some(_) => row.time(column).map(Some),
+                None => Ok(None),
+            };
+            let arrival = time("arrival_time")?;
+            let departure = time("departure_time")?;
+            Ok(StopTimeRow {
+                trip_id: row.get("trip_id")?.to_string(),
+                stop_id: row.get("stop_id")?.to_string(),
+                arrival: arrival.or(departure),
+                departure: departure.or(arrival),
+                stop_sequence: row.parse("stop_sequence")?,
+                shape_dist_traveled: row.parse_optional("shape_dist_traveled")?,
+            })
+        })?;
+        let mut trip_rows: HashMap<String, Vec<StopTimeRow>> = HashMap::new();
+        for stop_time in stop_times {
+            if trips.contains_key(&stop_time.trip_id) {
+                trip_rows
+                    .entry(stop_time.trip_id.clone())
+                    .or_default()
+                    .push(stop_time);
+            }
+        }
+        for (trip_id, mut rows) in trip_rows {
+            rows.sort_by_key(|row| row.stop_sequence);
+            trips.get_mut(&trip_id).unwrap().stop_times = interpolate_times(&rows);
+        }
+
+        let calendars = read_table(source, "calendar.txt", false, |row| {
+            let mut days = [false; 7];
+            let columns = [
+                "monday",
+                "tuesday",
+                "wednesday",
+                "thursday",
+                "friday",
+                "saturday",
+                "sunday",
+            ];
+            for (day, &column) in days.iter_mut().zip(columns.iter()) {
+                *day = row.parse::<u8>(column)? == 1;
+            }
+            Ok(Calendar {
+                service_id: row.get("service_id")?.to_string(),
+                days,
+                start_date: row.date("start_date")?,
+                end_date: row.date("end_date")?,
+            })
+        })?;
+
+        let calendar_dates = read_table(source, "calendar_dates.txt", false, |row| {
+            Ok(CalendarDate {
+                service_id: row.get("service_id")?.to_string(),
+                date: row.date("date")?,
+                exception_type: row.parse("exception_type")?,
+            })
+        })?;
+
+        let frequencies = read_table(source, "frequencies.txt", false, |row| {
+            Ok(Frequency {
+                trip_id: row.get("trip_id")?.to_string(),
+                start: row.time("start_time")?,
+                end: row.time("end_time")?,
+                headway: row.parse("headway_secs")?,
+            })
+        })?;
+
+        let transfers = read_table(source, "transfers.txt", false, |row| {
+            Ok(Transfer {
+                from_stop_id: row.get("from_stop_id")?.to_string(),
+                to_stop_id: row.get("to_stop_id")?.to_string(),
+                transfer_type: row.parse_optional("transfer_type")?.unwrap_or(0),
+                min_transfer_time: row.parse_optional("min_transfer_time")?,
+            })
+        })?;
+
+        Ok(Feed {
+            stops: stops
+                .into_iter()
+                .map(|stop| (stop.id.clone(), stop))
+                .collect(),
+            routes: routes
+                .into_iter()
+                .map(|route| (route.id.clone(), route))
+                .collect(),
+            trips,
+            calendars: calendars
+                .into_iter()
+                .map(|calendar| (calendar.service_id.clone(), calendar))
+                .collect(),
+            calendar_dates,
+            frequencies,
+            transfers,
+        })
+    }
+
+    /// The station that a stop belongs to, which is the stop itself if it has no parent
+    pub fn station_id<'a>(&'a self, stop_id: &'a str) -> &'a str {
+        match self.stops.get(stop_id) {
+            Some(Stop {
+                parent_station: Some(parent),
+                ..
+            }) => parent,
+            _ => stop_id,
+        }
+    }
+
+    /// Every station that some trip on one of these route types stops at, e.g. `&[0, 1]` for the
+    /// subway and light rail. Platforms are grouped into their parent station.
+    pub fn stations(&self, route_types: &[u16], centroid: &Point<f64>) -> Vec<Station> {
+        let mut stations: HashMap<&str, Station> = HashMap::new();
+        for trip in self.trips.values() {
+            match self.routes.get(&trip.route_id) {
+                Some(route) if route_types.contains(&route.route_type) => {}
+                _ => continue,
+            }
+            for stop_time in &trip.stop_times {
+                let station_id = self.station_id(&stop_time.stop_id);
+                let stop = match self.stops.get(station_id) {
+                    Some(stop) => stop,
+                    None => continue,
+                };
+                let station = stations.entry(station_id).or_insert_with(|| Station {
+                    id: station_id.to_string(),
+                    name: stop.name.clone(),
+                    location_x_y: lat_lon_to_x_y(centroid, (stop.lat, stop.lon)),
+                    stop_ids: vec![station_id.to_string()],
+                    route_ids: vec![],
+                });
+                if !station.stop_ids.contains(&stop_time.stop_id) {
+                    station.stop_ids.push(stop_time.stop_id.clone());
+                }
+                if !station.route_ids.contains(&trip.route_id) {
+                    station.route_ids.push(trip.route_id.clone());
+                }
+            }
+        }
+
+        let mut stations: Vec<Station> = stations.into_values().collect();
+        stations.sort_by(|a, b| a.id.cmp(&b.id));
+        stations
+    }
+
+    /// The first and last dates that the calendar and its exceptions cover
+    pub fn date_range(&self) -> Option<(Date, Date)> {
+        let starts = self.calendars.values().map(|calendar| calendar.start_date);
+        let ends = self.calendars.values().map(|calendar| calendar.end_date);
+        let exceptions = self.calendar_dates.iter().map(|exception| exception.date);
+        Some((
+            starts.chain(exceptions.clone()).min()?,
+            ends.chain(exceptions).max()?,
+        ))
+    }
+
+    /// The first Monday in the feed without any exceptions, so not e.g. a holiday, for a typical
+    /// weekday's schedule
+    pub fn typical_weekday(&self) -> Option<Date> {
+        let (start, end) = self.date_range()?;
+        let mut date = start;
+        while date <= end {
+            let ordinary = !self
+                .calendar_dates
+                .iter()
+                .any(|exception| exception.date == date);
+            if weekday(date) == 0 && ordinary && !self.services_on(date).is_empty() {
+                return Some(date);
+            }
+            date = next_day(date);
+        }
+        None
+    }
+
+    /// The services that run on this date, by the calendar and then its exceptions
+    pub fn services_on(&self, date: Date) -> Vec<&str> {
+        let mut services: Vec<&str> = self
+            .calendars
+            .values()
+            .filter(|calendar| calendar.runs_on(date))
+            .map(|calendar| calendar.service_id.as_str())
+            .collect();
+        for exception in self
+            .calendar_dates
+            .iter()
+            .filter(|exception| exception.date == date)
+        {
+            let service_id = exception.service_id.as_str();
+            match exception.exception_type {
+                1 if !services.contains(&service_id) => services.push(service_id),
+                2 => services.retain(|&service| service != service_id),
+                _ => {}
+            }
+        }
+        services
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// A tiny Green Line: two stations, one with two platforms, and one trip that repeats
+    const FILES: &[(&str, &str)] = &[
+        (
+            "stops.txt",
+            "\u{feff}stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station\n\
+             place-lech,Lechmere,42.370772,-71.076536,1,\n\
+             70500,Lechmere,42.370772,-71.076536,0,place-lech\n\
+             70501,Lechmere,42.370772,-71.076536,0,place-lech\n\
+             place-pktrm,Park Street,42.35639457,-71.0624242,1,\n\
+             70200,Park Street,42.35639457,-71.0624242,0,place-pktrm\n",
+        ),
+        (
+            "routes.txt",
+            "route_id,route_short_name,route_long_name,route_type,route_color\n\
+             Green-E,E,Green Line E,0,00843D\n\
+             1,1,Harvard - Dudley,3,FFC72C\n",
+        ),
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id,direction_id\n\
+             Green-E,weekday,green-1,0\n",
+        ),
+        (
+            // Deliberately out of order
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+             green-1,05:14:00,05:14:00,70200,20\n\
+             green-1,05:00:00,05:01:00,70501,10\n",
+        ),
+        (
+            "calendar.txt",
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+             weekday,1,1,1,1,1,0,0,20191001,20191231\n",
+        ),
+        (
+            // Thanksgiving runs a Sunday schedule instead
+            "calendar_dates.txt",
+            "service_id,date,exception_type\n\
+             weekday,20191128,2\n\
+             sunday,20191128,1\n",
+        ),
+        (
+            "frequencies.txt",
+            "trip_id,start_time,end_time,headway_secs\n\
+             green-1,05:00:00,25:00:00,360\n",
+        ),
+        (
+            "transfers.txt",
+            "from_stop_id,to_stop_id,transfer_type,min_transfer_time\n\
+             70500,70501,2,180\n",
+        ),
+    ];
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("glx-gtfs-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn check_feed(feed: &Feed) {
+        assert_eq!(feed.stops.len(), 5);
+        assert_eq!(feed.stops["place-lech"].location_type, 1);
+        assert_eq!(
+            feed.stops["70501"].parent_station,
+            Some(String::from("place-lech"))
+        );
+        assert_eq!(feed.routes["Green-E"].color, Some(String::from("00843D")));
+
+        let trip = &feed.trips["green-1"];
+        assert_eq!(trip.direction_id, Some(0));
+        let stop_ids: Vec<&str> = trip
+            .stop_times
+            .iter()
+            .map(|stop_time| stop_time.stop_id.as_str())
+            .collect();
+        assert_eq!(stop_ids, vec!["70501", "70200"]);
+        assert_eq!(trip.stop_times[0].departure, 5 * 3600 + 60);
+
+        assert_eq!(feed.frequencies[0].end, 25 * 3600);
+        assert_eq!(feed.frequencies[0].headway, 360);
+        assert_eq!(feed.transfers[0].min_transfer_time, Some(180));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("05:14:00"), Some(5 * 3600 + 14 * 60));
+        assert_eq!(parse_time(" 5:14:09"), Some(5 * 3600 + 14 * 60 + 9));
+        assert_eq!(parse_time("25:00:00"), Some(25 * 3600));
+        assert_eq!(parse_time("05:60:00"), None);
+        assert_eq!(parse_time("05:14"), None);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("20191104"), Some(20191104));
+        assert_eq!(parse_date("20200229"), Some(20200229));
+        assert_eq!(parse_date("20190229"), None);
+        assert_eq!(parse_date("20191304"), None);
+        assert_eq!(parse_date("20190004"), None);
+        assert_eq!(parse_date("00001104"), None);
+        assert_eq!(parse_date("2019114"), None);
+    }
+
+    #[test]
+    fn test_calendar() {
+        // November 4th, 2019 was a Monday
+        assert_eq!(weekday(20191104), 0);
+        assert_eq!(weekday(20191110), 6);
+        assert_eq!(weekday(20200229), 5);
+        assert_eq!(next_day(20200228), 20200229);
+        assert_eq!(next_day(20200229), 20200301);
+        assert_eq!(next_day(20191130), 20191201);
+        assert_eq!(next_day(20191231), 20200101);
+
+        let calendar = Calendar {
+            service_id: String::from("weekday"),
+            days: [true, true, true, true, true, false, false],
+            start_date: 20191001,
+            end_date: 20191231,
+        };
+        assert!(calendar.runs_on(20191104));
+        assert!(!calendar.runs_on(20191109));
+        assert!(!calendar.runs_on(20200106));
+    }
+
+    #[test]
+    fn test_open_dir() {
+        let dir = temp_path("dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in FILES {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let feed = Feed::open(&dir).unwrap();
+        check_feed(&feed);
+        assert_eq!(feed.services_on(20191104), vec!["weekday"]);
+        assert_eq!(feed.services_on(20191128), vec!["sunday"]);
+        assert_eq!(feed.date_range(), Some((20191001, 20191231)));
+        // October 1st, 2019 was a Tuesday
+        assert_eq!(feed.typical_weekday(), Some(20191007));
+
+        // Only the Green Line is light rail, and platforms are grouped into stations
+        let stations = feed.stations(&[0, 1], &Point::new(42.386755, -71.098472));
+        let names: Vec<&str> = stations
+            .iter()
+            .map(|station| station.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Lechmere", "Park Street"]);
+        assert_eq!(stations[0].stop_ids, vec!["place-lech", "70501"]);
+        assert_eq!(stations[0].route_ids, vec!["Green-E"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_zip() {
+        let path = temp_path("feed.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, contents) in FILES {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        check_feed(&Feed::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_interpolated_times() {
+        let dir = temp_path("interpolated");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in FILES {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        // Science Park isn't a timepoint, and is a quarter of the way along by distance. The
+        // stop after the last timepoint can't be timed.
+        let stop_times = "trip_id,arrival_time,departure_time,stop_id,stop_sequence,\
+                          shape_dist_traveled\n\
+                          green-1,05:00:00,05:01:00,70501,10,0.0\n\
+                          green-1,,,70207,15,1.0\n\
+                          green-1,05:09:00,,70200,20,4.0\n\
+                          green-1,,,70196,30,5.0\n";
+        std::fs::write(dir.join("stop_times.txt"), stop_times).unwrap();
+        let trip = &Feed::open(&dir).unwrap().trips["green-1"];
+        let times: Vec<(&str, Seconds, Seconds)> = trip
+            .stop_times
+            .iter()
+            .map(|stop_time| {
+                (
+                    stop_time.stop_id.as_str(),
+                    stop_time.arrival,
+                    stop_time.departure,
+                )
+            })
+            .collect();
+        let (five, minute) = (5 * 3600, 60);
+        assert_eq!(
+            times,
+            vec![
+                ("70501", five, five + minute),
+                ("70207", five + 3 * minute, five + 3 * minute),
+                ("70200", five + 9 * minute, five + 9 * minute),
+            ]
+        );
+
+        // Without distances, by stop instead
+        std::fs::write(
+            dir.join("stop_times.txt"),
+            stop_times.replace(",0.0\n", ",\n"),
+        )
+        .unwrap();
+        let trip = &Feed::open(&dir).unwrap().trips["green-1"];
+        assert_eq!(trip.stop_times[1].arrival, five + 5 * minute);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = temp_path("errors");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in FILES {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        std::fs::write(
+            dir.join("stop_times.txt"),
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+             green-1,05:00:00,05:01:00,70501,10\n\
+             green-1,5 past 5,,70200,20\n",
+        )
+        .unwrap();
+        match Feed::open(&dir) {
+            Err(GtfsError::Parse { line, column, .. }) => {
+                assert_eq!((line, column), (3, "arrival_time"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        std::fs::write(
+            dir.join("stop_times.txt"),
+            FILES
+                .iter()
+                .find(|(name, _)| *name == "stop_times.txt")
+                .unwrap()
+                .1,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("calendar_dates.txt"),
+            "service_id,date,exception_type\nweekday,20191328,2\n",
+        )
+        .unwrap();
+        match Feed::open(&dir) {
+            Err(GtfsError::Parse { column, value, .. }) => {
+                assert_eq!((column, value.as_str()), ("date", "20191328"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        std::fs::remove_file(dir.join("routes.txt")).unwrap();
+        match Feed::open(&dir) {
+            Err(GtfsError::MissingFile(file)) => assert_eq!(file, "routes.txt"),
+            other => panic!("{:?}", other),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
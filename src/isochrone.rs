@@ -0,0 +1,235 @@
+//! Travel times to downtown over the walking graph, computed for the whole map in one pass.
+//!
+//! Instead of trying every station for every grid cell, this runs a single Dijkstra seeded at
+//! every station, with each station starting at its own time to downtown. Every vertex ends up
+//! with the minutes to downtown through whichever station is best for it. That search runs toward
+//! the stations, since that's the way people walk, which matters on hills and one-way streets.
+use crate::graphics::Point2DData;
+use crate::routing::{Heading, Snap, StreetGraph};
+use std::sync::Arc;
+
+/// Minutes to the destination from every vertex of the walking graph, or from the sources to every
+/// vertex going `Outbound`
+pub struct TravelTimeField {
+    graph: Arc<StreetGraph>,
+    heading: Heading,
+    minutes: Vec<f64>,
+    /// Which source each vertex's minutes come from
+    origins: Vec<Option<usize>>,
+}
+
+impl TravelTimeField {
+    /// Each source is a location, e.g. a station, and the minutes from there to the destination.
+    /// Sources join the network at their nearest vertex. `Inbound` is for walking to the sources,
+    /// and `Outbound` for walking away from them.
+    pub fn new(graph: Arc<StreetGraph>, sources: &[(Point2DData, f64)], heading: Heading) -> Self {
+        TravelTimeField::within(graph, sources, heading, f64::INFINITY)
+    }
+
+    /// Like `new`, but only out to `max_minutes`. Anywhere farther is unreachable, and only the
+    /// neighborhood of the sources gets searched.
+    pub fn within(
+        graph: Arc<StreetGraph>,
+        sources: &[(Point2DData, f64)],
+        heading: Heading,
+        max_minutes: f64,
+    ) -> Self {
+        let (seeds, seed_sources): (Vec<(usize, f64)>, Vec<usize>) = sources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &(location, minutes))| {
+                graph.nearest_vertex(location).map(|vertex| {
+                    let walk_to_vertex = (graph.location(vertex) - location).length();
+                    (
+                        (vertex, minutes + walk_to_vertex / graph.meters_per_minute),
+                        i,
+                    )
+                })
+            })
+            .unzip();
+        let (minutes, origins) =
+            graph.multi_source_origins(&seeds, heading, max_minutes, |edge| edge.minutes);
+        let origins = origins
+            .into_iter()
+            .map(|seed| seed.map(|seed| seed_sources[seed]))
+            .collect();
+        TravelTimeField {
+            graph,
+            heading,
+            minutes,
+            origins,
+        }
+    }
+
+    pub fn graph(&self) -> &StreetGraph {
+        &self.graph
+    }
+
+    /// Infinite if the vertex can't reach any source
+    pub fn minutes_at_vertex(&self, vertex: usize) -> f64 {
+        self.minutes[vertex]
+    }
+
+    /// Minutes from an arbitrary point: walk straight to the nearest edge, then along the edge
+    /// to whichever end is better, unless it's one-way. None if that edge isn't connected to any
+    /// source.
+    pub fn sample(&self, point: Point2DData) -> Option<f64> {
+        self.sample_snap(&self.graph.snap(point)?)
+    }
+
+    /// Like `sample`, for a point that's already been snapped to this field's graph. This saves
+    /// looking up the same point again when sampling several fields over one graph.
+    pub fn sample_snap(&self, snap: &Snap) -> Option<f64> {
+        let (minutes, _) = self.via(snap);
+        let minutes = minutes + snap.distance / self.graph.meters_per_minute;
+        if minutes.is_finite() {
+            Some(minutes)
+        } else {
+            None
+        }
+    }
+
+    /// Which of the sources the minutes at this point come from, as an index into the sources
+    /// the field was made with, e.g. which station to walk to. None where `sample` is None.
+    pub fn source(&self, point: Point2DData) -> Option<usize> {
+        let (minutes, vertex) = self.via(&self.graph.snap(point)?);
+        if minutes.is_finite() {
+            self.origins[vertex]
+        } else {
+            None
+        }
+    }
+
+    /// The minutes from the snapped point along its edge, and which end of the edge that's via
+    fn via(&self, snap: &Snap) -> (f64, usize) {
+        let edge = self.graph.edge(snap);
+        // Edges can be slower than the speed, e.g. uphill, so go by the share of the length
+        let fraction = if edge.length > 0.0 {
+            snap.along / edge.length
+        } else {
+            0.0
+        };
+        // One of the ends can only be used if the edge goes both ways
+        let back = self.graph.reverse_edge(snap);
+        let (via_from, via_to) = match self.heading {
+            Heading::Outbound => (
+                self.minutes[snap.from] + fraction * edge.minutes,
+                back.map_or(f64::INFINITY, |back| {
+                    self.minutes[edge.to] + (1.0 - fraction) * back.minutes
+                }),
+            ),
+            Heading::Inbound => (
+                back.map_or(f64::INFINITY, |back| {
+                    self.minutes[snap.from] + fraction * back.minutes
+                }),
+                self.minutes[edge.to] + (1.0 - fraction) * edge.minutes,
+            ),
+        };
+        if via_to < via_from {
+            (via_to, edge.to)
+        } else {
+            (via_from, snap.from)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::{
+        bike_directions, is_bikeable, BIKING_METERS_PER_MINUTE, WALKING_METERS_PER_MINUTE,
+    };
+    use crate::testing::tags;
+    use crate::MyNode;
+
+    fn node(node_id: i64, x: f64, y: f64) -> MyNode {
+        MyNode {
+            x_y_meters: [x, y],
+            node_id,
+        }
+    }
+
+    /// One long street with a fast station at one end and a slow station at the other
+    #[test]
+    fn test_best_station_wins() {
+        let tags = tags(&[("highway", "residential")]);
+        let graph = Arc::new(StreetGraph::new(vec![(
+            &tags,
+            vec![
+                node(1, 0.0, 0.0),
+                node(2, 1000.0, 0.0),
+                node(3, 2000.0, 0.0),
+            ],
+        )]));
+
+        let fast = (Point2DData::new(0.0, 0.0), 10.0);
+        let slow = (Point2DData::new(2000.0, 0.0), 15.0);
+        let field = TravelTimeField::new(graph.clone(), &[fast, slow], Heading::Inbound);
+
+        let minutes_per_km = 1000.0 / WALKING_METERS_PER_MINUTE;
+        let near_fast = field.sample(Point2DData::new(500.0, 10.0)).unwrap();
+        assert!((near_fast - (10.0 + (500.0 + 10.0) / WALKING_METERS_PER_MINUTE)).abs() < 1e-9);
+
+        // Even right next to the slow station, it's only worth walking to the fast one if it's
+        // less than 5 minutes further away
+        let near_slow = field.sample(Point2DData::new(1900.0, 0.0)).unwrap();
+        assert!((near_slow - (15.0 + 0.1 * minutes_per_km)).abs() < 1e-9);
+        assert_eq!(field.source(Point2DData::new(500.0, 10.0)), Some(0));
+        assert_eq!(field.source(Point2DData::new(1900.0, 0.0)), Some(1));
+
+        // The middle of the street is 22 minutes from the fast station, and the far end is 34
+        let nearby = TravelTimeField::within(graph, &[fast], Heading::Inbound, 30.0);
+        let vertex = |x: f64| {
+            nearby
+                .graph()
+                .nearest_vertex(Point2DData::new(x, 0.0))
+                .unwrap()
+        };
+        assert!(nearby.minutes_at_vertex(vertex(1000.0)).is_finite());
+        assert!(nearby.minutes_at_vertex(vertex(2000.0)).is_infinite());
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let tags = tags(&[("highway", "residential")]);
+        let graph = Arc::new(StreetGraph::new(vec![
+            (&tags, vec![node(1, 0.0, 0.0), node(2, 100.0, 0.0)]),
+            (&tags, vec![node(3, 1000.0, 0.0), node(4, 1100.0, 0.0)]),
+        ]));
+        let field = TravelTimeField::new(
+            graph,
+            &[(Point2DData::new(0.0, 0.0), 10.0)],
+            Heading::Inbound,
+        );
+        assert!(field.sample(Point2DData::new(50.0, 5.0)).is_some());
+        assert!(field.sample(Point2DData::new(1050.0, 5.0)).is_none());
+    }
+
+    /// On a one-way street, you can only get to a point from the start of the street, and only
+    /// get to the end of the street from a point
+    #[test]
+    fn test_one_way() {
+        let tags = tags(&[("highway", "residential"), ("oneway", "yes")]);
+        let graph = Arc::new(StreetGraph::with_rules(
+            vec![(&tags, vec![node(1, 0.0, 0.0), node(2, 1000.0, 0.0)])],
+            BIKING_METERS_PER_MINUTE,
+            is_bikeable,
+            bike_directions,
+        ));
+
+        let field = |x: f64, heading: Heading| {
+            TravelTimeField::new(graph.clone(), &[(Point2DData::new(x, 0.0), 0.0)], heading)
+        };
+        let from_start = field(0.0, Heading::Outbound);
+        let minutes = from_start.sample(Point2DData::new(250.0, 0.0)).unwrap();
+        assert!((minutes - 250.0 / BIKING_METERS_PER_MINUTE).abs() < 1e-9);
+        let from_end = field(1000.0, Heading::Outbound);
+        assert!(from_end.sample(Point2DData::new(250.0, 0.0)).is_none());
+
+        let to_end = field(1000.0, Heading::Inbound);
+        let minutes = to_end.sample(Point2DData::new(250.0, 0.0)).unwrap();
+        assert!((minutes - 750.0 / BIKING_METERS_PER_MINUTE).abs() < 1e-9);
+        let to_start = field(0.0, Heading::Inbound);
+        assert!(to_start.sample(Point2DData::new(250.0, 0.0)).is_none());
+    }
+}
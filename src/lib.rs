@@ -2,14 +2,39 @@
 //#![feature(alloc_system)]
 //extern crate alloc_system;
 
+use crate::projection::{AzimuthalEquidistant, Crs, Projection};
 use crate::protos::DenseNode;
-use geo::algorithm::bearing::Bearing;
-use geo::haversine_distance::HaversineDistance;
 use geo_types::Point;
 
+pub mod accessibility;
+pub mod clip;
+pub mod contour;
+pub mod destination;
+pub mod elevation;
 pub mod graphics;
+pub mod grid;
+pub mod gtfs;
+pub mod isochrone;
+pub mod line;
+pub mod osm_station;
 pub mod plot;
+pub mod population;
+pub mod projection;
+pub mod proposed;
 pub mod protos;
+pub mod region;
+pub mod reliability;
+pub mod report;
+pub mod routing;
+pub mod scenario;
+pub mod simplify;
+pub mod spatial;
+pub mod station;
+pub mod table;
+#[cfg(test)]
+mod testing;
+pub mod transit;
+pub mod walk_cost;
 
 use graphics::Point2DData;
 
@@ -21,7 +46,7 @@ pub struct MyNode {
 
 impl MyNode {
     pub fn to_point2d(&self) -> Point2DData {
-        Point2DData::new(self.x_y_meters[0] as f32, self.x_y_meters[1] as f32)
+        Point2DData::new(self.x_y_meters[0], self.x_y_meters[1])
     }
 }
 
@@ -31,43 +56,39 @@ impl PartialEq for MyNode {
     }
 }
 
-//impl rstar::Point for MyNode {
-//    type Scalar = f64;
-//    const DIMENSIONS: usize = 2;
-//
-//    fn generate(generator: impl Fn(usize) -> Self::Scalar) -> Self {
-//        MyNode {
-//            x_y_meters: <[f64; 2]>::generate(generator),
-//            node_id: -9999,
-//        }
-//    }
-//
-//    fn nth(&self, index: usize) -> Self::Scalar {
-//        self.x_y_meters.nth(index)
-//    }
-//
-//    fn nth_mut(&mut self, _index: usize) -> &mut Self::Scalar {
-//        unimplemented!()
-//    }
-//}
+fn centroid_projection(centroid: &Point<f64>) -> AzimuthalEquidistant {
+    // Unlike the geo library, the centroid is (lat, lon) here
+    AzimuthalEquidistant {
+        centroid: (centroid.x(), centroid.y()),
+    }
+}
+
+/// The CRS of everything that `lat_lon_to_x_y` produces for this centroid
+pub fn centroid_crs(centroid: &Point<f64>) -> Crs {
+    Crs::AzimuthalEquidistant(centroid_projection(centroid))
+}
 
-const DEG_TO_RAD: f32 = 2.0 * std::f32::consts::PI / 360.0;
+pub fn lat_lon_to_x_y(centroid: &Point<f64>, lat_lon: (f64, f64)) -> Point2DData {
+    let x_y = centroid_projection(centroid).project(lat_lon);
+    Point2DData::new(x_y[0], x_y[1])
+}
 
-pub fn lat_lon_to_x_y(centroid: &Point<f32>, lat_lon: (f32, f32)) -> Point2DData {
-    // The geo library takes (lon, lat) since it's like (x, y); x is lon, y is lat
-    let centroid_lon_lat = Point::new(centroid.y(), centroid.x());
-    let distance_from_location =
-        centroid_lon_lat.haversine_distance(&Point::new(lat_lon.1, lat_lon.0));
-    let bearing_from_location =
-        centroid_lon_lat.bearing(Point::new(lat_lon.1, lat_lon.0)) * DEG_TO_RAD;
-    Point2DData::new(
-        distance_from_location * bearing_from_location.sin(),
-        -distance_from_location * bearing_from_location.cos(),
-    )
+/// The inverse of `lat_lon_to_x_y`
+pub fn x_y_to_lat_lon(centroid: &Point<f64>, x_y: Point2DData) -> (f64, f64) {
+    centroid_projection(centroid).unproject([x_y.x, x_y.y])
 }
 
-pub fn dense_node_to_x_y(node: &DenseNode, centroid: Point<f32>) -> Point2DData {
-    let lat = node.lat as f32 / 10000000.0;
-    let lon = node.lon as f32 / 10000000.0;
+pub fn dense_node_to_x_y(node: &DenseNode, centroid: Point<f64>) -> Point2DData {
+    // OSM stores units of 1e-7 degrees. f32 would lose about a meter here, so stay in f64.
+    let lat = node.lat as f64 / 10000000.0;
+    let lon = node.lon as f64 / 10000000.0;
     lat_lon_to_x_y(&centroid, (lat, lon))
 }
+
+pub fn dense_node_to_my_node(node: &DenseNode, centroid: Point<f64>) -> MyNode {
+    let x_y = dense_node_to_x_y(node, centroid);
+    MyNode {
+        x_y_meters: [x_y.x, x_y.y],
+        node_id: node.id,
+    }
+}
@@ -0,0 +1,166 @@
+//! Transit lines, e.g. the Green Line with its branches, loaded from data instead of hardcoded.
+//! Stations refer to lines by ID, so a station like Park Street can be on several.
+use crate::gtfs::Route;
+use crate::table::{Columns, TableError};
+use std::io::Read;
+
+/// What kind of vehicle runs on a line, from GTFS `route_type`:
+/// https://developers.google.com/transit/gtfs/reference#routestxt
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    LightRail,
+    Subway,
+    Rail,
+    Bus,
+    Ferry,
+    Other(u16),
+}
+
+impl Mode {
+    pub fn from_route_type(route_type: u16) -> Self {
+        match route_type {
+            0 => Mode::LightRail,
+            1 => Mode::Subway,
+            2 => Mode::Rail,
+            3 => Mode::Bus,
+            4 => Mode::Ferry,
+            other => Mode::Other(other),
+        }
+    }
+
+    pub fn route_type(self) -> u16 {
+        match self {
+            Mode::LightRail => 0,
+            Mode::Subway => 1,
+            Mode::Rail => 2,
+            Mode::Bus => 3,
+            Mode::Ferry => 4,
+            Mode::Other(route_type) => route_type,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// What stations refer to the line by, e.g. "Green"
+    pub id: String,
+    pub name: String,
+    pub color: [f32; 4],
+    pub mode: Mode,
+    /// E.g. "B", "C", "D" and "E" for the Green Line. Empty if the line doesn't branch.
+    pub branches: Vec<String>,
+}
+
+impl Line {
+    /// One line per route, for feeds where that's good enough. Routes without a color are gray.
+    pub fn from_route(route: &Route) -> Self {
+        Line {
+            id: route.id.clone(),
+            name: if route.long_name.is_empty() {
+                route.short_name.clone()
+            } else {
+                route.long_name.clone()
+            },
+            color: route
+                .color
+                .as_deref()
+                .and_then(parse_hex_color)
+                .unwrap_or([0.5, 0.5, 0.5, 1.0]),
+            mode: Mode::from_route_type(route.route_type),
+            branches: vec![],
+        }
+    }
+}
+
+/// E.g. "00843A" or "#00843A", which is how GTFS and most style guides write colors
+pub fn parse_hex_color(hex: &str) -> Option<[f32; 4]> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| -> Option<f32> {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|value| f32::from(value) / 255.0)
+    };
+    Some([channel(0)?, channel(2)?, channel(4)?, 1.0])
+}
+
+/// A CSV with `id`, `name`, `color` and `route_type` columns, and optionally `branches`,
+/// separated by semicolons
+pub fn load_lines<R: Read>(reader: R) -> Result<Vec<Line>, TableError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let columns = Columns::new(reader.headers()?);
+    let id_column = columns.required("id")?;
+    let name_column = columns.required("name")?;
+    let color_column = columns.required("color")?;
+    let route_type_column = columns.required("route_type")?;
+    let branches_column = columns.optional("branches");
+
+    let mut lines = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let color = parse_hex_color(&record[color_column])
+            .ok_or_else(|| columns.error(&record, color_column))?;
+        let route_type: u16 = columns.parse(&record, route_type_column)?;
+        let branches = columns.list(&record, branches_column);
+        lines.push(Line {
+            id: record[id_column].to_string(),
+            name: record[name_column].to_string(),
+            color,
+            mode: Mode::from_route_type(route_type),
+            branches,
+        });
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(
+            parse_hex_color("#FF8000"),
+            Some([1.0, 128.0 / 255.0, 0.0, 1.0])
+        );
+        assert_eq!(parse_hex_color("00843a"), parse_hex_color("00843A"));
+        assert_eq!(parse_hex_color("green"), None);
+        assert_eq!(parse_hex_color("00843"), None);
+    }
+
+    #[test]
+    fn test_load_lines() {
+        let csv = "id,name,color,route_type,branches\n\
+                   Green,Green Line,00843A,0,B;C;D;E\n\
+                   Orange,Orange Line,EF8C00,1,\n";
+        let lines = load_lines(csv.as_bytes()).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].mode, Mode::LightRail);
+        assert_eq!(lines[0].branches, vec!["B", "C", "D", "E"]);
+        assert!(lines[1].branches.is_empty());
+
+        match load_lines("id,name,color,route_type\nBlue,Blue Line,blue,1\n".as_bytes()) {
+            Err(TableError::Parse { line, column, .. }) => {
+                assert_eq!((line, column.as_str()), (2, "color"));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_route() {
+        let route = Route {
+            id: String::from("Red"),
+            short_name: String::new(),
+            long_name: String::from("Red Line"),
+            route_type: 1,
+            color: Some(String::from("DA291C")),
+        };
+        let line = Line::from_route(&route);
+        assert_eq!(line.name, "Red Line");
+        assert_eq!(line.mode, Mode::Subway);
+        assert_eq!(line.color, parse_hex_color("DA291C").unwrap());
+    }
+}
@@ -2,61 +2,288 @@ extern crate env_logger;
 extern crate wgpu;
 
 use log::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use glx::accessibility::{self, Accessibility, Decay};
+use glx::contour::Raster;
+use glx::destination::{Aggregate, Destination};
+use glx::elevation::Dem;
 use glx::graphics;
 use glx::graphics::*;
-use glx::protos::osmformat::Way;
+use glx::grid::{self, GridCell};
+use glx::gtfs::{Date, Feed, Seconds};
+use glx::isochrone::*;
+use glx::line::{self, Line, Mode};
+use glx::osm_station::{self, OsmId, OsmStation};
+use glx::proposed::{ProposedLine, ProposedStation};
+use glx::protos::osmformat::{Relation_MemberType, Way};
 use glx::protos::*;
+use glx::region::{self, Region, RegionStats};
+use glx::reliability::{self, Distribution};
+use glx::routing::{
+    bike_directions, is_bikeable, is_walkable, Directions, Heading, StreetGraph,
+    BIKING_METERS_PER_MINUTE, WALKING_METERS_PER_MINUTE,
+};
+use glx::scenario::{self, Delta, Modes, Network, Scenario, TransitOptions};
+use glx::spatial::*;
+use glx::transit::{StationWalks, Timetable};
+use glx::walk_cost::WalkCost;
 use glx::*;
 use rayon::prelude::*;
 use std::fs::File;
+use std::iter;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use geo_types::Point;
 use glx::plot::*;
 
-#[derive(Clone, Debug, PartialEq)]
-enum MbtaLine {
-    Green,
-    Orange,
-    Red,
+/// A typical day
+const TYPICAL_PERCENTILE: f64 = 50.0;
+/// A time you can count on, e.g. to get to work on time
+const RELIABLE_PERCENTILE: f64 = 90.0;
+
+/// Leaving at 8 AM, when routing over the schedule
+const DEPARTURE: Seconds = 8 * 3600;
+
+/// How much the ride downtown varies from day to day, in minutes. These are guesses until we
+/// have on-time performance data; light rail shares the street with traffic in places.
+fn ride_std_dev(line: &Line) -> f64 {
+    match line.mode {
+        Mode::LightRail | Mode::Bus => 2.0,
+        _ => 1.5,
+    }
+}
+
+fn load_lines() -> Vec<Line> {
+    line::load_lines(std::fs::File::open("data/lines.csv").unwrap()).unwrap()
 }
 
 #[derive(Clone, Debug)]
 struct Station {
     name: String,
-    minutes_to_ps_dtx: f32,
     location_x_y: Point2DData,
+    /// From OSM, if it has the station
+    entrances: Vec<Point2DData>,
     glx: bool,
-    line: MbtaLine,
+    /// Line IDs, e.g. Park Street would be on both "Green" and "Red"
+    lines: Vec<String>,
+    /// E.g. "GLX-Medford"
+    branches: Vec<String>,
+    /// Riding to Park Street or Downtown Crossing
+    ride: Distribution,
+    /// Waiting plus riding
+    trip: Distribution,
+}
+
+impl Station {
+    /// Where walking to the station ends
+    fn access_points(&self) -> Vec<Point2DData> {
+        if self.entrances.is_empty() {
+            vec![self.location_x_y]
+        } else {
+            self.entrances.clone()
+        }
+    }
 }
 
-fn load_stations(centroid: Point<f32>) -> Vec<Station> {
-    csv::Reader::from_reader(
+/// How far a spreadsheet station can be from OSM's or the feed's to be the same one
+const STATION_MATCH_METERS: f64 = 300.0;
+
+/// The property in `data/wards.geojson` that wards are named by, as in Somerville's open data
+const WARD_NAME_PROPERTY: &str = "WARD";
+
+/// The property in `data/population.geojson` with each census block's population, as in the 2010
+/// census's TIGER/Line blocks
+const POPULATION_PROPERTY: &str = "POP10";
+
+/// How finely census blocks' people are spread out by area
+const POPULATION_CELL_METERS: f64 = 50.0;
+
+/// Entrances from the nearest OSM station, and its lines for stations the spreadsheet doesn't put
+/// on any
+fn add_osm_stations(stations: &mut [Station], osm_stations: &[OsmStation]) {
+    for station in stations {
+        let nearest = osm_stations
+            .iter()
+            .map(|osm| (osm, (osm.location_x_y - station.location_x_y).length()))
+            .filter(|&(_, meters)| meters <= STATION_MATCH_METERS)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        match nearest {
+            Some((osm, _)) => {
+                station.entrances = osm.entrances.clone();
+                if station.lines.is_empty() {
+                    station.lines = osm.lines.clone();
+                }
+            }
+            None => info!("{} isn't in OSM", station.name),
+        }
+    }
+}
+
+/// See `glx::station` for the columns. Rows with mistakes are logged and skipped.
+fn load_stations(centroid: Point<f64>, lines: &[Line]) -> Vec<Station> {
+    let loaded = station::load_stations(
         std::fs::File::open("data/GLX Project MBTA Data - Stations.csv").unwrap(),
+        lines,
     )
-    .records()
-    .map(|row| {
-        let row = row.unwrap();
-        let glx = &row[1] == "#N/A";
-        let lat = row[6].parse().unwrap();
-        let lon = row[7].parse().unwrap();
-        let line = match (&row[8], &row[9], &row[10]) {
-            ("1", "0", "0") => MbtaLine::Green,
-            ("0", "1", "0") => MbtaLine::Orange,
-            ("0", "0", "1") => MbtaLine::Red,
-            _ => unimplemented!("Can't handle other lines or combinations yet"),
-        };
-        Station {
-            name: row[0].to_string(),
-            location_x_y: lat_lon_to_x_y(&centroid, (lat, lon)),
-            minutes_to_ps_dtx: row[5].parse().unwrap(),
-            glx,
-            line,
+    .unwrap();
+    for error in &loaded.errors {
+        warn!("Skipping a station: {}", error);
+    }
+
+    loaded
+        .stations
+        .into_iter()
+        .map(|record| {
+            let station_lines: Vec<&Line> = lines
+                .iter()
+                .filter(|line| record.lines.contains(&line.id))
+                .collect();
+            if station_lines.is_empty() {
+                warn!("{} isn't on any line", record.name);
+            }
+            // The spreadsheet's total includes the expected wait, which is half the headway. A
+            // station on several lines is as unreliable as its worst one.
+            let headway_minutes = record.headway_minutes.unwrap_or(0.0);
+            let ride_minutes = record.total_minutes - headway_minutes / 2.0;
+            let std_dev = station_lines
+                .iter()
+                .map(|line| ride_std_dev(line))
+                .fold(0.0, f64::max);
+            let ride = Distribution::normal(ride_minutes, std_dev);
+            let trip = Distribution::wait(headway_minutes).plus(&ride);
+            Station {
+                name: record.name,
+                location_x_y: lat_lon_to_x_y(&centroid, (record.lat, record.lon)),
+                entrances: vec![],
+                glx: record.proposed,
+                lines: record.lines,
+                branches: record.branches,
+                ride,
+                trip,
+            }
+        })
+        .collect()
+}
+
+/// Waits from the schedule's headways during the morning rush instead of the spreadsheet's, for
+/// the stations the feed has. Irregular service makes for longer waits.
+fn add_scheduled_waits(stations: &mut [Station], feed: &Feed, date: Date, centroid: &Point<f64>) {
+    let timetable = Timetable::from_feed(feed, date, &[0, 1]);
+    let headways = reliability::headways(&timetable, 7 * 3600, 9 * 3600);
+    let feed_stations = feed.stations(&[0, 1], centroid);
+    for station in stations {
+        let headway = feed_stations
+            .iter()
+            .map(|feed_station| {
+                let meters = (feed_station.location_x_y - station.location_x_y).length();
+                (feed_station, meters)
+            })
+            .filter(|&(_, meters)| meters <= STATION_MATCH_METERS)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .and_then(|(feed_station, _)| {
+                let stops: Vec<usize> = feed_station
+                    .stop_ids
+                    .iter()
+                    .filter_map(|stop_id| timetable.stop(stop_id))
+                    .collect();
+                reliability::most_frequent(&headways, &stops)
+            });
+        match headway {
+            Some(headway) => station.trip = headway.wait().plus(&station.ride),
+            None => info!("No scheduled headway for {}", station.name),
         }
-    })
-    .collect()
+    }
+}
+
+/// The GLX branches from Lechmere, through the spreadsheet's stations on each branch in the order
+/// they're listed. The E branch runs on to Medford, and Union has its own trains. None if
+/// Lechmere isn't in the feed or a branch has no stations in the spreadsheet.
+fn glx_lines(
+    feed: &Feed,
+    stations: &[Station],
+    centroid: &Point<f64>,
+) -> Option<Vec<ProposedLine>> {
+    let lechmere = match feed.stops.get("place-lech") {
+        Some(stop) => ProposedStation::from_stop(stop),
+        None => {
+            warn!("Lechmere isn't in the feed");
+            return None;
+        }
+    };
+    let proposed_station = |station: &Station| {
+        let (lat, lon) = x_y_to_lat_lon(centroid, station.location_x_y);
+        ProposedStation {
+            id: format!("glx-{}", station.name),
+            name: station.name.clone(),
+            lat,
+            lon,
+        }
+    };
+    let branch = |branch: &str, through_route_id: Option<&str>| {
+        let on_branch: Vec<&Station> = stations
+            .iter()
+            .filter(|station| station.branches.iter().any(|name| name == branch))
+            .collect();
+        if on_branch.is_empty() {
+            warn!("No stations in the spreadsheet are on {}", branch);
+            return None;
+        }
+        Some(ProposedLine {
+            route_id: format!("Green-{}", branch),
+            name: String::from("Green Line Extension"),
+            route_type: 0,
+            color: Some(String::from("00843D")),
+            stations: iter::once(lechmere.clone())
+                .chain(on_branch.into_iter().map(proposed_station))
+                .collect(),
+            // The spreadsheet has 10 minutes for the 3.3 miles to College Ave.
+            meters_per_second: 8.0,
+            dwell: 30,
+            headway: 8 * 60,
+            start: 5 * 3600,
+            end: 25 * 3600,
+            through_route_id: through_route_id.map(String::from),
+        })
+    };
+    Some(vec![
+        branch("GLX-Union", None)?,
+        branch("GLX-Medford", Some("Green-E"))?,
+    ])
+}
+
+/// In the bottom right corner of every map
+fn legend(title: String, series: Vec<Series>) -> Legend {
+    Legend {
+        title,
+        series,
+        area: Box2DData::new(
+            Point2DData::new(1500.0, -3000.0),
+            Point2DData::new(3000.0, -1500.0),
+        ),
+    }
+}
+
+/// A legend for a color scale from `min` to `max`, with a series in the middle of each zone
+fn scale_legend<C: Fn(f64) -> [f32; 4]>(
+    title: String,
+    n_zones: usize,
+    (min, max): (f64, f64),
+    to_color: C,
+) -> Legend {
+    legend(
+        title,
+        (0..n_zones)
+            .map(|i| {
+                let value = min + (i as f64 + 0.5) * (max - min) / (n_zones as f64 - 1.0);
+                Series {
+                    title: format!("{}", (value * 10.0).round() / 10.0),
+                    color: to_color(value),
+                }
+            })
+            .collect(),
+    )
 }
 
 #[derive(Clone, Debug)]
@@ -65,15 +292,19 @@ struct BestStation {
     time: f32,
 }
 
-fn best_station(stations: &[Station], location_x_y: Point2DData) -> BestStation {
+/// Everyone picks the station that's best at this percentile, not whichever happens to be faster
+/// on the day
+fn best_station(stations: &[Station], location_x_y: Point2DData, percentile: f64) -> BestStation {
     let station_time = |station: &Station| {
-        let distance_walking = (location_x_y - station.location_x_y).length();
-        // Average walking speed is about 5 kph: https://en.wikipedia.org/wiki/Walking
-        let average_walking_speed_meters_per_minute = 5.0 * 1_000.0 / 60.0;
-        let fudge_factor = 1.2;
-        let walk_minutes =
-            distance_walking / average_walking_speed_meters_per_minute * fudge_factor;
-        walk_minutes + station.minutes_to_ps_dtx
+        let distance_walking = station
+            .access_points()
+            .iter()
+            .map(|&access| (location_x_y - access).length())
+            .fold(f64::INFINITY, f64::min) as f32;
+        // Streets don't go as the crow flies
+        let detour_factor = 1.2;
+        let walk_minutes = distance_walking / WALKING_METERS_PER_MINUTE as f32 * detour_factor;
+        walk_minutes + station.trip.percentile(percentile) as f32
     };
 
     let best_station = stations
@@ -93,7 +324,7 @@ mod tests {
 
     #[test]
     fn test_load_stations() {
-        let stations = load_stations(geo_types::Point::new(42.386755, -71.098472));
+        let stations = load_stations(geo_types::Point::new(42.386755, -71.098472), &load_lines());
 
         let station: &Station = stations
             .iter()
@@ -108,14 +339,17 @@ mod tests {
             station.location_x_y.to_vector().length() > 100.0,
             "Gilman is not THAT close to city hall"
         );
-        assert_eq!(station.minutes_to_ps_dtx, 19.0);
+        // 15 minutes on the train, and half of the 8 minute headway
+        assert!((station.trip.percentile(TYPICAL_PERCENTILE) - 19.0).abs() < 0.01);
+        assert_eq!(station.lines, vec!["Green"]);
     }
 
     #[test]
     fn test_best_station() {
-        let stations = load_stations(geo_types::Point::new(42.386755, -71.098472));
+        let stations = load_stations(geo_types::Point::new(42.386755, -71.098472), &load_lines());
 
-        let best_station: BestStation = best_station(&stations, Point2DData::new(0.0, 0.0));
+        let best_station: BestStation =
+            best_station(&stations, Point2DData::new(0.0, 0.0), TYPICAL_PERCENTILE);
 
         assert_eq!(
             best_station.station.name, "Gilman",
@@ -124,6 +358,9 @@ mod tests {
 
         assert!(best_station.time > 20.0);
         assert!(best_station.time < 25.0);
+
+        let reliable = best_station(&stations, Point2DData::new(0.0, 0.0), RELIABLE_PERCENTILE);
+        assert!(reliable.time > best_station.time + 2.0);
     }
 }
 
@@ -138,14 +375,16 @@ fn main() {
     );
 
     // Somerville city hall (93 Highland)
-    let centroid: geo_types::Point<f32> = geo_types::Point::new(42.386755, -71.098472);
+    let centroid: geo_types::Point<f64> = geo_types::Point::new(42.386755, -71.098472);
 
-    let stations: Vec<Station> = load_stations(centroid);
-    let stations_before: Vec<Station> = stations
-        .clone()
-        .into_iter()
-        .filter(|station| !station.glx)
+    let crs = centroid_crs(&centroid);
+
+    let lines = load_lines();
+    let line_colors: HashMap<String, [f32; 4]> = lines
+        .iter()
+        .map(|line| (line.id.clone(), line.color))
         .collect();
+    let mut stations: Vec<Station> = load_stations(centroid, &lines);
 
     info!("Loading OSM data...");
     let reader = File::open("pbf/massachusetts-latest.osm.pbf").unwrap();
@@ -179,13 +418,6 @@ fn main() {
         .map(|blob_data| {
             if let FileBlock::Primitive(primitive_block) = blob_data.deserialize() {
                 into_vec_ways(primitive_block)
-                    .into_iter()
-                    .filter(|way: &MyWay| {
-                        get_nodes_vec(way.way.clone())
-                            .iter()
-                            .any(|node| viewport.contains(&dense_node_to_x_y(&node, centroid)))
-                    })
-                    .collect::<Vec<MyWay>>()
             } else {
                 vec![]
             }
@@ -195,7 +427,339 @@ fn main() {
         .flatten()
         .collect();
 
-    info!("{} ways loaded from OSM", ways.len());
+    // Nothing further out than the boundaries of the regions below is ever looked up, so the
+    // rest of Massachusetts doesn't need to be indexed
+    let index_area = viewport.inflate(20_000.0, 20_000.0);
+    info!("Indexing ways near the viewport out of {}...", ways.len());
+    let way_index: BoxIndex<MyWay> = BoxIndex::new(
+        ways.into_par_iter()
+            .filter_map(|way: MyWay| {
+                let points: Vec<Point2DData> = get_nodes_vec(way.way.clone())
+                    .iter()
+                    .map(|node| dense_node_to_x_y(node, centroid))
+                    .collect();
+                bounds(&points)
+                    .filter(|way_bounds| way_bounds.intersects(&index_area))
+                    .map(|way_bounds| (way_bounds, way))
+            })
+            .collect(),
+    );
+    let ways: Vec<&MyWay> = way_index.intersecting(&viewport);
+
+    info!("{} ways in the viewport", ways.len());
+
+    // Walk a little past the edge of the viewport, since the best way to a station near the edge
+    // can go outside it
+    info!("Building walking graph...");
+    let walk_area = viewport.inflate(1000.0, 1000.0);
+    let walk_area_ways: Vec<(&HashMap<String, String>, Vec<MyNode>)> = way_index
+        .intersecting(&walk_area)
+        .into_iter()
+        .map(|way: &MyWay| {
+            let nodes: Vec<MyNode> = get_nodes_vec(way.way.clone())
+                .iter()
+                .map(|node| dense_node_to_my_node(node, centroid))
+                .collect();
+            (&way.tags, nodes)
+        })
+        .collect();
+    // Hills only count if there's a DEM, e.g. from MassGIS's LiDAR terrain data, converted to an
+    // ASCII grid in longitude and latitude
+    let dem_path = PathBuf::from("data/dem.asc");
+    let walk_cost = WalkCost {
+        elevation: if dem_path.exists() {
+            info!("Loading elevations...");
+            let dem = Dem::load_ascii_grid(File::open(&dem_path).unwrap()).unwrap();
+            Some(Box::new(move |point: Point2DData| {
+                let (lat, lon) = x_y_to_lat_lon(&centroid, point);
+                dem.elevation(lat, lon)
+            }))
+        } else {
+            None
+        },
+        ..WalkCost::default()
+    };
+    let walk_graph = Arc::new(StreetGraph::with_costs(
+        walk_area_ways
+            .iter()
+            .map(|(tags, nodes)| (*tags, nodes.clone())),
+        WALKING_METERS_PER_MINUTE,
+        is_walkable,
+        |_| Directions::BOTH,
+        |tags, geometry, share| walk_cost.minutes(tags, geometry, share),
+    ));
+
+    info!("Finding stations in OSM...");
+    let tagged_nodes: Vec<MyTaggedNode> = vec_blob
+        .par_iter()
+        .flat_map(|blob_data| {
+            if let FileBlock::Primitive(primitive_block) = blob_data.deserialize() {
+                into_vec_tagged_nodes(&primitive_block)
+            } else {
+                vec![]
+            }
+        })
+        .collect();
+    let relations: Vec<MyRelation> = vec_blob
+        .par_iter()
+        .flat_map(|blob_data| {
+            if let FileBlock::Primitive(primitive_block) = blob_data.deserialize() {
+                into_vec_relations(&primitive_block)
+            } else {
+                vec![]
+            }
+        })
+        .collect();
+    let is_station_feature = |tags: &HashMap<String, String>| {
+        osm_station::is_station(tags)
+            || osm_station::is_stop(tags)
+            || osm_station::is_entrance(tags)
+    };
+    let station_ways = way_index
+        .intersecting(&walk_area)
+        .into_iter()
+        .filter(|way: &&MyWay| is_station_feature(&way.tags))
+        .filter_map(|way: &MyWay| {
+            let points: Vec<Point2DData> = get_nodes_vec(way.way.clone())
+                .iter()
+                .map(|node| dense_node_to_x_y(node, centroid))
+                .collect();
+            mean_point(&points).map(|middle| (OsmId::Way(way.way.get_id()), &way.tags, middle))
+        });
+    let osm_stations = osm_station::extract_stations(
+        tagged_nodes
+            .iter()
+            .map(|tagged_node| {
+                (
+                    OsmId::Node(tagged_node.node.id),
+                    &tagged_node.tags,
+                    dense_node_to_x_y(&tagged_node.node, centroid),
+                )
+            })
+            .filter(|(_, _, location_x_y)| walk_area.contains(location_x_y))
+            .chain(station_ways),
+        relations.iter().map(|relation| {
+            (
+                &relation.tags,
+                relation
+                    .members
+                    .iter()
+                    .filter_map(|member| match member.member_type {
+                        Relation_MemberType::NODE => Some(OsmId::Node(member.id)),
+                        Relation_MemberType::WAY => Some(OsmId::Way(member.id)),
+                        _ => None,
+                    })
+                    .collect(),
+            )
+        }),
+        |tags| osm_station::line_of(&lines, tags).map(|line| line.id.clone()),
+    );
+    info!("{} stations in OSM", osm_stations.len());
+    add_osm_stations(&mut stations, &osm_stations);
+    let stations_before: Vec<Station> = stations
+        .clone()
+        .into_iter()
+        .filter(|station| !station.glx)
+        .collect();
+
+    // Downtown is what the spreadsheet's times are to, so it comes first. The others need a
+    // schedule to route over.
+    let downtown = Destination {
+        name: String::from("Downtown"),
+        weight: 2.0,
+        points: vec![
+            // Park Street and Downtown Crossing
+            lat_lon_to_x_y(&centroid, (42.356395, -71.062424)),
+            lat_lon_to_x_y(&centroid, (42.355518, -71.060225)),
+        ],
+    };
+    let harvard = Destination::from_osm(
+        "Harvard",
+        1.0,
+        way_index
+            .intersecting(&walk_area)
+            .into_iter()
+            .map(|way: &MyWay| {
+                let points: Vec<Point2DData> = get_nodes_vec(way.way.clone())
+                    .iter()
+                    .map(|node| dense_node_to_x_y(node, centroid))
+                    .collect();
+                (&way.tags, points)
+            }),
+        |tags| tags.get("name").map(String::as_str) == Some("Harvard Yard"),
+    );
+    if harvard.points.is_empty() {
+        warn!("Couldn't find Harvard Yard in OSM");
+    }
+    let kendall = Destination::point(
+        "Kendall/MIT",
+        1.0,
+        lat_lon_to_x_y(&centroid, (42.362491, -71.086176)),
+    );
+
+    // Real itineraries from the schedule, if MBTA's feed has been downloaded from
+    // https://www.mbta.com/developers/gtfs. Otherwise, use the spreadsheet's times.
+    let gtfs_path = PathBuf::from("data/MBTA_GTFS.zip");
+    let schedule: Option<(Feed, Feed, Date)> = if gtfs_path.exists() {
+        let feed = Feed::open(&gtfs_path).unwrap();
+        // A feed only covers a few months, so the day has to come from its calendar
+        let date = feed.typical_weekday().filter(|&date| {
+            !Timetable::from_feed(&feed, date, &[0, 1])
+                .connections
+                .is_empty()
+        });
+        if date.is_none() {
+            warn!(
+                "{} has no rail service on an ordinary Monday",
+                gtfs_path.display()
+            );
+        }
+        match (date, glx_lines(&feed, &stations, &centroid)) {
+            (Some(date), Some(lines)) => {
+                let mut feed_after = feed.clone();
+                for line in lines {
+                    line.add_to(&mut feed_after);
+                }
+                Some((feed, feed_after, date))
+            }
+            _ => {
+                warn!("Using the spreadsheet's times instead of the schedule");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some((_, feed_after, date)) = &schedule {
+        add_scheduled_waits(&mut stations, feed_after, *date, &centroid);
+    }
+    let stations = stations;
+
+    info!("Computing travel times...");
+    let sources = |stations: &[Station], percentile: f64| -> Vec<(Point2DData, f64)> {
+        stations
+            .iter()
+            .flat_map(|station| {
+                let minutes = station.trip.percentile(percentile);
+                station
+                    .access_points()
+                    .into_iter()
+                    .map(move |access| (access, minutes))
+            })
+            .collect()
+    };
+    // Which station each of the sources above is for, in the same order
+    let access_stations: Vec<&Station> = stations
+        .iter()
+        .flat_map(|station| station.access_points().into_iter().map(move |_| station))
+        .collect();
+    // People walk to the stations, so uphill is toward them
+    let field = Arc::new(TravelTimeField::new(
+        walk_graph.clone(),
+        &sources(&stations, TYPICAL_PERCENTILE),
+        Heading::Inbound,
+    ));
+    let field_reliable = TravelTimeField::new(
+        walk_graph.clone(),
+        &sources(&stations, RELIABLE_PERCENTILE),
+        Heading::Inbound,
+    );
+
+    let before_color = [0.6, 0.6, 0.6, 1.0];
+    // The GLX is part of the Green Line
+    let after_color = line_colors.get("Green").cloned().unwrap_or_else(|| {
+        warn!("lines.csv doesn't have the Green Line");
+        [0.0, 0.52, 0.24, 1.0]
+    });
+    let (scenarios, mode_comparisons): (Vec<Scenario>, Vec<(String, Scenario, Scenario)>) =
+        if let Some((feed_before, feed_after, date)) = &schedule {
+            info!("Routing over the GTFS feed for {}...", date);
+
+            let options = TransitOptions {
+                centroid,
+                date: *date,
+                departure: DEPARTURE,
+                destinations: vec![downtown, harvard, kendall],
+                area: walk_area,
+            };
+
+            let bike_graph = Arc::new(StreetGraph::with_rules(
+                walk_area_ways
+                    .iter()
+                    .map(|(tags, nodes)| (*tags, nodes.clone())),
+                BIKING_METERS_PER_MINUTE,
+                is_bikeable,
+                bike_directions,
+            ));
+            let all_modes = [
+                Modes {
+                    name: String::from("Walk + rail"),
+                    access: walk_graph.clone(),
+                    route_types: vec![0, 1],
+                },
+                Modes {
+                    name: String::from("Bike + rail"),
+                    access: bike_graph,
+                    route_types: vec![0, 1],
+                },
+                Modes {
+                    name: String::from("Walk + bus + rail"),
+                    access: walk_graph.clone(),
+                    route_types: vec![0, 1, 3],
+                },
+            ];
+            let mode_comparisons: Vec<(String, Scenario, Scenario)> = all_modes
+                .iter()
+                .map(|modes| {
+                    info!("Routing {}...", modes.name);
+                    let scenario = |name: &str, color: [f32; 4], feed: &Feed| {
+                        Scenario::from_feed(name, color, feed, &options, modes)
+                            .with_aggregate(Aggregate::WeightedAverage)
+                    };
+                    (
+                        modes.name.clone(),
+                        scenario("Before GLX", before_color, feed_before),
+                        scenario("After GLX", after_color, feed_after),
+                    )
+                })
+                .collect();
+            // The maps are for walking to the train, like the spreadsheet
+            let (_, before, after) = mode_comparisons[0].clone();
+            (vec![before, after], mode_comparisons)
+        } else {
+            let sources_before = sources(&stations_before, TYPICAL_PERCENTILE);
+            let network = |stations: &[Station]| {
+                Network::new(
+                    stations.iter().map(|station| station.name.clone()),
+                    stations.iter().flat_map(|station| station.lines.clone()),
+                )
+            };
+            let field = field.clone();
+            let scenarios = vec![
+                Scenario::from_stations(
+                    "Before GLX",
+                    before_color,
+                    walk_graph.clone(),
+                    &sources_before,
+                    downtown.clone(),
+                )
+                .with_network(network(&stations_before)),
+                Scenario::new("After GLX", after_color, downtown, move |point| {
+                    field.sample(point)
+                })
+                .with_network(network(&stations)),
+            ];
+            (scenarios, vec![])
+        };
+    let (before, after) = (&scenarios[0], &scenarios[1]);
+    scenario::write_networks(&scenarios, File::create("output/scenarios.csv").unwrap()).unwrap();
+    let added = after.network.minus(&before.network);
+    info!(
+        "{} adds {} stations: {}",
+        after.name,
+        added.stations.len(),
+        added.stations.join(", ")
+    );
 
     let color_object = 1.0;
     let alpha_object = 1.0;
@@ -203,7 +767,7 @@ fn main() {
     // Popular tags: https://taginfo.openstreetmap.org/tags
     let osm_styled_geoms: Vec<_> = ways
         .into_par_iter()
-        .filter_map(|way: MyWay| {
+        .filter_map(|way: &MyWay| {
             let nodes: Vec<_> = get_nodes_vec(way.way.clone())
                 .into_iter()
                 .map(|node| dense_node_to_x_y(&node, centroid))
@@ -233,12 +797,12 @@ fn main() {
             //                })
             } else if way.tags.contains_key("highway") {
                 // It seem like this is in feet
-                let meters_per_foot: f32 = 1.0 / 3.0;
+                let meters_per_foot: f64 = 1.0 / 3.0;
                 let width = way
                     .tags
                     .get("width")
                     .unwrap_or(&String::from("3.0"))
-                    .parse::<f32>()
+                    .parse::<f64>()
                     .unwrap_or(3.0)
                     * meters_per_foot;
                 Some(StyledGeom {
@@ -259,58 +823,642 @@ fn main() {
                 //                })
             }
         })
+        // Only tessellate what's actually in the viewport
+        .flat_map(|styled_geom: StyledGeom| Render2::styled_geoms(&styled_geom, viewport))
+        .map(|styled_geom| Projected {
+            t: styled_geom,
+            crs,
+        })
         .collect();
 
-    let stations_2 = stations.clone();
+    let cell_size = 300.0;
+
+    // A cell whose center is this far from any street is in the harbor, a rail yard, etc.
+    let max_meters_to_street = cell_size / 2.0;
+    let is_off_street = move |field: &TravelTimeField, point: Point2DData| -> bool {
+        field
+            .graph()
+            .distance_to_street(point)
+            .filter(|&meters| meters <= max_meters_to_street)
+            .is_none()
+    };
+
+    info!("Writing grid cells...");
+    let mut cells_writer = csv::Writer::from_path("output/cells.csv").unwrap();
+    let header = [
+        "lat",
+        "lon",
+        "station",
+        "minutes_before",
+        "minutes",
+        "minutes_crow_flies",
+        "minutes_p90",
+        "improvement",
+        "percent_improvement",
+        "meters_to_street",
+    ];
+    cells_writer
+        .write_record(
+            header.iter().map(|column| column.to_string()).chain(
+                after
+                    .destinations()
+                    .iter()
+                    .map(|destination| format!("minutes_to_{}", destination.name)),
+            ),
+        )
+        .unwrap();
+    for cell_center in grid_cell_centers(viewport, cell_size) {
+        let station = field
+            .source(cell_center)
+            .map_or_else(String::new, |source| access_stations[source].name.clone());
+        let delta = Delta::new(before, after, cell_center);
+        let minutes = delta
+            .after
+            .map_or_else(String::new, |minutes| minutes.to_string());
+        // A rough estimate from the nearest station entrance, for where the streets don't reach
+        let minutes_crow_flies = best_station(&stations, cell_center, TYPICAL_PERCENTILE)
+            .time
+            .to_string();
+        let minutes_before = delta
+            .before
+            .map_or_else(String::new, |minutes| minutes.to_string());
+        // The spreadsheet's ride times, since the schedule doesn't say how late trains run
+        let minutes_p90 = field_reliable
+            .sample(cell_center)
+            .map_or_else(String::new, |minutes| minutes.to_string());
+        let improvement = delta
+            .improvement()
+            .map_or_else(String::new, |minutes| minutes.to_string());
+        let percent_improvement = delta
+            .percent_improvement()
+            .map_or_else(String::new, |percent| percent.to_string());
+        let meters_to_street = field
+            .graph()
+            .distance_to_street(cell_center)
+            .map_or_else(String::new, |meters| meters.to_string());
+        let minutes_to = (0..after.destinations().len()).map(|destination| {
+            after
+                .minutes_to(destination, cell_center)
+                .map_or_else(String::new, |minutes| minutes.to_string())
+        });
+        let (lat, lon) = x_y_to_lat_lon(&centroid, cell_center);
+        cells_writer
+            .write_record(
+                [
+                    lat.to_string(),
+                    lon.to_string(),
+                    station,
+                    minutes_before,
+                    minutes,
+                    minutes_crow_flies,
+                    minutes_p90,
+                    improvement,
+                    percent_improvement,
+                    meters_to_street,
+                ]
+                .iter()
+                .cloned()
+                .chain(minutes_to),
+            )
+            .unwrap();
+    }
+    cells_writer.flush().unwrap();
+
+    if !mode_comparisons.is_empty() {
+        info!("Comparing modes...");
+        let mut modes_writer = csv::Writer::from_path("output/modes.csv").unwrap();
+        modes_writer
+            .write_record(
+                ["lat", "lon"]
+                    .iter()
+                    .map(|column| column.to_string())
+                    .chain(mode_comparisons.iter().map(|(name, _, _)| name.clone())),
+            )
+            .unwrap();
+        let mut totals = vec![(0.0, 0); mode_comparisons.len()];
+        for cell_center in grid_cell_centers(viewport, cell_size) {
+            if is_off_street(&field, cell_center) {
+                continue;
+            }
+            let improvements: Vec<Option<f64>> = mode_comparisons
+                .iter()
+                .map(|(_, before, after)| Delta::new(before, after, cell_center).improvement())
+                .collect();
+            for (total, improvement) in totals.iter_mut().zip(&improvements) {
+                if let Some(minutes) = improvement {
+                    total.0 += minutes;
+                    total.1 += 1;
+                }
+            }
+            let (lat, lon) = x_y_to_lat_lon(&centroid, cell_center);
+            modes_writer
+                .write_record(
+                    [lat.to_string(), lon.to_string()].iter().cloned().chain(
+                        improvements
+                            .iter()
+                            .map(|minutes| minutes.map_or_else(String::new, |m| m.to_string())),
+                    ),
+                )
+                .unwrap();
+        }
+        modes_writer.flush().unwrap();
+        for ((name, _, _), (total, count)) in mode_comparisons.iter().zip(totals) {
+            info!(
+                "{}: the GLX saves {:.1} minutes on average",
+                name,
+                total / count.max(1) as f64
+            );
+        }
+    }
+
+    info!("Summarizing by population...");
+    // Census blocks if there are any, as polygons or as centroids. Otherwise, residential
+    // buildings in OSM, at roughly Somerville's people per square meter of footprint.
+    let blocks_path = PathBuf::from("data/population.geojson");
+    let population_path = PathBuf::from("data/population.csv");
+    let mut residents = if blocks_path.exists() {
+        population::load_geojson(
+            &std::fs::read_to_string(&blocks_path).unwrap(),
+            &centroid,
+            POPULATION_PROPERTY,
+            POPULATION_CELL_METERS,
+        )
+        .unwrap()
+    } else if population_path.exists() {
+        population::load_csv(File::open(&population_path).unwrap(), &centroid).unwrap()
+    } else {
+        population::from_buildings(
+            way_index
+                .intersecting(&viewport)
+                .into_iter()
+                .map(|way: &MyWay| {
+                    let points: Vec<Point2DData> = get_nodes_vec(way.way.clone())
+                        .iter()
+                        .map(|node| dense_node_to_x_y(node, centroid))
+                        .collect();
+                    (&way.tags, points)
+                }),
+            0.03,
+        )
+    };
+    let neighborhoods: Vec<(String, Point2DData)> = tagged_nodes
+        .iter()
+        .filter(|tagged_node| {
+            tagged_node.tags.get("place").map(String::as_str) == Some("neighbourhood")
+        })
+        .filter_map(|tagged_node| {
+            let location_x_y = dense_node_to_x_y(&tagged_node.node, centroid);
+            match tagged_node.tags.get("name") {
+                Some(name) if walk_area.contains(&location_x_y) => {
+                    Some((name.clone(), location_x_y))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    population::assign_neighborhoods(&mut residents, &neighborhoods);
+    let thresholds = [15.0, 20.0, 30.0];
+    let summary = report::summarize(&scenarios, &residents, &thresholds);
+    report::write_csv(
+        &summary,
+        &thresholds,
+        File::create("output/summary.csv").unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        "output/summary.json",
+        report::to_json(&summary, &thresholds),
+    )
+    .unwrap();
+    for row in summary
+        .iter()
+        .filter(|row| row.neighborhood == report::EVERYONE)
+    {
+        info!(
+            "{}: median {:.1} minutes, {:.0}% within {} minutes",
+            row.scenario,
+            row.summary.median_minutes.unwrap_or(f64::NAN),
+            row.summary.share_within[1] * 100.0,
+            thresholds[1]
+        );
+    }
+
+    info!("Aggregating by region...");
+    // Wards from the city's open data if there's a GeoJSON of them. Otherwise, the city and
+    // neighborhood boundaries in OSM, which can reach well past the viewport.
+    let wards_path = PathBuf::from("data/wards.geojson");
+    let regions: Vec<Region> = if wards_path.exists() {
+        region::load_geojson(
+            &std::fs::read_to_string(&wards_path).unwrap(),
+            &centroid,
+            WARD_NAME_PROPERTY,
+        )
+        .unwrap()
+    } else {
+        let boundaries: Vec<&MyRelation> = relations
+            .iter()
+            .filter(|relation| {
+                relation.tags.get("boundary").map(String::as_str) == Some("administrative")
+                    && matches!(
+                        relation.tags.get("admin_level").map(String::as_str),
+                        Some("8") | Some("10")
+                    )
+            })
+            .collect();
+        let member_way_ids: HashSet<i64> = boundaries
+            .iter()
+            .flat_map(|relation| relation.members.iter())
+            .filter(|member| member.member_type == Relation_MemberType::WAY)
+            .map(|member| member.id)
+            .collect();
+        let member_ways: HashMap<i64, Vec<Point2DData>> = way_index
+            .intersecting(&index_area)
+            .into_iter()
+            .filter(|way: &&MyWay| member_way_ids.contains(&way.way.get_id()))
+            .map(|way: &MyWay| {
+                let points: Vec<Point2DData> = get_nodes_vec(way.way.clone())
+                    .iter()
+                    .map(|node| dense_node_to_x_y(node, centroid))
+                    .collect();
+                (way.way.get_id(), points)
+            })
+            .collect();
+        region::from_osm(
+            boundaries.iter().map(|relation| {
+                (
+                    &relation.tags,
+                    relation
+                        .members
+                        .iter()
+                        .filter(|member| member.member_type == Relation_MemberType::WAY)
+                        .filter_map(|member| {
+                            member_ways
+                                .get(&member.id)
+                                .map(|points| (member.role.clone(), points.clone()))
+                        })
+                        .collect(),
+                )
+            }),
+            |tags| tags.contains_key("name"),
+        )
+    }
+    .into_iter()
+    .filter(|region| {
+        region
+            .bounds()
+            .filter(|bounds| bounds.intersects(&viewport))
+            .is_some()
+    })
+    .collect();
+    info!("{} regions", regions.len());
+    // Before, after and the improvement, on the same streets as the maps
+    let region_stats: Vec<[RegionStats; 3]> = regions
+        .par_iter()
+        .map(|region| {
+            let on_street = |point: Point2DData| !is_off_street(&field, point);
+            [
+                region::aggregate(region, cell_size, |point| {
+                    before.minutes(point).filter(|_| on_street(point))
+                }),
+                region::aggregate(region, cell_size, |point| {
+                    after.minutes(point).filter(|_| on_street(point))
+                }),
+                region::aggregate(region, cell_size, |point| {
+                    Delta::new(before, after, point)
+                        .improvement()
+                        .filter(|_| on_street(point))
+                }),
+            ]
+        })
+        .collect();
+    region::write_csv(
+        &regions,
+        &region_stats,
+        File::create("output/regions.csv").unwrap(),
+    )
+    .unwrap();
+    if let Some((region, stats)) = regions
+        .iter()
+        .zip(&region_stats)
+        .filter(|(_, stats)| stats[2].mean.is_some())
+        .max_by(|(_, a), (_, b)| a[2].mean.partial_cmp(&b[2].mean).unwrap())
+    {
+        info!(
+            "{} gains the most from the GLX: {:.1} minutes on average",
+            region.name,
+            stats[2].mean.unwrap()
+        );
+    }
+
+    info!("Counting opportunities...");
+    // A weighted CSV if there is one, e.g. jobs by census block. Otherwise, the shops and
+    // amenities in OSM.
+    let opportunities_path = PathBuf::from("data/opportunities.csv");
+    let opportunities = if opportunities_path.exists() {
+        accessibility::load_csv(File::open(&opportunities_path).unwrap(), &centroid).unwrap()
+    } else {
+        accessibility::from_osm(
+            tagged_nodes
+                .iter()
+                .map(|tagged_node| {
+                    (
+                        &tagged_node.tags,
+                        dense_node_to_x_y(&tagged_node.node, centroid),
+                    )
+                })
+                .filter(|(_, location_x_y)| walk_area.contains(location_x_y)),
+            |tags| tags.contains_key("amenity") || tags.contains_key("shop"),
+        )
+    };
+    info!("{} opportunities", opportunities.len());
+    let access_minutes = 15.0;
+    let access = Accessibility::new(walk_graph.clone(), &opportunities, access_minutes);
+    // Riding counts too if there's a schedule, the same way as in the scenarios
+    let (access, access_title) = match &schedule {
+        Some((_, feed_after, date)) => {
+            let timetable = Timetable::from_feed(feed_after, *date, &[0, 1]);
+            let feed_stations: Vec<_> = feed_after
+                .stations(&[0, 1], &centroid)
+                .into_iter()
+                .filter(|station| walk_area.contains(&station.location_x_y))
+                .collect();
+            let walks = Arc::new(StationWalks::new(
+                walk_graph.clone(),
+                &timetable,
+                &feed_stations,
+            ));
+            (
+                access.with_transit(timetable, walks, DEPARTURE),
+                format!("Opportunities within {} Minutes by Rail", access_minutes),
+            )
+        }
+        None => (
+            access,
+            format!("Opportunities within {} Minutes' Walk", access_minutes),
+        ),
+    };
+    // A search per cell, so each hexagon is sampled once for both its color and its label
+    let access_cells: Vec<GridCell<Option<f64>>> = grid::hex_cells(viewport, cell_size, |center| {
+        if is_off_street(&field, center) {
+            None
+        } else {
+            Some(access.at(center, &[Decay::Threshold(access_minutes)])[0])
+        }
+    });
+
+    // Small cells only where the minutes saved change by more than this from one cell to the
+    // next, e.g. around the new stations
+    let split_minutes = 2.0;
+    let delta_cells: Vec<GridCell<Option<f64>>> = grid::quadtree_cells(
+        viewport,
+        cell_size * 4.0,
+        cell_size / 2.0,
+        |center| {
+            if is_off_street(&field, center) {
+                None
+            } else {
+                Delta::new(before, after, center).improvement()
+            }
+        },
+        |a: &Option<f64>, b: &Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() > split_minutes,
+            _ => false,
+        },
+    );
+
+    // Three samples per cell, so the contour lines come out smooth
+    let downtown_raster = Raster::sample(viewport, cell_size / 3.0, |point| {
+        if is_off_street(&field, point) {
+            None
+        } else {
+            after.minutes_to(0, point)
+        }
+    });
+
+    let delta_title = format!("Minutes Saved, {} vs. {}", after.name, before.name);
+
+    // Downtown over the walking network, and nothing where there's no street nearby
+    let minutes_at = |scenario: &Scenario, point: Point2DData| -> Option<f32> {
+        if is_off_street(&field, point) {
+            None
+        } else {
+            scenario.minutes_to(0, point).map(|minutes| minutes as f32)
+        }
+    };
 
     let n_zones = 6;
 
     let time_to_color = |time| scale_temperature(1.0 - (time - 10.0) / 40.0, n_zones as f32);
 
-    let render: Layers<Box<Render>> = Layers(vec![
+    // The maps only borrow what they show, since each is captured before moving on to the next
+    let render: Layers<Box<dyn Render + '_>> = Layers(vec![
         Box::new(FnGrid {
             viewport: Some(viewport),
-            cell_size: 300.0,
-            color_fn: move |point| {
-                // Using a move closure here is sort of weird. Do we really want to maintain a
-                // dependency on our data all the way through our rendering phases?
+            cell_size,
+            color_fn: |point| minutes_at(after, point).map_or([0.0, 0.0, 0.0, 0.0], time_to_color),
+            label_fn: |point| {
+                minutes_at(after, point)
+                    .map_or_else(String::new, |minutes| format!("{}", minutes as usize))
+            },
+        }),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+        Box::new(
+            scale_legend(
+                String::from("Time to Downtown Boston"),
+                n_zones,
+                (10.0, 40.0),
+                |time| time_to_color(time as f32),
+            )
+            .render(),
+        ),
+    ]);
 
-                let best_after = best_station(&stations, point);
+    graphics::capture(render, viewport, PathBuf::from("output/map.png"), 4096);
 
-                let _color = match best_after.station.line {
-                    MbtaLine::Green => [0.0 / 255.0, 132.0 / 255.0, 58.0 / 255.0, 1.0],
-                    MbtaLine::Orange => [239.0 / 255.0, 140.0 / 255.0, 0.0 / 255.0, 1.0],
-                    MbtaLine::Red => [217.0 / 255.0, 37.0 / 255.0, 10.0 / 255.0, 1.0],
-                };
+    info!("Rendering contours...");
+    let contour_minutes: Vec<f64> = (2..=8).map(|i| i as f64 * 5.0).collect();
+    let contour_render: Layers<Box<dyn Render>> = Layers(vec![
+        Box::new(
+            Contours {
+                raster: downtown_raster,
+                band_colors: contour_minutes
+                    .iter()
+                    .map(|&minutes| time_to_color(minutes as f32 + 2.5))
+                    .collect(),
+                thresholds: contour_minutes,
+                line_color: [0.0, 0.0, 0.0, 1.0],
+                line_width: 15.0,
+                label_spacing: 1500.0,
+            }
+            .render(),
+        ),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+    ]);
+
+    graphics::capture(
+        contour_render,
+        viewport,
+        PathBuf::from("output/contours.png"),
+        4096,
+    );
 
-                time_to_color(best_after.time)
+    info!("Rendering the difference...");
+    let max_improvement = 15.0;
+    let improvement_to_color =
+        |minutes: f64| scale_chroma((minutes / max_improvement) as f32, n_zones as f32);
+    let improvement_legend = |title: &str| {
+        scale_legend(
+            title.to_string(),
+            n_zones,
+            (0.0, max_improvement),
+            improvement_to_color,
+        )
+    };
+    let delta_render: Layers<Box<dyn Render + '_>> = Layers(vec![
+        Box::new(FnGrid {
+            viewport: Some(viewport),
+            cell_size,
+            color_fn: |point| match Delta::new(before, after, point).improvement() {
+                Some(minutes) if !is_off_street(&field, point) => improvement_to_color(minutes),
+                _ => [0.0, 0.0, 0.0, 0.0],
             },
-            label_fn: move |point| {
-                let best_after = best_station(&stations_2, point);
-                format!("{}", best_after.time as usize)
+            label_fn: |point| match Delta::new(before, after, point).improvement() {
+                Some(minutes) if !is_off_street(&field, point) => {
+                    format!("{}", minutes.round())
+                }
+                _ => String::new(),
             },
         }),
-        Box::new(Layer(osm_styled_geoms)),
-        Box::new(
-            Legend {
-                title: String::from("Time to Downtown Boston"),
-                series: (0..n_zones)
-                    .map(|i| {
-                        let time = 10.0 + (i as f32 + 0.5) * (40.0 - 10.0) / (n_zones as f32 - 1.0);
-                        Series {
-                            title: format!("{}", time),
-                            color: time_to_color(time),
-                        }
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+        Box::new(improvement_legend(&delta_title).render()),
+    ]);
+
+    graphics::capture(
+        delta_render,
+        viewport,
+        PathBuf::from("output/delta.png"),
+        4096,
+    );
+
+    info!("Rendering the difference with adaptive cells...");
+    let adaptive_render: Layers<Box<dyn Render + '_>> = Layers(vec![
+        Box::new(SampledGrid {
+            cells: delta_cells,
+            color_fn: |minutes: &Option<f64>| {
+                minutes.map_or([0.0, 0.0, 0.0, 0.0], improvement_to_color)
+            },
+            label_fn: |minutes: &Option<f64>| {
+                minutes.map_or_else(String::new, |minutes| format!("{}", minutes.round()))
+            },
+        }),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+        Box::new(improvement_legend(&delta_title).render()),
+    ]);
+
+    graphics::capture(
+        adaptive_render,
+        viewport,
+        PathBuf::from("output/delta_adaptive.png"),
+        4096,
+    );
+
+    info!("Rendering which scenario wins...");
+    let legend_series: Vec<Series> = scenarios
+        .iter()
+        .map(|scenario| Series {
+            title: scenario.name.clone(),
+            color: scenario.color,
+        })
+        .collect();
+    let winner_render: Layers<Box<dyn Render + '_>> = Layers(vec![
+        Box::new(FnGrid {
+            viewport: Some(viewport),
+            cell_size,
+            color_fn: |point| match scenario::winner(&scenarios, point) {
+                Some(i) if !is_off_street(&field, point) => scenarios[i].color,
+                _ => [0.0, 0.0, 0.0, 0.0],
+            },
+            label_fn: |point| match scenario::winner(&scenarios, point) {
+                Some(i) if !is_off_street(&field, point) => {
+                    format!("{}", scenarios[i].minutes(point).unwrap() as usize)
+                }
+                _ => String::new(),
+            },
+        }),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+        Box::new(legend(String::from("Fastest Scenario"), legend_series).render()),
+    ]);
+
+    graphics::capture(
+        winner_render,
+        viewport,
+        PathBuf::from("output/winner.png"),
+        4096,
+    );
+
+    info!("Rendering accessibility...");
+    let max_access = access_cells
+        .iter()
+        .filter_map(|cell| cell.value)
+        .fold(1.0, f64::max);
+    let access_to_color = |count: f64| scale_chroma((count / max_access) as f32, n_zones as f32);
+    let access_render: Layers<Box<dyn Render + '_>> = Layers(vec![
+        Box::new(SampledGrid {
+            cells: access_cells,
+            color_fn: |count: &Option<f64>| count.map_or([0.0, 0.0, 0.0, 0.0], access_to_color),
+            label_fn: |count: &Option<f64>| {
+                count.map_or_else(String::new, |count| format!("{}", count.round()))
+            },
+        }),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms.clone()).unwrap()),
+        Box::new(scale_legend(access_title, n_zones, (0.0, max_access), access_to_color).render()),
+    ]);
+
+    graphics::capture(
+        access_render,
+        viewport,
+        PathBuf::from("output/access.png"),
+        4096,
+    );
+
+    info!("Rendering regions...");
+    let regions_render: Layers<Box<dyn Render + '_>> = Layers(vec![
+        Box::new(Choropleth {
+            areas: regions
+                .iter()
+                .zip(&region_stats)
+                .filter_map(|(region, stats)| {
+                    Some(ChoroplethArea {
+                        rings: region.outers.iter().chain(&region.holes).cloned().collect(),
+                        value: stats[2].mean,
+                        label: Text {
+                            text: match stats[2].mean {
+                                Some(minutes) => format!("{}: {:.1}", region.name, minutes),
+                                None => region.name.clone(),
+                            },
+                            location: region.label_location()?,
+                        },
                     })
-                    .collect(),
-                area: Box2DData::new(
-                    Point2DData::new(1500.0, -3000.0),
-                    Point2DData::new(3000.0, -1500.0),
-                ),
-            }
+                })
+                .collect(),
+            color_fn: |minutes: Option<f64>| {
+                minutes.map_or([0.0, 0.0, 0.0, 0.0], improvement_to_color)
+            },
+        }),
+        Box::new(Layer::from_projected(crs, osm_styled_geoms).unwrap()),
+        Box::new(
+            improvement_legend(&format!(
+                "Average Minutes Saved, {} vs. {}",
+                after.name, before.name
+            ))
             .render(),
         ),
     ]);
 
-    graphics::capture(render, viewport, PathBuf::from("output/map.png"), 4096);
+    graphics::capture(
+        regions_render,
+        viewport,
+        PathBuf::from("output/regions.png"),
+        4096,
+    );
 }
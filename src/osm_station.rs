@@ -0,0 +1,247 @@
+//! Stations, their entrances and the lines that stop at them, from OSM's public transport tags:
+//! https://wiki.openstreetmap.org/wiki/Public_transport
+//! Unlike the spreadsheet, OSM knows where the entrances are, so walking can end at the nearest
+//! one instead of the middle of the station.
+use crate::graphics::Point2DData;
+use crate::line::Line;
+use crate::spatial::{Located, PointIndex};
+use std::collections::{HashMap, HashSet};
+
+/// Entrances and stops that aren't in a stop area go with the nearest station, if it's this close
+const MAX_MATCH_METERS: f64 = 300.0;
+
+/// Nodes and ways are numbered separately, so the same number can be both
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OsmId {
+    Node(i64),
+    Way(i64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OsmStation {
+    pub id: OsmId,
+    pub name: String,
+    pub location_x_y: Point2DData,
+    pub entrances: Vec<Point2DData>,
+    /// Line IDs, in the order their routes were found
+    pub lines: Vec<String>,
+}
+
+fn tag_is(tags: &HashMap<String, String>, key: &str, values: &[&str]) -> bool {
+    matches!(tags.get(key), Some(value) if values.contains(&value.as_str()))
+}
+
+pub fn is_station(tags: &HashMap<String, String>) -> bool {
+    tag_is(tags, "railway", &["station", "halt"]) || tag_is(tags, "public_transport", &["station"])
+}
+
+pub fn is_entrance(tags: &HashMap<String, String>) -> bool {
+    tag_is(
+        tags,
+        "railway",
+        &["subway_entrance", "train_station_entrance"],
+    )
+}
+
+/// Where a vehicle stops or people wait, which routes list as their members
+pub fn is_stop(tags: &HashMap<String, String>) -> bool {
+    tag_is(tags, "public_transport", &["stop_position", "platform"])
+        || tag_is(tags, "railway", &["stop", "tram_stop", "platform"])
+}
+
+/// Which of our lines a route relation is, by its `ref` being a line's ID, e.g. "Red", or its
+/// `name` starting with a line's name, e.g. "Green Line B Branch"
+pub fn line_of<'a>(lines: &'a [Line], tags: &HashMap<String, String>) -> Option<&'a Line> {
+    if tags.get("type").map(String::as_str) != Some("route") {
+        return None;
+    }
+    lines.iter().find(|line| {
+        tags.get("ref") == Some(&line.id)
+            || matches!(tags.get("name"), Some(name) if name.starts_with(&line.name))
+    })
+}
+
+struct StationPoint {
+    i: usize,
+    location_x_y: Point2DData,
+}
+
+impl Located for StationPoint {
+    fn location(&self) -> Point2DData {
+        self.location_x_y
+    }
+}
+
+/// From tagged features, each with its ID and location, and relations, each with the IDs of its
+/// members. Ways, e.g. a station building or a platform, go at the middle of their outline.
+/// Stop areas (`public_transport=stop_area`) say which station an entrance or stop belongs to,
+/// and anything not in one goes with the nearest station. Routes give line membership, with
+/// `line_id` picking which routes are which lines, e.g. with `line_of`.
+pub fn extract_stations<'a, N, R, F>(features: N, relations: R, line_id: F) -> Vec<OsmStation>
+where
+    N: IntoIterator<Item = (OsmId, &'a HashMap<String, String>, Point2DData)>,
+    R: IntoIterator<Item = (&'a HashMap<String, String>, Vec<OsmId>)>,
+    F: Fn(&HashMap<String, String>) -> Option<String>,
+{
+    let features: Vec<(OsmId, &HashMap<String, String>, Point2DData)> =
+        features.into_iter().collect();
+    let relations: Vec<(&HashMap<String, String>, Vec<OsmId>)> = relations.into_iter().collect();
+
+    let mut stations: Vec<OsmStation> = features
+        .iter()
+        .filter(|(_, tags, _)| is_station(tags))
+        .map(|&(id, tags, location_x_y)| OsmStation {
+            id,
+            name: tags.get("name").cloned().unwrap_or_default(),
+            location_x_y,
+            entrances: vec![],
+            lines: vec![],
+        })
+        .collect();
+    let station_by_id: HashMap<OsmId, usize> = stations
+        .iter()
+        .enumerate()
+        .map(|(i, station)| (station.id, i))
+        .collect();
+    let index = PointIndex::new(
+        stations
+            .iter()
+            .enumerate()
+            .map(|(i, station)| StationPoint {
+                i,
+                location_x_y: station.location_x_y,
+            })
+            .collect(),
+    );
+
+    // Everything in a stop area goes with the area's station
+    let mut in_area: HashMap<OsmId, usize> = HashMap::new();
+    for (tags, members) in &relations {
+        if !tag_is(tags, "public_transport", &["stop_area"]) {
+            continue;
+        }
+        if let Some(&i) = members.iter().find_map(|id| station_by_id.get(id)) {
+            for &id in members {
+                in_area.entry(id).or_insert(i);
+            }
+        }
+    }
+    let location_by_id: HashMap<OsmId, Point2DData> = features
+        .iter()
+        .map(|&(id, _, location_x_y)| (id, location_x_y))
+        .collect();
+    let station_for = |id: OsmId| -> Option<usize> {
+        if let Some(&i) = station_by_id.get(&id).or_else(|| in_area.get(&id)) {
+            return Some(i);
+        }
+        let location_x_y = *location_by_id.get(&id)?;
+        index
+            .nearest(location_x_y)
+            .filter(|station| (station.location_x_y - location_x_y).length() <= MAX_MATCH_METERS)
+            .map(|station| station.i)
+    };
+
+    for &(id, tags, location_x_y) in &features {
+        if is_entrance(tags) {
+            if let Some(i) = station_for(id) {
+                stations[i].entrances.push(location_x_y);
+            }
+        }
+    }
+
+    let stops: HashSet<OsmId> = features
+        .iter()
+        .filter(|(_, tags, _)| is_stop(tags) || is_station(tags))
+        .map(|&(id, _, _)| id)
+        .collect();
+    for (tags, members) in &relations {
+        let id = match line_id(tags) {
+            Some(id) => id,
+            None => continue,
+        };
+        for &member in members {
+            if !stops.contains(&member) {
+                continue;
+            }
+            if let Some(i) = station_for(member) {
+                if !stations[i].lines.contains(&id) {
+                    stations[i].lines.push(id.clone());
+                }
+            }
+        }
+    }
+    stations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{green_line, tags};
+
+    #[test]
+    fn test_line_of() {
+        let lines = vec![green_line()];
+        let branch = tags(&[("type", "route"), ("name", "Green Line E Branch")]);
+        assert_eq!(line_of(&lines, &branch).unwrap().id, "Green");
+        let by_ref = tags(&[("type", "route"), ("ref", "Green")]);
+        assert_eq!(line_of(&lines, &by_ref).unwrap().id, "Green");
+        let area = tags(&[("type", "public_transport"), ("name", "Green Line Depot")]);
+        assert!(line_of(&lines, &area).is_none());
+    }
+
+    #[test]
+    fn test_extract_stations() {
+        let station = tags(&[("railway", "station"), ("name", "Union Square")]);
+        let entrance = tags(&[("railway", "subway_entrance")]);
+        let stop = tags(&[("public_transport", "stop_position")]);
+        let platform = tags(&[("railway", "platform")]);
+        let area = tags(&[("public_transport", "stop_area")]);
+        let route = tags(&[("type", "route"), ("ref", "Green")]);
+        let other_route = tags(&[("type", "route"), ("ref", "Orange")]);
+        let features = vec![
+            (OsmId::Node(1), &station, Point2DData::new(0.0, 0.0)),
+            // In the stop area, even though it's far away
+            (OsmId::Node(2), &entrance, Point2DData::new(0.0, 500.0)),
+            // Not in the stop area, but close
+            (OsmId::Node(3), &entrance, Point2DData::new(0.0, -100.0)),
+            // Too far to be this station's
+            (OsmId::Node(4), &entrance, Point2DData::new(0.0, -1000.0)),
+            (OsmId::Node(5), &stop, Point2DData::new(50.0, 0.0)),
+            // A platform drawn as a way, with the same number as the station node
+            (OsmId::Way(1), &platform, Point2DData::new(-50.0, 0.0)),
+        ];
+        let relations = vec![
+            (&area, vec![OsmId::Node(1), OsmId::Node(2)]),
+            (&route, vec![OsmId::Node(5), OsmId::Node(6)]),
+            (&other_route, vec![OsmId::Way(1)]),
+        ];
+        let stations = extract_stations(features, relations, |tags| tags.get("ref").cloned());
+
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].name, "Union Square");
+        assert_eq!(
+            stations[0].entrances,
+            vec![Point2DData::new(0.0, 500.0), Point2DData::new(0.0, -100.0)]
+        );
+        assert_eq!(stations[0].lines, vec!["Green", "Orange"]);
+    }
+
+    /// Stations mapped as buildings instead of points
+    #[test]
+    fn test_way_station() {
+        let station = tags(&[("railway", "station"), ("name", "Lechmere")]);
+        let entrance = tags(&[("railway", "subway_entrance")]);
+        let area = tags(&[("public_transport", "stop_area")]);
+        let features = vec![
+            (OsmId::Way(7), &station, Point2DData::new(100.0, 100.0)),
+            (OsmId::Node(7), &entrance, Point2DData::new(0.0, 800.0)),
+        ];
+        let relations = vec![(&area, vec![OsmId::Way(7), OsmId::Node(7)])];
+        let stations = extract_stations(features, relations, |_| None);
+
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].id, OsmId::Way(7));
+        assert_eq!(stations[0].location_x_y, Point2DData::new(100.0, 100.0));
+        assert_eq!(stations[0].entrances, vec![Point2DData::new(0.0, 800.0)]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::contour::{label_points, Raster};
 use crate::graphics::*;
 use std::path::PathBuf;
 
@@ -72,6 +73,60 @@ impl Legend {
     }
 }
 
+/// Filled bands between the thresholds of a raster, with a labeled line at each threshold
+pub struct Contours {
+    pub raster: Raster,
+    /// In increasing order
+    pub thresholds: Vec<f64>,
+    /// For the band from each threshold up to the next. The last band has no upper bound.
+    pub band_colors: Vec<[f32; 4]>,
+    pub line_color: [f32; 4],
+    /// In data space
+    pub line_width: f64,
+    /// How far apart the labels along each line are, in data space
+    pub label_spacing: f64,
+}
+
+impl Contours {
+    pub fn render(&self) -> impl Render {
+        let mut bands = vec![];
+        let mut lines = vec![];
+        let mut texts = vec![];
+        for (i, (&threshold, &color)) in self.thresholds.iter().zip(&self.band_colors).enumerate() {
+            let upper = self.thresholds.get(i + 1).cloned().unwrap_or(f64::INFINITY);
+            let rings = self.raster.isobands(threshold, upper);
+            if !rings.is_empty() {
+                bands.push(StyledGeom {
+                    geom: Geom::MultiPolygon(rings),
+                    color,
+                });
+            }
+            for points in self.raster.isolines(threshold) {
+                for location in label_points(&points, self.label_spacing) {
+                    texts.push(Text {
+                        text: format!("{}", threshold),
+                        location,
+                    });
+                }
+                lines.push(StyledGeom {
+                    geom: Geom::Lines {
+                        points,
+                        width: self.line_width,
+                    },
+                    color: self.line_color,
+                });
+            }
+        }
+
+        let x: Layers<Box<dyn Render>> = Layers(vec![
+            Box::new(Layer(bands)),
+            Box::new(Layer(lines)),
+            Box::new(Layer(texts)),
+        ]);
+        x
+    }
+}
+
 mod tests {
     use super::*;
 
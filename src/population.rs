@@ -0,0 +1,256 @@
+//! Where people live, for weighting travel times by how many people they matter to. Census
+//! blocks are best; building footprints from OSM are a rough stand-in where there's no census
+//! data at hand.
+use crate::graphics::{Box2DData, Point2DData};
+use crate::lat_lon_to_x_y;
+use crate::region::{self, GeoJsonError};
+use crate::spatial::{mean_point, polygon_area};
+use crate::table::{Columns, TableError};
+use geo_types::Point;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Some number of people at a place, e.g. a census block's centroid
+#[derive(Clone, Debug, PartialEq)]
+pub struct Residents {
+    pub location_x_y: Point2DData,
+    pub count: f64,
+    /// Empty if it's not known
+    pub neighborhood: String,
+}
+
+/// A CSV with `lat`, `lon` and `population` columns, and optionally `neighborhood`, e.g. census
+/// block centroids
+pub fn load_csv<R: Read>(reader: R, centroid: &Point<f64>) -> Result<Vec<Residents>, TableError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let columns = Columns::new(reader.headers()?);
+    let lat_column = columns.required("lat")?;
+    let lon_column = columns.required("lon")?;
+    let population_column = columns.required("population")?;
+    let neighborhood_column = columns.optional("neighborhood");
+
+    let mut residents = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let lat = columns.parse(&record, lat_column)?;
+        let lon = columns.parse(&record, lon_column)?;
+        residents.push(Residents {
+            location_x_y: lat_lon_to_x_y(centroid, (lat, lon)),
+            count: columns.parse(&record, population_column)?,
+            neighborhood: neighborhood_column
+                .map_or_else(String::new, |i| record[i].trim().to_string()),
+        });
+    }
+    Ok(residents)
+}
+
+/// Census blocks from a GeoJSON FeatureCollection, with their population in `population_property`
+/// as a number or a numeric string. Each block's people are spread over the `cell_size` grid
+/// cells it overlaps, in proportion to the overlap, and then added up per cell. That keeps a big
+/// block at the edge of town from counting as if everyone lived at its middle. The cell size has
+/// to be positive.
+pub fn load_geojson(
+    text: &str,
+    centroid: &Point<f64>,
+    population_property: &str,
+    cell_size: f64,
+) -> Result<Vec<Residents>, GeoJsonError> {
+    assert!(cell_size > 0.0, "bad cell size {}", cell_size);
+    let blocks = region::load_geojson_with(text, centroid, |properties| {
+        let population = match properties.get(population_property) {
+            Some(Value::Number(number)) => number.as_f64(),
+            Some(Value::String(number)) => number.trim().parse().ok(),
+            _ => None,
+        };
+        match population {
+            Some(population) if population.is_finite() => Ok((String::new(), population)),
+            _ => Err("missing the population property"),
+        }
+    })?;
+
+    let mut cells: HashMap<(i64, i64), f64> = HashMap::new();
+    let mut points = vec![];
+    for (block, population) in blocks {
+        if population <= 0.0 {
+            continue;
+        }
+        let bounds = match block.bounds() {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        let area = block.area();
+        if area <= 0.0 {
+            // Degenerate, so there's nothing to weight by
+            points.extend(
+                mean_point(&block.outers.concat()).map(|location_x_y| Residents {
+                    location_x_y,
+                    count: population,
+                    neighborhood: String::new(),
+                }),
+            );
+            continue;
+        }
+        let min_x = (bounds.min.x / cell_size).floor() as i64;
+        let min_y = (bounds.min.y / cell_size).floor() as i64;
+        let max_x = (bounds.max.x / cell_size).floor() as i64;
+        let max_y = (bounds.max.y / cell_size).floor() as i64;
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                let overlap = block.area_in(&cell_box(x, y, cell_size));
+                if overlap > 0.0 {
+                    *cells.entry((x, y)).or_default() += population * overlap / area;
+                }
+            }
+        }
+    }
+
+    let mut residents: Vec<Residents> = cells
+        .into_iter()
+        .map(|((x, y), count)| Residents {
+            location_x_y: cell_box(x, y, cell_size).center(),
+            count,
+            neighborhood: String::new(),
+        })
+        .collect();
+    residents.extend(points);
+    Ok(residents)
+}
+
+fn cell_box(x: i64, y: i64, cell_size: f64) -> Box2DData {
+    Box2DData::new(
+        Point2DData::new(x as f64 * cell_size, y as f64 * cell_size),
+        Point2DData::new((x + 1) as f64 * cell_size, (y + 1) as f64 * cell_size),
+    )
+}
+
+/// Every `building` way, with people in proportion to its footprint. Commercial and other
+/// non-residential buildings are left out when they're tagged as such.
+pub fn from_buildings<'a, I>(ways: I, people_per_square_meter: f64) -> Vec<Residents>
+where
+    I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<Point2DData>)>,
+{
+    ways.into_iter()
+        .filter(|(tags, points)| {
+            points.len() >= 4
+                && match tags.get("building").map(String::as_str) {
+                    None | Some("no") => false,
+                    Some(building) => !NON_RESIDENTIAL_BUILDINGS.contains(&building),
+                }
+        })
+        .filter_map(|(_, points)| {
+            Some(Residents {
+                location_x_y: mean_point(&points)?,
+                count: polygon_area(&points) * people_per_square_meter,
+                neighborhood: String::new(),
+            })
+        })
+        .collect()
+}
+
+const NON_RESIDENTIAL_BUILDINGS: &[&str] = &[
+    "commercial",
+    "industrial",
+    "retail",
+    "warehouse",
+    "office",
+    "garage",
+    "garages",
+    "parking",
+    "shed",
+    "church",
+    "school",
+    "university",
+    "hospital",
+    "train_station",
+    "transportation",
+    "roof",
+];
+
+/// Residents without a neighborhood get the nearest named one, e.g. from OSM's
+/// `place=neighbourhood` nodes
+pub fn assign_neighborhoods(residents: &mut [Residents], neighborhoods: &[(String, Point2DData)]) {
+    for resident in residents.iter_mut().filter(|r| r.neighborhood.is_empty()) {
+        if let Some((name, _)) = neighborhoods.iter().min_by(|(_, a), (_, b)| {
+            let a = (*a - resident.location_x_y).length();
+            let b = (*b - resident.location_x_y).length();
+            a.partial_cmp(&b).unwrap()
+        }) {
+            resident.neighborhood = name.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tags;
+
+    #[test]
+    fn test_load_csv() {
+        let centroid = Point::new(42.386755, -71.098472);
+        let csv = "lat,lon,population,neighborhood\n42.386755,-71.098472,120,Prospect Hill\n";
+        let residents = load_csv(csv.as_bytes(), &centroid).unwrap();
+        assert_eq!(residents[0].count, 120.0);
+        assert_eq!(residents[0].neighborhood, "Prospect Hill");
+        assert!(matches!(
+            load_csv("lat,lon\n42.38,-71.09\n".as_bytes(), &centroid),
+            Err(TableError::MissingColumn(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_geojson() {
+        // The first block is about 100 meters wide, centered on the edge between two 100 meter
+        // cells, and the second is inside the eastern one
+        let centroid = Point::new(42.38, -71.10);
+        let text = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"POP10": 90},
+             "geometry": {"type": "Polygon", "coordinates": [[[-71.1006, 42.3801],
+                 [-71.0994, 42.3801], [-71.0994, 42.3808], [-71.1006, 42.3808]]]}},
+            {"type": "Feature", "properties": {"POP10": "10"},
+             "geometry": {"type": "Polygon", "coordinates": [[[-71.0992, 42.3801],
+                 [-71.0990, 42.3801], [-71.0990, 42.3805], [-71.0992, 42.3805]]]}}
+        ]}"#;
+        let mut residents = load_geojson(text, &centroid, "POP10", 100.0).unwrap();
+        residents.sort_by(|a, b| a.location_x_y.x.partial_cmp(&b.location_x_y.x).unwrap());
+        assert_eq!(residents.len(), 2);
+        assert_eq!(residents[0].location_x_y, Point2DData::new(-50.0, -50.0));
+        assert_eq!(residents[1].location_x_y, Point2DData::new(50.0, -50.0));
+        assert!((residents[0].count - 45.0).abs() < 0.5);
+        assert!((residents[1].count - 55.0).abs() < 0.5);
+        assert!(matches!(
+            load_geojson(text, &centroid, "POP20", 100.0),
+            Err(GeoJsonError::Feature { feature: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_buildings() {
+        let house = tags(&[("building", "house")]);
+        let office = tags(&[("building", "office")]);
+        let square = |x: f64| {
+            vec![
+                Point2DData::new(x, 0.0),
+                Point2DData::new(x + 10.0, 0.0),
+                Point2DData::new(x + 10.0, 10.0),
+                Point2DData::new(x, 10.0),
+                Point2DData::new(x, 0.0),
+            ]
+        };
+        let mut residents =
+            from_buildings(vec![(&house, square(0.0)), (&office, square(100.0))], 0.02);
+        assert_eq!(residents.len(), 1);
+        assert_eq!(residents[0].count, 2.0);
+        assert_eq!(residents[0].location_x_y, Point2DData::new(5.0, 5.0));
+
+        assign_neighborhoods(
+            &mut residents,
+            &[
+                (String::from("Union Square"), Point2DData::new(0.0, 100.0)),
+                (String::from("Spring Hill"), Point2DData::new(0.0, -1000.0)),
+            ],
+        );
+        assert_eq!(residents[0].neighborhood, "Union Square");
+    }
+}
@@ -0,0 +1,228 @@
+//! Projections between WGS84 latitude/longitude and data space.
+//!
+//! Each projection comes with its inverse, so anything computed in data space (grid cells, best
+//! stations, labels) can be reported in real coordinates again.
+
+/// Mean radius of the Earth in meters. The forward and inverse projections must agree on this, or
+/// round trips drift by tens of meters across a city.
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// A projection from (lat, lon) in degrees to (x, y) in meters, paired with its inverse
+pub trait Projection {
+    fn project(&self, lat_lon: (f64, f64)) -> [f64; 2];
+
+    fn unproject(&self, x_y: [f64; 2]) -> (f64, f64);
+}
+
+/// Preserves the distance and bearing of every point from the centroid, which is what walking
+/// times care about. x points east and y points south, like screen coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AzimuthalEquidistant {
+    /// (lat, lon) in degrees
+    pub centroid: (f64, f64),
+}
+
+impl Projection for AzimuthalEquidistant {
+    fn project(&self, lat_lon: (f64, f64)) -> [f64; 2] {
+        let (lat_0, lon_0) = (self.centroid.0.to_radians(), self.centroid.1.to_radians());
+        let (lat, lon) = (lat_lon.0.to_radians(), lat_lon.1.to_radians());
+        let delta_lon = lon - lon_0;
+
+        // Haversine distance, see https://www.movable-type.co.uk/scripts/latlong.html
+        let a = ((lat - lat_0) / 2.0).sin().powi(2)
+            + lat_0.cos() * lat.cos() * (delta_lon / 2.0).sin().powi(2);
+        let distance = 2.0 * EARTH_RADIUS_METERS * a.sqrt().atan2((1.0 - a).sqrt());
+
+        // Initial bearing, clockwise from north
+        let bearing = (delta_lon.sin() * lat.cos())
+            .atan2(lat_0.cos() * lat.sin() - lat_0.sin() * lat.cos() * delta_lon.cos());
+
+        [distance * bearing.sin(), -distance * bearing.cos()]
+    }
+
+    fn unproject(&self, x_y: [f64; 2]) -> (f64, f64) {
+        let (lat_0, lon_0) = (self.centroid.0.to_radians(), self.centroid.1.to_radians());
+        let angular_distance = x_y[0].hypot(x_y[1]) / EARTH_RADIUS_METERS;
+        let bearing = x_y[0].atan2(-x_y[1]);
+
+        // Destination point given distance and bearing from the centroid
+        let lat = (lat_0.sin() * angular_distance.cos()
+            + lat_0.cos() * angular_distance.sin() * bearing.cos())
+        .asin();
+        let lon = lon_0
+            + (bearing.sin() * angular_distance.sin() * lat_0.cos())
+                .atan2(angular_distance.cos() - lat_0.sin() * lat.sin());
+
+        // Normalize to -180..180
+        let lon = (lon.to_degrees() + 540.0) % 360.0 - 180.0;
+        (lat.to_degrees(), lon)
+    }
+}
+
+/// A coordinate reference system for data space: which projection produced the coordinates, and
+/// where its origin is. Data space points are only comparable within the same `Crs`. Only
+/// `Layer::from_projected` checks this, so the other layers are trusted to use the centroid's CRS.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Crs {
+    AzimuthalEquidistant(AzimuthalEquidistant),
+}
+
+impl Crs {
+    /// The CRS that `lat_lon_to_x_y` uses for this centroid
+    pub fn centered_on(centroid: (f64, f64)) -> Self {
+        Crs::AzimuthalEquidistant(AzimuthalEquidistant { centroid })
+    }
+
+    /// Move a point from this CRS into another one by way of lat/lon
+    pub fn convert(&self, to: &Crs, x_y: [f64; 2]) -> [f64; 2] {
+        if self == to {
+            x_y
+        } else {
+            to.project(self.unproject(x_y))
+        }
+    }
+
+    /// Fail unless both CRSes are the same
+    pub fn check(&self, other: &Crs) -> Result<(), CrsMismatch> {
+        if self == other {
+            Ok(())
+        } else {
+            Err(CrsMismatch {
+                expected: *self,
+                found: *other,
+            })
+        }
+    }
+}
+
+impl Projection for Crs {
+    fn project(&self, lat_lon: (f64, f64)) -> [f64; 2] {
+        match self {
+            Crs::AzimuthalEquidistant(projection) => projection.project(lat_lon),
+        }
+    }
+
+    fn unproject(&self, x_y: [f64; 2]) -> (f64, f64) {
+        match self {
+            Crs::AzimuthalEquidistant(projection) => projection.unproject(x_y),
+        }
+    }
+}
+
+/// Geometry from two different CRSes was combined without converting it first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrsMismatch {
+    pub expected: Crs,
+    pub found: Crs,
+}
+
+impl std::fmt::Display for CrsMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "expected geometry in {:?} but found {:?}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for CrsMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Somerville city hall (93 Highland)
+    const CITY_HALL: (f64, f64) = (42.386755, -71.098472);
+
+    #[test]
+    fn test_centroid_is_origin() {
+        let projection = AzimuthalEquidistant {
+            centroid: CITY_HALL,
+        };
+        let x_y = projection.project(CITY_HALL);
+        assert!(x_y[0].abs() < 1e-9 && x_y[1].abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_orientation() {
+        let projection = AzimuthalEquidistant {
+            centroid: CITY_HALL,
+        };
+
+        // One degree of latitude is about 111 km
+        let north = projection.project((CITY_HALL.0 + 1.0, CITY_HALL.1));
+        assert!(north[0].abs() < 1e-6);
+        assert!((north[1] + 111_195.0).abs() < 10.0, "{:?}", north);
+
+        let east = projection.project((CITY_HALL.0, CITY_HALL.1 + 0.01));
+        assert!(east[0] > 0.0);
+    }
+
+    /// Data space -> lat/lon -> data space should be accurate to the centimeter within 50 km
+    #[test]
+    fn test_round_trip_x_y() {
+        let projection = AzimuthalEquidistant {
+            centroid: CITY_HALL,
+        };
+        for radius in &[0.01, 1.0, 100.0, 5_000.0, 20_000.0, 50_000.0] {
+            for i in 0..36 {
+                let angle = i as f64 * 10.0_f64.to_radians();
+                let x_y = [radius * angle.cos(), radius * angle.sin()];
+                let round_trip = projection.project(projection.unproject(x_y));
+                let error = (round_trip[0] - x_y[0]).hypot(round_trip[1] - x_y[1]);
+                assert!(error < 0.01, "{:?} came back as {:?}", x_y, round_trip);
+            }
+        }
+    }
+
+    #[test]
+    fn test_crs_convert() {
+        let city_hall = Crs::centered_on(CITY_HALL);
+        // Park Street
+        let park_street = Crs::centered_on((42.356395, -71.062424));
+
+        let x_y = city_hall.project(CITY_HALL);
+        let converted = city_hall.convert(&park_street, x_y);
+        assert!(converted[0] < -2_000.0, "City hall is west of Park Street");
+        assert!(converted[1] < -2_000.0, "City hall is north of Park Street");
+
+        let round_trip = park_street.convert(&city_hall, converted);
+        assert!((round_trip[0] - x_y[0]).hypot(round_trip[1] - x_y[1]) < 0.01);
+    }
+
+    #[test]
+    fn test_crs_check() {
+        let city_hall = Crs::centered_on(CITY_HALL);
+        assert_eq!(city_hall.check(&Crs::centered_on(CITY_HALL)), Ok(()));
+        assert!(city_hall.check(&Crs::centered_on((0.0, 0.0))).is_err());
+    }
+
+    /// Lat/lon -> data space -> lat/lon should be accurate to the centimeter within 50 km
+    #[test]
+    fn test_round_trip_lat_lon() {
+        let projection = AzimuthalEquidistant {
+            centroid: CITY_HALL,
+        };
+        // About 0.45 degrees of latitude and 0.6 degrees of longitude is 50 km here
+        for i in -10..=10 {
+            for j in -10..=10 {
+                let lat_lon = (
+                    CITY_HALL.0 + i as f64 * 0.045,
+                    CITY_HALL.1 + j as f64 * 0.045,
+                );
+                let round_trip = projection.unproject(projection.project(lat_lon));
+                let meters_lat = (round_trip.0 - lat_lon.0).to_radians() * EARTH_RADIUS_METERS;
+                let meters_lon = (round_trip.1 - lat_lon.1).to_radians()
+                    * EARTH_RADIUS_METERS
+                    * lat_lon.0.to_radians().cos();
+                assert!(
+                    meters_lat.hypot(meters_lon) < 0.01,
+                    "{:?} came back as {:?}",
+                    lat_lon,
+                    round_trip
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Proposed lines, like the Green Line Extension, which don't have a published schedule yet.
+//! These generate the trips that a schedule would have, so that they can be merged into a real
+//! feed and routed over like everything else.
+use crate::gtfs::{
+    Calendar, Date, Feed, Frequency, Route, Seconds, Stop, StopTime, Transfer, Trip,
+};
+use crate::projection::{AzimuthalEquidistant, Projection};
+
+/// When the line runs if the feed doesn't have any dates of its own to go by
+const ANY_DATE: (Date, Date) = (20000101, 20991231);
+
+/// A station on a proposed line. If the feed already has a stop with this ID, e.g. where the new
+/// line branches off, the line uses that stop instead of adding a new one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProposedStation {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl ProposedStation {
+    pub fn from_stop(stop: &Stop) -> Self {
+        ProposedStation {
+            id: stop.id.clone(),
+            name: stop.name.clone(),
+            lat: stop.lat,
+            lon: stop.lon,
+        }
+    }
+
+    /// Meters as the crow flies
+    fn distance(&self, other: &ProposedStation) -> f64 {
+        let x_y = AzimuthalEquidistant {
+            centroid: (self.lat, self.lon),
+        }
+        .project((other.lat, other.lon));
+        x_y[0].hypot(x_y[1])
+    }
+}
+
+/// A line that runs both ways between its stations at a constant headway
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProposedLine {
+    pub route_id: String,
+    pub name: String,
+    /// Same as `Route::route_type`, e.g. 0 for light rail
+    pub route_type: u16,
+    pub color: Option<String>,
+    /// In order from one end of the line to the other
+    pub stations: Vec<ProposedStation>,
+    /// Average speed between stations, including speeding up and slowing down. Distances are as
+    /// the crow flies, so this should also account for the track not being straight.
+    pub meters_per_second: f64,
+    /// How long trains wait at each station
+    pub dwell: Seconds,
+    pub headway: Seconds,
+    /// When the first trains leave the ends of the line
+    pub start: Seconds,
+    /// When the last trains have left
+    pub end: Seconds,
+    /// An existing route whose trips end at the first station, e.g. the E branch at Lechmere,
+    /// that runs on along this line instead, so riders don't have to change trains. The line
+    /// then has no trips of its own, and the headway, start and end are unused.
+    pub through_route_id: Option<String>,
+}
+
+impl ProposedLine {
+    /// The stop times of one trip in one direction, leaving the first station at 0
+    fn stop_times(&self, stations: &[&ProposedStation]) -> Vec<StopTime> {
+        let mut time = 0.0;
+        let mut stop_times = vec![];
+        for (i, station) in stations.iter().enumerate() {
+            if i > 0 {
+                time += station.distance(stations[i - 1]) / self.meters_per_second;
+            }
+            let arrival = time.round() as Seconds;
+            let departure = if i == 0 || i == stations.len() - 1 {
+                arrival
+            } else {
+                time += f64::from(self.dwell);
+                time.round() as Seconds
+            };
+            stop_times.push(StopTime {
+                stop_id: station.id.clone(),
+                arrival,
+                departure,
+                stop_sequence: i as u32,
+            });
+        }
+        stop_times
+    }
+
+    /// Add the route, its new stops, and trips in both directions to the feed. The line runs
+    /// every day that the feed covers. Where the first station already exists, riders change
+    /// trains across the platform, so there's no time to walk between them. A line without any
+    /// stations adds nothing.
+    pub fn add_to(&self, feed: &mut Feed) {
+        let first_id = match self.stations.first() {
+            Some(first) => &first.id,
+            None => return,
+        };
+        let platforms: Vec<String> = feed
+            .stops
+            .values()
+            .filter(|stop| stop.parent_station.as_ref() == Some(first_id))
+            .map(|stop| stop.id.clone())
+            .collect();
+        for platform in platforms {
+            for (from, to) in &[(&platform, first_id), (first_id, &platform)] {
+                // From another line that starts here
+                let exists = feed
+                    .transfers
+                    .iter()
+                    .any(|transfer| transfer.from_stop_id == **from && transfer.to_stop_id == **to);
+                if exists {
+                    continue;
+                }
+                feed.transfers.push(Transfer {
+                    from_stop_id: from.to_string(),
+                    to_stop_id: to.to_string(),
+                    transfer_type: 2,
+                    min_transfer_time: Some(0),
+                });
+            }
+        }
+        for station in &self.stations {
+            feed.stops
+                .entry(station.id.clone())
+                .or_insert_with(|| Stop {
+                    id: station.id.clone(),
+                    name: station.name.clone(),
+                    lat: station.lat,
+                    lon: station.lon,
+                    location_type: 0,
+                    parent_station: None,
+                });
+        }
+
+        if let Some(through_route_id) = &self.through_route_id {
+            self.extend_trips(feed, through_route_id);
+            return;
+        }
+
+        feed.routes.insert(
+            self.route_id.clone(),
+            Route {
+                id: self.route_id.clone(),
+                short_name: self.name.clone(),
+                long_name: self.name.clone(),
+                route_type: self.route_type,
+                color: self.color.clone(),
+            },
+        );
+
+        let (start_date, end_date) = feed.date_range().unwrap_or(ANY_DATE);
+        let service_id = format!("{}-service", self.route_id);
+        feed.calendars.insert(
+            service_id.clone(),
+            Calendar {
+                service_id: service_id.clone(),
+                days: [true; 7],
+                start_date,
+                end_date,
+            },
+        );
+
+        let forward: Vec<&ProposedStation> = self.stations.iter().collect();
+        let backward: Vec<&ProposedStation> = self.stations.iter().rev().collect();
+        for (direction_id, stations) in [forward, backward].iter().enumerate() {
+            let trip_id = format!("{}-{}", self.route_id, direction_id);
+            let stop_times: Vec<StopTime> = self
+                .stop_times(stations)
+                .into_iter()
+                .map(|stop_time| StopTime {
+                    arrival: stop_time.arrival + self.start,
+                    departure: stop_time.departure + self.start,
+                    ..stop_time
+                })
+                .collect();
+            feed.trips.insert(
+                trip_id.clone(),
+                Trip {
+                    id: trip_id.clone(),
+                    route_id: self.route_id.clone(),
+                    service_id: service_id.clone(),
+                    direction_id: Some(direction_id as u8),
+                    stop_times,
+                },
+            );
+            feed.frequencies.push(Frequency {
+                trip_id,
+                start: self.start,
+                end: self.end,
+                headway: self.headway,
+            });
+        }
+    }
+
+    /// Run the route's trips that end at the first station on to the end of the line, after a
+    /// dwell, and start the ones that leave from there at the end of the line instead
+    fn extend_trips(&self, feed: &mut Feed, route_id: &str) {
+        let first_id = self.stations[0].id.clone();
+        let outbound = self.stop_times(&self.stations.iter().collect::<Vec<_>>());
+        let inbound = self.stop_times(&self.stations.iter().rev().collect::<Vec<_>>());
+        let at_first = |stop_time: Option<&StopTime>, feed: &Feed| {
+            stop_time
+                .filter(|stop_time| feed.station_id(&stop_time.stop_id) == first_id)
+                .is_some()
+        };
+
+        let trip_ids: Vec<String> = feed
+            .trips
+            .values()
+            .filter(|trip| trip.route_id == route_id)
+            .map(|trip| trip.id.clone())
+            .collect();
+        for trip_id in trip_ids {
+            let mut stop_times = feed.trips[&trip_id].stop_times.clone();
+            if at_first(stop_times.last(), feed) {
+                let last = stop_times.last_mut().unwrap();
+                last.departure = last.arrival + self.dwell;
+                let leave = last.departure;
+                stop_times.extend(outbound.iter().skip(1).map(|stop_time| StopTime {
+                    arrival: stop_time.arrival + leave,
+                    departure: stop_time.departure + leave,
+                    ..stop_time.clone()
+                }));
+            } else if at_first(stop_times.first(), feed) {
+                let (to_first, first_station) = inbound.split_at(inbound.len() - 1);
+                let first = &mut stop_times[0];
+                first.arrival = first.departure.saturating_sub(self.dwell);
+                let start = first.arrival.saturating_sub(first_station[0].arrival);
+                let mut extended: Vec<StopTime> = to_first
+                    .iter()
+                    .map(|stop_time| StopTime {
+                        arrival: stop_time.arrival + start,
+                        departure: stop_time.departure + start,
+                        ..stop_time.clone()
+                    })
+                    .collect();
+                extended.append(&mut stop_times);
+                stop_times = extended;
+            } else {
+                continue;
+            }
+            for (i, stop_time) in stop_times.iter_mut().enumerate() {
+                stop_time.stop_sequence = i as u32;
+            }
+            feed.trips.get_mut(&trip_id).unwrap().stop_times = stop_times;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transit::Timetable;
+
+    /// Three stations about a kilometer apart, heading north from an existing station
+    fn example_line() -> ProposedLine {
+        let station = |id: &str, lat: f64| ProposedStation {
+            id: id.to_string(),
+            name: id.to_string(),
+            lat,
+            lon: -71.08,
+        };
+        ProposedLine {
+            route_id: String::from("Green-GLX"),
+            name: String::from("GLX"),
+            route_type: 0,
+            color: Some(String::from("00843D")),
+            stations: vec![
+                station("place-lech", 42.37),
+                station("glx-1", 42.38),
+                station("glx-2", 42.39),
+            ],
+            meters_per_second: 10.0,
+            dwell: 30,
+            headway: 600,
+            start: 5 * 3600,
+            end: 6 * 3600,
+            through_route_id: None,
+        }
+    }
+
+    #[test]
+    fn test_stop_times() {
+        let line = example_line();
+        let stations: Vec<&ProposedStation> = line.stations.iter().collect();
+        let stop_times = line.stop_times(&stations);
+
+        // 0.01 degrees of latitude is about 1112 meters, so 111 seconds at 10 m/s
+        let times: Vec<(Seconds, Seconds)> = stop_times
+            .iter()
+            .map(|stop_time| (stop_time.arrival, stop_time.departure))
+            .collect();
+        assert_eq!(times, vec![(0, 0), (111, 141), (252, 252)]);
+    }
+
+    #[test]
+    fn test_add_to_feed() {
+        let mut feed = Feed::default();
+        feed.stops.insert(
+            String::from("place-lech"),
+            Stop {
+                id: String::from("place-lech"),
+                name: String::from("Lechmere"),
+                lat: 42.370772,
+                lon: -71.076536,
+                location_type: 1,
+                parent_station: None,
+            },
+        );
+        feed.calendars.insert(
+            String::from("weekday"),
+            Calendar {
+                service_id: String::from("weekday"),
+                days: [true, true, true, true, true, false, false],
+                start_date: 20190901,
+                end_date: 20191231,
+            },
+        );
+        example_line().add_to(&mut feed);
+
+        // Running over the feed's dates
+        assert_eq!(feed.date_range(), Some((20190901, 20191231)));
+        assert_eq!(feed.typical_weekday(), Some(20190902));
+
+        // The existing station is kept as is
+        assert_eq!(feed.stops.len(), 3);
+        assert_eq!(feed.stops["place-lech"].location_type, 1);
+        assert_eq!(feed.trips.len(), 2);
+        assert_eq!(
+            feed.trips["Green-GLX-1"].stop_times[0].stop_id,
+            String::from("glx-2")
+        );
+
+        // Six runs an hour each way, two connections each
+        let timetable = Timetable::from_feed(&feed, 20191104, &[0]);
+        assert_eq!(timetable.connections.len(), 2 * 6 * 2);
+        let lechmere = timetable.stop("place-lech").unwrap();
+        let end = timetable.stop("glx-2").unwrap();
+        assert_eq!(
+            timetable.earliest_arrival(&[(lechmere, 5 * 3600 + 1)], &[(end, 0)]),
+            Some(5 * 3600 + 600 + 252)
+        );
+    }
+
+    #[test]
+    fn test_through_running() {
+        let mut feed = Feed::default();
+        let stop = |id: &str, parent_station: Option<&str>| Stop {
+            id: id.to_string(),
+            name: id.to_string(),
+            lat: 42.37,
+            lon: -71.08,
+            location_type: if parent_station.is_some() { 0 } else { 1 },
+            parent_station: parent_station.map(String::from),
+        };
+        feed.stops
+            .insert(String::from("place-lech"), stop("place-lech", None));
+        feed.stops
+            .insert(String::from("70501"), stop("70501", Some("place-lech")));
+        feed.stops.insert(String::from("park"), stop("park", None));
+        let trip = |id: &str, stop_ids: [&str; 2]| Trip {
+            id: id.to_string(),
+            route_id: String::from("Green-E"),
+            service_id: String::from("weekday"),
+            direction_id: None,
+            stop_times: vec![
+                StopTime {
+                    stop_id: stop_ids[0].to_string(),
+                    arrival: 8 * 3600,
+                    departure: 8 * 3600,
+                    stop_sequence: 1,
+                },
+                StopTime {
+                    stop_id: stop_ids[1].to_string(),
+                    arrival: 8 * 3600 + 600,
+                    departure: 8 * 3600 + 600,
+                    stop_sequence: 2,
+                },
+            ],
+        };
+        feed.trips
+            .insert(String::from("out"), trip("out", ["park", "70501"]));
+        feed.trips
+            .insert(String::from("in"), trip("in", ["70501", "park"]));
+
+        let line = ProposedLine {
+            through_route_id: Some(String::from("Green-E")),
+            ..example_line()
+        };
+        line.add_to(&mut feed);
+
+        // The same trips run on, with no new ones
+        assert_eq!(feed.trips.len(), 2);
+        assert!(!feed.routes.contains_key("Green-GLX"));
+        // Relative to 8 AM, which the inbound trip now starts before
+        let stops = |trip_id: &str| -> Vec<(String, i64, i64)> {
+            feed.trips[trip_id]
+                .stop_times
+                .iter()
+                .map(|stop_time| {
+                    (
+                        stop_time.stop_id.clone(),
+                        i64::from(stop_time.arrival) - 8 * 3600,
+                        i64::from(stop_time.departure) - 8 * 3600,
+                    )
+                })
+                .collect()
+        };
+        let s = |id: &str| id.to_string();
+        assert_eq!(
+            stops("out"),
+            vec![
+                (s("park"), 0, 0),
+                (s("70501"), 600, 630),
+                (s("glx-1"), 741, 771),
+                (s("glx-2"), 882, 882),
+            ]
+        );
+        assert_eq!(
+            stops("in"),
+            vec![
+                (s("glx-2"), -282, -282),
+                (s("glx-1"), -171, -141),
+                (s("70501"), -30, 0),
+                (s("park"), 600, 600),
+            ]
+        );
+
+        // Changing trains at the shared station takes no time
+        assert_eq!(feed.transfers.len(), 2);
+        assert!(feed
+            .transfers
+            .iter()
+            .all(|transfer| transfer.min_transfer_time == Some(0)));
+        // Not again for another line from the same station
+        example_line().add_to(&mut feed);
+        assert_eq!(feed.transfers.len(), 2);
+
+        let before = feed.trips.len();
+        ProposedLine {
+            stations: vec![],
+            ..example_line()
+        }
+        .add_to(&mut feed);
+        assert_eq!(feed.trips.len(), before);
+    }
+}
@@ -9,7 +9,9 @@
 //! File format protobuf: https://github.com/scrosby/OSM-binary/blob/master/src/fileformat.proto
 //! OSM format protobuf: https://github.com/scrosby/OSM-binary/blob/master/src/osmformat.proto
 use crate::protos::fileformat::{Blob, BlobHeader};
-use crate::protos::osmformat::{DenseNodes, HeaderBlock, Node, PrimitiveBlock, Way};
+use crate::protos::osmformat::{
+    DenseNodes, HeaderBlock, Node, PrimitiveBlock, Relation, Relation_MemberType, Way,
+};
 use byteorder::{BigEndian, ReadBytesExt};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
@@ -211,6 +213,51 @@ pub fn as_vec_dense_nodes(dense_nodes: &DenseNodes) -> Vec<DenseNode> {
         .collect()
 }
 
+/// A node with tags of its own, like a shop or a bus stop
+pub struct MyTaggedNode {
+    pub node: DenseNode,
+    pub tags: HashMap<String, String>,
+}
+
+/// Only the nodes that have tags. Most nodes are just points along ways, so this is much smaller
+/// than all of the nodes.
+pub fn into_vec_tagged_nodes(primitive_block: &PrimitiveBlock) -> Vec<MyTaggedNode> {
+    let strings: Vec<String> = primitive_block
+        .get_stringtable()
+        .get_s()
+        .iter()
+        .map(|bytes| String::from_utf8(bytes.clone()).unwrap())
+        .collect();
+    let strings = &strings;
+    iter_dense_nodeses(primitive_block)
+        .flat_map(|dense_nodes| {
+            // Keys and values alternate, and each node's tags end with a 0. If no node in the
+            // block has tags, this is empty.
+            let mut keys_vals = dense_nodes.get_keys_vals().iter();
+            as_vec_dense_nodes(dense_nodes)
+                .into_iter()
+                .filter_map(move |node| {
+                    let mut tags = HashMap::new();
+                    while let Some(&key) = keys_vals.next() {
+                        if key == 0 {
+                            break;
+                        }
+                        let value = *keys_vals.next().unwrap();
+                        tags.insert(
+                            strings[usize::try_from(key).unwrap()].clone(),
+                            strings[usize::try_from(value).unwrap()].clone(),
+                        );
+                    }
+                    if tags.is_empty() {
+                        None
+                    } else {
+                        Some(MyTaggedNode { node, tags })
+                    }
+                })
+        })
+        .collect()
+}
+
 pub fn iter_ways(primitive_block: &PrimitiveBlock) -> impl Iterator<Item = &Way> {
     primitive_block
         .get_primitivegroup()
@@ -252,6 +299,69 @@ pub fn into_vec_ways(mut primitive_block: PrimitiveBlock) -> Vec<MyWay> {
         .collect()
 }
 
+pub fn iter_relations(primitive_block: &PrimitiveBlock) -> impl Iterator<Item = &Relation> {
+    primitive_block
+        .get_primitivegroup()
+        .iter()
+        .flat_map(|group| group.get_relations().iter())
+}
+
+/// One member of a relation, e.g. a stop on a route with the role "stop"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub id: i64,
+    pub member_type: Relation_MemberType,
+    pub role: String,
+}
+
+/// A relation, like a subway route or a station's stop area, with its members in order
+pub struct MyRelation {
+    pub id: i64,
+    pub tags: HashMap<String, String>,
+    pub members: Vec<Member>,
+}
+
+pub fn into_vec_relations(primitive_block: &PrimitiveBlock) -> Vec<MyRelation> {
+    let strings: Vec<String> = primitive_block
+        .get_stringtable()
+        .get_s()
+        .iter()
+        .map(|bytes| String::from_utf8(bytes.clone()).unwrap())
+        .collect();
+    let string = |i: u32| strings[usize::try_from(i).unwrap()].clone();
+    iter_relations(primitive_block)
+        .map(|relation| {
+            let tags = relation
+                .get_keys()
+                .iter()
+                .zip(relation.get_vals())
+                .map(|(&key, &value)| (string(key), string(value)))
+                .collect();
+            // Member IDs are delta coded, like way refs
+            let mut id = 0;
+            let members = relation
+                .get_memids()
+                .iter()
+                .zip(relation.get_types())
+                .zip(relation.get_roles_sid())
+                .map(|((&memid, member_type), &role)| {
+                    id += memid;
+                    Member {
+                        id,
+                        member_type: member_type.clone(),
+                        role: string(u32::try_from(role).unwrap()),
+                    }
+                })
+                .collect();
+            MyRelation {
+                id: relation.get_id(),
+                tags,
+                members,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -299,4 +409,43 @@ mod tests {
                 > 0
         );
     }
+
+    #[test]
+    fn test_tagged_nodes() {
+        let vec_blob: Vec<BlobData> = read_blobs(get_reader()).collect();
+
+        let tagged_nodes: Vec<MyTaggedNode> = vec_blob
+            .par_iter()
+            .flat_map(|blob_data| {
+                if let FileBlock::Primitive(primitive_block) = blob_data.deserialize() {
+                    into_vec_tagged_nodes(&primitive_block)
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        assert!(!tagged_nodes.is_empty());
+        assert!(tagged_nodes.iter().all(|node| !node.tags.is_empty()));
+    }
+
+    #[test]
+    fn test_relations() {
+        let vec_blob: Vec<BlobData> = read_blobs(get_reader()).collect();
+
+        let relations: Vec<MyRelation> = vec_blob
+            .par_iter()
+            .flat_map(|blob_data| {
+                if let FileBlock::Primitive(primitive_block) = blob_data.deserialize() {
+                    into_vec_relations(&primitive_block)
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        assert!(!relations.is_empty());
+        assert!(relations.iter().any(|relation| relation
+            .members
+            .iter()
+            .any(|member| !member.role.is_empty())));
+    }
 }
@@ -0,0 +1,483 @@
+//! Results summed up over areas like wards, neighborhoods or cities, instead of square grid cells,
+//! to answer questions like "which ward gains the most from the GLX".
+use crate::clip::clip_polygon;
+use crate::graphics::{Box2DData, Point2DData};
+use crate::lat_lon_to_x_y;
+use crate::spatial::{bounds, mean_point, polygon_area, polygon_contains};
+use geo_types::Point;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+/// An area with a name. Like `Geom::Polygon`, rings don't repeat their first point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    /// Several for e.g. a city with islands
+    pub outers: Vec<Vec<Point2DData>>,
+    pub holes: Vec<Vec<Point2DData>>,
+}
+
+impl Region {
+    /// None if the region has no rings at all
+    pub fn bounds(&self) -> Option<Box2DData> {
+        bounds(&self.outers.concat())
+    }
+
+    pub fn contains(&self, point: Point2DData) -> bool {
+        self.outers.iter().any(|ring| polygon_contains(ring, point))
+            && !self.holes.iter().any(|ring| polygon_contains(ring, point))
+    }
+
+    /// How much of the box is inside the region, in square meters
+    pub fn area_in(&self, box2d: &Box2DData) -> f64 {
+        let area = |rings: &[Vec<Point2DData>]| -> f64 {
+            rings
+                .iter()
+                .filter_map(|ring| clip_polygon(ring, box2d))
+                .map(|clipped| polygon_area(&clipped))
+                .sum()
+        };
+        (area(&self.outers) - area(&self.holes)).max(0.0)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.bounds().map_or(0.0, |bounds| self.area_in(&bounds))
+    }
+
+    /// Where to put the name on a map: the average of the largest ring's points, which is close
+    /// enough to the middle for the compact shapes that wards and neighborhoods tend to have. None
+    /// if the region has no rings at all.
+    pub fn label_location(&self) -> Option<Point2DData> {
+        let largest = self.outers.iter().max_by(|a, b| {
+            polygon_area(a)
+                .partial_cmp(&polygon_area(b))
+                .unwrap_or(Ordering::Equal)
+        })?;
+        mean_point(largest)
+    }
+}
+
+fn drop_closing_point(mut ring: Vec<Point2DData>) -> Vec<Point2DData> {
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    ring
+}
+
+/// Ways joined end to end into closed rings, the way OSM multipolygons and boundaries are drawn.
+/// Ways that don't close up, e.g. because part of the boundary wasn't loaded, are left out.
+pub fn assemble_rings(ways: Vec<Vec<Point2DData>>) -> Vec<Vec<Point2DData>> {
+    let mut ways: Vec<Vec<Point2DData>> = ways.into_iter().filter(|way| way.len() >= 2).collect();
+    let mut rings = vec![];
+    while let Some(mut ring) = ways.pop() {
+        while ring.first() != ring.last() {
+            let end = *ring.last().unwrap();
+            let next = ways
+                .iter()
+                .position(|way| way.first() == Some(&end) || way.last() == Some(&end));
+            match next {
+                Some(i) => {
+                    let mut way = ways.swap_remove(i);
+                    if way.first() != Some(&end) {
+                        way.reverse();
+                    }
+                    ring.extend(way.into_iter().skip(1));
+                }
+                None => break,
+            }
+        }
+        if ring.len() >= 4 && ring.first() == ring.last() {
+            rings.push(drop_closing_point(ring));
+        }
+    }
+    rings
+}
+
+/// From OSM relations, e.g. `boundary=administrative`, with their member ways' roles and points.
+/// The name comes from the `name` tag.
+pub fn from_osm<'a, I, P>(relations: I, select: P) -> Vec<Region>
+where
+    I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<(String, Vec<Point2DData>)>)>,
+    P: Fn(&HashMap<String, String>) -> bool,
+{
+    relations
+        .into_iter()
+        .filter(|(tags, _)| select(tags))
+        .filter_map(|(tags, members)| {
+            let (inners, outers): (Vec<_>, Vec<_>) =
+                members.into_iter().partition(|(role, _)| role == "inner");
+            let region = Region {
+                name: tags.get("name").cloned().unwrap_or_default(),
+                outers: assemble_rings(outers.into_iter().map(|(_, points)| points).collect()),
+                holes: assemble_rings(inners.into_iter().map(|(_, points)| points).collect()),
+            };
+            if region.outers.is_empty() {
+                None
+            } else {
+                Some(region)
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum GeoJsonError {
+    Json(serde_json::Error),
+    /// The feature number is 0-based, like in the file's `features` array
+    Feature {
+        feature: usize,
+        message: &'static str,
+    },
+}
+
+impl fmt::Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GeoJsonError::Json(error) => write!(f, "{}", error),
+            GeoJsonError::Feature { feature, message } => {
+                write!(f, "feature {}: {}", feature, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GeoJsonError {}
+
+impl From<serde_json::Error> for GeoJsonError {
+    fn from(error: serde_json::Error) -> Self {
+        GeoJsonError::Json(error)
+    }
+}
+
+/// Polygon and MultiPolygon features from a GeoJSON FeatureCollection in longitude and latitude,
+/// named by one of their properties, e.g. "WARD". Other geometry types are skipped, and so are
+/// polygons without an outer ring: https://tools.ietf.org/html/rfc7946
+pub fn load_geojson(
+    text: &str,
+    centroid: &Point<f64>,
+    name_property: &str,
+) -> Result<Vec<Region>, GeoJsonError> {
+    load_geojson_with(text, centroid, |properties| {
+        let name = match properties.get(name_property) {
+            Some(Value::String(name)) => name.clone(),
+            Some(Value::Number(number)) => number.to_string(),
+            _ => return Err("missing the name property"),
+        };
+        Ok((name, ()))
+    })
+    .map(|features| features.into_iter().map(|(region, ())| region).collect())
+}
+
+/// Like `load_geojson`, for features that carry more than a name, e.g. census blocks with their
+/// population. `properties` gets each feature's `properties` object and returns its name and
+/// whatever else is needed from it.
+pub fn load_geojson_with<T, F>(
+    text: &str,
+    centroid: &Point<f64>,
+    properties: F,
+) -> Result<Vec<(Region, T)>, GeoJsonError>
+where
+    F: Fn(&Value) -> Result<(String, T), &'static str>,
+{
+    let json: Value = serde_json::from_str(text)?;
+    let features = json["features"].as_array().ok_or(GeoJsonError::Feature {
+        feature: 0,
+        message: "expected a FeatureCollection",
+    })?;
+
+    let mut regions = vec![];
+    for (i, feature) in features.iter().enumerate() {
+        let error = |message| GeoJsonError::Feature {
+            feature: i,
+            message,
+        };
+        let (name, value) = properties(&feature["properties"]).map_err(error)?;
+        // Features without a location have a null geometry
+        let geometry = &feature["geometry"];
+        let coordinates = &geometry["coordinates"];
+        let polygons: Vec<&Value> = match geometry["type"].as_str() {
+            Some("Polygon") => vec![coordinates],
+            Some("MultiPolygon") => coordinates
+                .as_array()
+                .ok_or_else(|| error("bad coordinates"))?
+                .iter()
+                .collect(),
+            _ => continue,
+        };
+
+        let ring = |ring: &Value| -> Option<Vec<Point2DData>> {
+            ring.as_array()?
+                .iter()
+                .map(|position| match position.as_array()?.as_slice() {
+                    // Sometimes with an elevation after
+                    [lon, lat, ..] => {
+                        Some(lat_lon_to_x_y(centroid, (lat.as_f64()?, lon.as_f64()?)))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(drop_closing_point)
+        };
+        let mut region = Region {
+            name,
+            outers: vec![],
+            holes: vec![],
+        };
+        for polygon in polygons {
+            // The first ring is the outside, and the rest are holes
+            let rings = polygon
+                .as_array()
+                .ok_or_else(|| error("bad polygon"))?
+                .iter()
+                .map(ring)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| error("bad ring"))?;
+            let mut rings = rings.into_iter();
+            match rings.next() {
+                Some(outer) if outer.len() >= 3 => {
+                    region.outers.push(outer);
+                    region.holes.extend(rings.filter(|hole| hole.len() >= 3));
+                }
+                _ => {}
+            }
+        }
+        if !region.outers.is_empty() {
+            regions.push((region, value));
+        }
+    }
+    Ok(regions)
+}
+
+/// A field summed up over a region, weighting each grid cell by how much of it is in the region
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionStats {
+    /// Where the field has a value, in square meters
+    pub area: f64,
+    /// None if the field has no value anywhere in the region
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Samples the field at the center of each grid cell, on the same grid as `FnGrid`. The cell size
+/// has to be positive.
+pub fn aggregate<F: Fn(Point2DData) -> Option<f64>>(
+    region: &Region,
+    cell_size: f64,
+    field: F,
+) -> RegionStats {
+    assert!(cell_size > 0.0, "bad cell size {}", cell_size);
+    let region_bounds = match region.bounds() {
+        Some(region_bounds) => region_bounds,
+        None => {
+            return RegionStats {
+                area: 0.0,
+                mean: None,
+                min: None,
+                max: None,
+            }
+        }
+    };
+    let min_x = (region_bounds.min.x / cell_size).floor() as isize;
+    let min_y = (region_bounds.min.y / cell_size).floor() as isize;
+    let max_x = (region_bounds.max.x / cell_size).floor() as isize;
+    let max_y = (region_bounds.max.y / cell_size).floor() as isize;
+
+    let mut area = 0.0;
+    let mut total = 0.0;
+    let mut min: Option<f64> = None;
+    let mut max: Option<f64> = None;
+    for x in min_x..=max_x {
+        for y in min_y..=max_y {
+            let cell = Box2DData::new(
+                Point2DData::new(x as f64 * cell_size, y as f64 * cell_size),
+                Point2DData::new((x + 1) as f64 * cell_size, (y + 1) as f64 * cell_size),
+            );
+            let weight = region.area_in(&cell);
+            if weight <= 0.0 {
+                continue;
+            }
+            if let Some(value) = field(cell.center()) {
+                area += weight;
+                total += weight * value;
+                min = Some(min.map_or(value, |min| min.min(value)));
+                max = Some(max.map_or(value, |max| max.max(value)));
+            }
+        }
+    }
+    RegionStats {
+        area,
+        mean: if area > 0.0 { Some(total / area) } else { None },
+        min,
+        max,
+    }
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or_else(String::new, |value| value.to_string())
+}
+
+/// One row per region, with the mean, min and max of the before, after and improvement stats
+pub fn write_csv<W: Write>(
+    regions: &[Region],
+    stats: &[[RegionStats; 3]],
+    writer: W,
+) -> csv::Result<()> {
+    assert_eq!(regions.len(), stats.len());
+    let mut writer = csv::Writer::from_writer(writer);
+    let header = ["region", "area"];
+    writer.write_record(header.iter().map(|column| column.to_string()).chain(
+        ["before", "after", "improvement"].iter().flat_map(|name| {
+            ["mean", "min", "max"]
+                .iter()
+                .map(move |stat| format!("{}_{}", name, stat))
+        }),
+    ))?;
+    for (region, stats) in regions.iter().zip(stats) {
+        writer.write_record(
+            [region.name.clone(), region.area().to_string()]
+                .iter()
+                .cloned()
+                .chain(stats.iter().flat_map(|stats| {
+                    vec![
+                        optional(stats.mean),
+                        optional(stats.min),
+                        optional(stats.max),
+                    ]
+                })),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<Point2DData> {
+        vec![
+            Point2DData::new(x, y),
+            Point2DData::new(x + size, y),
+            Point2DData::new(x + size, y + size),
+            Point2DData::new(x, y + size),
+        ]
+    }
+
+    #[test]
+    fn test_assemble_rings() {
+        // A square in two halves, one of them drawn backwards, and a stray line
+        let ways = vec![
+            vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(10.0, 0.0),
+                Point2DData::new(10.0, 10.0),
+            ],
+            vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(0.0, 10.0),
+                Point2DData::new(10.0, 10.0),
+            ],
+            vec![Point2DData::new(50.0, 0.0), Point2DData::new(60.0, 0.0)],
+        ];
+        let rings = assemble_rings(ways);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 4);
+        assert_eq!(polygon_area(&rings[0]), 100.0);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        // 200 by 100 meters with a hole, over a field that's 1 in the west and 3 in the east
+        let region = Region {
+            name: String::from("Ward 1"),
+            outers: vec![vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(200.0, 0.0),
+                Point2DData::new(200.0, 100.0),
+                Point2DData::new(0.0, 100.0),
+            ]],
+            holes: vec![square(150.0, 0.0, 50.0)],
+        };
+        assert_eq!(region.area(), 20000.0 - 2500.0);
+        assert!(region.contains(Point2DData::new(10.0, 10.0)));
+        assert!(!region.contains(Point2DData::new(160.0, 10.0)));
+        assert_eq!(region.label_location(), Some(Point2DData::new(100.0, 50.0)));
+
+        let stats = aggregate(&region, 100.0, |point| {
+            Some(if point.x < 100.0 { 1.0 } else { 3.0 })
+        });
+        assert_eq!(stats.area, 17500.0);
+        assert_eq!(stats.mean, Some((10000.0 * 1.0 + 7500.0 * 3.0) / 17500.0));
+        assert_eq!((stats.min, stats.max), (Some(1.0), Some(3.0)));
+
+        let nowhere = aggregate(&region, 100.0, |_| None);
+        assert_eq!(nowhere.mean, None);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let region = Region {
+            name: String::from("Ward 1"),
+            outers: vec![square(0.0, 0.0, 100.0)],
+            holes: Vec::new(),
+        };
+        let stats = RegionStats {
+            area: 10000.0,
+            mean: Some(12.5),
+            min: Some(10.0),
+            max: Some(15.0),
+        };
+        let nowhere = RegionStats {
+            area: 0.0,
+            mean: None,
+            min: None,
+            max: None,
+        };
+        let mut csv = Vec::new();
+        write_csv(&[region], &[[stats.clone(), stats, nowhere]], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "region,area,before_mean,before_min,before_max,after_mean,after_min,after_max,\
+             improvement_mean,improvement_min,improvement_max\n\
+             Ward 1,10000,12.5,10,15,12.5,10,15,,,\n"
+        );
+    }
+
+    #[test]
+    fn test_load_geojson() {
+        let centroid = Point::new(42.386755, -71.098472);
+        let text = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"WARD": 3},
+             "geometry": {"type": "Polygon", "coordinates": [[[-71.10, 42.38], [-71.09, 42.38],
+                 [-71.09, 42.39], [-71.10, 42.39], [-71.10, 42.38]]]}},
+            {"type": "Feature", "properties": {"WARD": 4},
+             "geometry": {"type": "Point", "coordinates": [-71.10, 42.38]}},
+            {"type": "Feature", "properties": {"WARD": 5},
+             "geometry": {"type": "Polygon", "coordinates": []}},
+            {"type": "Feature", "properties": {"WARD": 6},
+             "geometry": {"type": "MultiPolygon", "coordinates": [[], [[]]]}},
+            {"type": "Feature", "properties": {"WARD": 7}, "geometry": null}
+        ]}"#;
+        let regions = load_geojson(text, &centroid, "WARD").unwrap();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].name, "3");
+        assert_eq!(regions[0].outers[0].len(), 4);
+        assert!(regions[0].contains(Point2DData::new(0.0, 0.0)));
+
+        assert!(matches!(
+            load_geojson(text, &centroid, "NAME"),
+            Err(GeoJsonError::Feature {
+                feature: 0,
+                message: "missing the name property"
+            })
+        ));
+        assert!(matches!(
+            load_geojson("{\"features\": [", &centroid, "WARD"),
+            Err(GeoJsonError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+//! Waiting for the train, and how much the ride itself varies from day to day. Trip times here are
+//! distributions, so that maps can show e.g. the 90th percentile instead of a typical day.
+use crate::gtfs::Seconds;
+use crate::transit::Timetable;
+use std::collections::HashMap;
+
+/// How many quantiles a distribution keeps. Adding two distributions takes the square of this.
+const N_QUANTILES: usize = 50;
+
+/// Time between trains, in minutes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Headway {
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl Headway {
+    /// For someone who shows up at a random time. Irregular headways make this longer, since
+    /// you're more likely to show up during a long gap: https://en.wikipedia.org/wiki/Inspection_paradox
+    pub fn expected_wait(&self) -> f64 {
+        if self.mean <= 0.0 {
+            return 0.0;
+        }
+        self.mean / 2.0 + self.std_dev.powi(2) / (2.0 * self.mean)
+    }
+
+    /// Spread evenly up to twice the expected wait, so that bunching shows up in both the mean and
+    /// the tail
+    pub fn wait(&self) -> Distribution {
+        Distribution::uniform(0.0, 2.0 * self.expected_wait())
+    }
+}
+
+/// Headways from the schedule between `start` and `end`, for each pair of consecutive stops.
+/// Keeping the next stop separates the directions and branches that share a platform.
+pub fn headways(
+    timetable: &Timetable,
+    start: Seconds,
+    end: Seconds,
+) -> HashMap<(usize, usize), Headway> {
+    let mut departures: HashMap<(usize, usize), Vec<Seconds>> = HashMap::new();
+    for connection in &timetable.connections {
+        if connection.departure >= start && connection.departure < end {
+            departures
+                .entry((connection.from, connection.to))
+                .or_default()
+                .push(connection.departure);
+        }
+    }
+
+    departures
+        .into_iter()
+        .filter_map(|(stops, mut times)| {
+            times.sort_unstable();
+            times.dedup();
+            let gaps: Vec<f64> = times
+                .windows(2)
+                .map(|pair| f64::from(pair[1] - pair[0]) / 60.0)
+                .collect();
+            if gaps.is_empty() {
+                return None;
+            }
+            let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+            let variance =
+                gaps.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+            Some((
+                stops,
+                Headway {
+                    mean,
+                    std_dev: variance.sqrt(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The most frequent service from any of these stops, e.g. a station's platforms. Branches that
+/// share a trunk already count together, since their trains go on to the same stop.
+pub fn most_frequent(
+    headways: &HashMap<(usize, usize), Headway>,
+    stops: &[usize],
+) -> Option<Headway> {
+    headways
+        .iter()
+        .filter(|((from, _), _)| stops.contains(from))
+        .map(|(_, &headway)| headway)
+        .min_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap())
+}
+
+/// Minutes, kept as evenly spaced quantiles so that distributions of any shape can be added
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution {
+    /// Sorted, at probabilities (i + 0.5) / N_QUANTILES
+    quantiles: Vec<f64>,
+}
+
+impl Distribution {
+    fn from_fn<F: Fn(f64) -> f64>(quantile: F) -> Self {
+        Distribution {
+            quantiles: (0..N_QUANTILES)
+                .map(|i| quantile((i as f64 + 0.5) / N_QUANTILES as f64))
+                .collect(),
+        }
+    }
+
+    pub fn constant(minutes: f64) -> Self {
+        Distribution::from_fn(|_| minutes)
+    }
+
+    pub fn uniform(min: f64, max: f64) -> Self {
+        Distribution::from_fn(|p| min + (max - min) * p)
+    }
+
+    /// Waiting for a train that comes every `headway` minutes, if you show up at a random time
+    pub fn wait(headway: f64) -> Self {
+        Distribution::uniform(0.0, headway)
+    }
+
+    /// From a mean and standard deviation, e.g. for a line's published run times
+    pub fn normal(mean: f64, std_dev: f64) -> Self {
+        Distribution::from_fn(|p| mean + std_dev * standard_normal_quantile(p))
+    }
+
+    /// From observed trip times, e.g. a line's on-time performance data
+    pub fn empirical(samples: &[f64]) -> Self {
+        let mut samples = samples.to_vec();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Distribution::from_fn(|p| quantile(&samples, p))
+    }
+
+    /// The time for doing one and then the other, assuming they're independent
+    pub fn plus(&self, other: &Distribution) -> Self {
+        let mut sums: Vec<f64> = self
+            .quantiles
+            .iter()
+            .flat_map(|a| other.quantiles.iter().map(move |b| a + b))
+            .collect();
+        sums.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Distribution::from_fn(|p| quantile(&sums, p))
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.quantiles.iter().sum::<f64>() / self.quantiles.len() as f64
+    }
+
+    /// E.g. 50 for a typical day, or 90 for a time you can count on
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        quantile(&self.quantiles, percentile / 100.0)
+    }
+}
+
+/// Interpolated between sorted samples, each of which is the middle of its share of the
+/// probability
+fn quantile(sorted: &[f64], p: f64) -> f64 {
+    let position = (p * sorted.len() as f64 - 0.5)
+        .max(0.0)
+        .min(sorted.len() as f64 - 1.0);
+    let i = position.floor() as usize;
+    let j = (i + 1).min(sorted.len() - 1);
+    let fraction = position - i as f64;
+    sorted[i] * (1.0 - fraction) + sorted[j] * fraction
+}
+
+/// Abramowitz and Stegun 26.2.23, which is good to about 4.5e-4
+fn standard_normal_quantile(p: f64) -> f64 {
+    let upper_tail = |q: f64| {
+        let t = (-2.0 * q.ln()).sqrt();
+        t - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+            / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t)
+    };
+    if p < 0.5 {
+        -upper_tail(p)
+    } else {
+        upper_tail(1.0 - p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::two_stop_feed;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} isn't close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_expected_wait() {
+        let regular = Headway {
+            mean: 8.0,
+            std_dev: 0.0,
+        };
+        assert_close(regular.expected_wait(), 4.0);
+        let bunched = Headway {
+            mean: 8.0,
+            std_dev: 4.0,
+        };
+        assert_close(bunched.expected_wait(), 5.0);
+        assert_close(bunched.wait().mean(), 5.0);
+        assert_close(regular.wait().percentile(90.0), 7.2);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let wait = Distribution::wait(8.0);
+        assert_close(wait.percentile(50.0), 4.0);
+        assert_close(wait.percentile(90.0), 7.2);
+        assert_close(wait.mean(), 4.0);
+
+        let ride = Distribution::normal(10.0, 2.0);
+        assert_close(ride.percentile(50.0), 10.0);
+        assert!((ride.percentile(90.0) - (10.0 + 1.2816 * 2.0)).abs() < 0.05);
+
+        let trip = wait.plus(&Distribution::constant(10.0));
+        assert_close(trip.percentile(50.0), 14.0);
+        assert_close(trip.percentile(90.0), 17.2);
+
+        let observed = Distribution::empirical(&[12.0, 10.0, 11.0, 13.0, 14.0]);
+        assert_close(observed.percentile(50.0), 12.0);
+        assert_close(observed.mean(), 12.0);
+    }
+
+    #[test]
+    fn test_headways() {
+        let feed = two_stop_feed(5 * 3600, 6 * 3600);
+        let timetable = Timetable::from_feed(&feed, 20191104, &[0]);
+        let a = timetable.stop("a").unwrap();
+        let b = timetable.stop("b").unwrap();
+
+        let headways = headways(&timetable, 5 * 3600, 6 * 3600);
+        assert_eq!(headways.len(), 2);
+        assert_eq!(
+            headways[&(a, b)],
+            Headway {
+                mean: 10.0,
+                std_dev: 0.0
+            }
+        );
+        assert_eq!(
+            most_frequent(&headways, &[b]).map(|headway| headway.mean),
+            Some(10.0)
+        );
+        assert_eq!(most_frequent(&headways, &[]), None);
+    }
+}
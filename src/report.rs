@@ -0,0 +1,260 @@
+//! Travel times summed up over the people they matter to, so conclusions come from numbers instead
+//! of eyeballing maps: e.g. "the median resident gets downtown 4 minutes faster", or "60% of
+//! Union Square is within 20 minutes", for each scenario and neighborhood.
+use crate::population::Residents;
+use crate::scenario::Scenario;
+use rayon::prelude::*;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// What the summary for everyone, across all neighborhoods, is called
+pub const EVERYONE: &str = "All";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub population: f64,
+    /// People who can get there at all
+    pub reachable: f64,
+    /// Over the people who can get there. None if nobody can.
+    pub mean_minutes: Option<f64>,
+    pub median_minutes: Option<f64>,
+    /// For each threshold, the share of everyone who can get there within that many minutes
+    pub share_within: Vec<f64>,
+}
+
+impl Summary {
+    /// From the number of people at each place and their travel time, if any. A travel time that
+    /// isn't finite counts as no way to get there.
+    pub fn new(samples: &[(f64, Option<f64>)], thresholds: &[f64]) -> Self {
+        let population: f64 = samples.iter().map(|(count, _)| count).sum();
+        let mut reachable: Vec<(f64, f64)> = samples
+            .iter()
+            .filter_map(|&(count, minutes)| match minutes {
+                Some(minutes) if minutes.is_finite() => Some((count, minutes)),
+                _ => None,
+            })
+            .collect();
+        reachable.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        let reachable_population: f64 = reachable.iter().map(|(count, _)| count).sum();
+
+        let (mean_minutes, median_minutes) = if reachable_population > 0.0 {
+            let total: f64 = reachable
+                .iter()
+                .map(|(count, minutes)| count * minutes)
+                .sum();
+            let mut so_far = 0.0;
+            let median = reachable
+                .iter()
+                .find(|(count, _)| {
+                    so_far += count;
+                    so_far >= reachable_population / 2.0
+                })
+                .map(|&(_, minutes)| minutes);
+            (Some(total / reachable_population), median)
+        } else {
+            (None, None)
+        };
+        let share_within = thresholds
+            .iter()
+            .map(|&threshold| {
+                let within: f64 = reachable
+                    .iter()
+                    .filter(|&&(_, minutes)| minutes <= threshold)
+                    .map(|(count, _)| count)
+                    .sum();
+                if population > 0.0 {
+                    within / population
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        Summary {
+            population,
+            reachable: reachable_population,
+            mean_minutes,
+            median_minutes,
+            share_within,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub scenario: String,
+    /// `EVERYONE` for the whole population
+    pub neighborhood: String,
+    pub summary: Summary,
+}
+
+/// For each scenario, a row for everyone and then one per neighborhood, in alphabetical order.
+/// Residents without a neighborhood only count toward everyone.
+pub fn summarize(scenarios: &[Scenario], residents: &[Residents], thresholds: &[f64]) -> Vec<Row> {
+    let mut rows = vec![];
+    for scenario in scenarios {
+        let samples: Vec<(f64, Option<f64>)> = residents
+            .par_iter()
+            .map(|resident| (resident.count, scenario.minutes(resident.location_x_y)))
+            .collect();
+        rows.push(Row {
+            scenario: scenario.name.clone(),
+            neighborhood: EVERYONE.to_string(),
+            summary: Summary::new(&samples, thresholds),
+        });
+
+        let mut by_neighborhood: BTreeMap<&str, Vec<(f64, Option<f64>)>> = BTreeMap::new();
+        for (resident, sample) in residents.iter().zip(samples) {
+            if !resident.neighborhood.is_empty() {
+                by_neighborhood
+                    .entry(&resident.neighborhood)
+                    .or_default()
+                    .push(sample);
+            }
+        }
+        for (neighborhood, samples) in by_neighborhood {
+            rows.push(Row {
+                scenario: scenario.name.clone(),
+                neighborhood: neighborhood.to_string(),
+                summary: Summary::new(&samples, thresholds),
+            });
+        }
+    }
+    rows
+}
+
+fn optional(minutes: Option<f64>) -> String {
+    minutes.map_or_else(String::new, |minutes| minutes.to_string())
+}
+
+/// One row per scenario and neighborhood, with a `share_within_<N>` column for each threshold
+pub fn write_csv<W: Write>(rows: &[Row], thresholds: &[f64], writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let header = [
+        "scenario",
+        "neighborhood",
+        "population",
+        "reachable",
+        "mean_minutes",
+        "median_minutes",
+    ];
+    writer.write_record(
+        header.iter().map(|column| column.to_string()).chain(
+            thresholds
+                .iter()
+                .map(|threshold| format!("share_within_{}", threshold)),
+        ),
+    )?;
+    for row in rows {
+        let summary = &row.summary;
+        writer.write_record(
+            [
+                row.scenario.clone(),
+                row.neighborhood.clone(),
+                summary.population.to_string(),
+                summary.reachable.to_string(),
+                optional(summary.mean_minutes),
+                optional(summary.median_minutes),
+            ]
+            .iter()
+            .cloned()
+            .chain(summary.share_within.iter().map(|share| share.to_string())),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The same as the CSV, as an array of objects, with `share_within` keyed by threshold
+pub fn to_json(rows: &[Row], thresholds: &[f64]) -> String {
+    let objects: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let summary = &row.summary;
+            let shares: Map<String, Value> = thresholds
+                .iter()
+                .zip(&summary.share_within)
+                .map(|(threshold, &share)| (threshold.to_string(), json!(share)))
+                .collect();
+            json!({
+                "scenario": row.scenario,
+                "neighborhood": row.neighborhood,
+                "population": summary.population,
+                "reachable": summary.reachable,
+                "mean_minutes": summary.mean_minutes,
+                "median_minutes": summary.median_minutes,
+                "share_within": shares,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&objects).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::destination::Destination;
+    use crate::graphics::Point2DData;
+
+    #[test]
+    fn test_summary() {
+        // 10 people 5 minutes away, 30 people 20 minutes away, and 10 people who can't get there
+        let summary = Summary::new(
+            &[(10.0, Some(5.0)), (30.0, Some(20.0)), (10.0, None)],
+            &[10.0],
+        );
+        assert_eq!(summary.population, 50.0);
+        assert_eq!(summary.reachable, 40.0);
+        assert_eq!(summary.mean_minutes, Some((50.0 + 600.0) / 40.0));
+        assert_eq!(summary.median_minutes, Some(20.0));
+        assert_eq!(summary.share_within, vec![0.2]);
+
+        let nobody = Summary::new(&[(10.0, None), (10.0, Some(f64::NAN))], &[10.0]);
+        assert_eq!(nobody.population, 20.0);
+        assert_eq!(nobody.mean_minutes, None);
+        assert_eq!(nobody.share_within, vec![0.0]);
+    }
+
+    #[test]
+    fn test_summarize() {
+        // Ten minutes per kilometer east
+        let scenario = Scenario::new(
+            "Walk",
+            [0.0, 0.0, 0.0, 1.0],
+            Destination::point("Downtown", 1.0, Point2DData::new(0.0, 0.0)),
+            |point: Point2DData| Some(point.x / 100.0),
+        );
+        let resident = |x: f64, count: f64, neighborhood: &str| Residents {
+            location_x_y: Point2DData::new(x, 0.0),
+            count,
+            neighborhood: neighborhood.to_string(),
+        };
+        let residents = [
+            resident(500.0, 100.0, "Union Square"),
+            resident(1500.0, 300.0, "Spring Hill"),
+            resident(2500.0, 100.0, ""),
+        ];
+        let rows = summarize(&[scenario], &residents, &[10.0, 20.0]);
+        let names: Vec<&str> = rows.iter().map(|row| row.neighborhood.as_str()).collect();
+        assert_eq!(names, vec![EVERYONE, "Spring Hill", "Union Square"]);
+        assert_eq!(rows[0].summary.population, 500.0);
+        assert_eq!(rows[0].summary.median_minutes, Some(15.0));
+        assert_eq!(rows[0].summary.share_within, vec![0.2, 0.8]);
+        assert_eq!(rows[2].summary.mean_minutes, Some(5.0));
+
+        let mut csv = vec![];
+        write_csv(&rows, &[10.0, 20.0], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with(
+            "scenario,neighborhood,population,reachable,mean_minutes,median_minutes,\
+             share_within_10,share_within_20\n"
+        ));
+        assert_eq!(csv.lines().count(), 4);
+
+        let json: Value = serde_json::from_str(&to_json(&rows, &[10.0, 20.0])).unwrap();
+        assert_eq!(json[2]["neighborhood"], "Union Square");
+        assert_eq!(json[0]["share_within"], json!({"10": 0.2, "20": 0.8}));
+        assert_eq!(json[0]["mean_minutes"], json!(rows[0].summary.mean_minutes));
+    }
+}
@@ -0,0 +1,909 @@
+//! A walking graph built from OSM highways, with shortest paths between arbitrary points. This
+//! replaces "as the crow flies" walking times, which are badly wrong around barriers like I-93.
+//! The same graph works for biking, with different rules about which ways to use.
+use crate::graphics::{Box2DData, Point2DData};
+use crate::spatial::{bounds, BoxIndex, Located, PointIndex};
+use crate::MyNode;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Average walking speed is about 5 kph: https://en.wikipedia.org/wiki/Walking
+pub const WALKING_METERS_PER_MINUTE: f64 = 5.0 * 1_000.0 / 60.0;
+
+/// A relaxed pace for getting around town, including stopping at lights
+pub const BIKING_METERS_PER_MINUTE: f64 = 15.0 * 1_000.0 / 60.0;
+
+/// Highways that are never walkable, regardless of other tags
+const UNWALKABLE_HIGHWAYS: &[&str] = &[
+    "motorway",
+    "motorway_link",
+    "construction",
+    "proposed",
+    "raceway",
+    "bus_guideway",
+    "abandoned",
+];
+
+/// Whether a person may walk along a way with these tags.
+///
+/// Crossings (`footway=crossing`) are ordinary footways here; they're what connects sidewalks to
+/// each other across roads.
+pub fn is_walkable(tags: &HashMap<String, String>) -> bool {
+    let tag = |key: &str| tags.get(key).map(String::as_str);
+    let highway = match tag("highway") {
+        Some(highway) => highway,
+        None => return false,
+    };
+    if UNWALKABLE_HIGHWAYS.contains(&highway) {
+        return false;
+    }
+    match tag("foot") {
+        Some("no") | Some("private") => false,
+        Some("yes") | Some("designated") | Some("permissive") => true,
+        _ => !matches!(tag("access"), Some("no") | Some("private")),
+    }
+}
+
+/// Whether a bike may ride along a way with these tags. Sidewalks are only for walking unless
+/// they say otherwise, and `bicycle=use_sidepath` means there's a cycleway alongside to use
+/// instead.
+pub fn is_bikeable(tags: &HashMap<String, String>) -> bool {
+    let tag = |key: &str| tags.get(key).map(String::as_str);
+    let highway = match tag("highway") {
+        Some(highway) => highway,
+        None => return false,
+    };
+    if UNWALKABLE_HIGHWAYS.contains(&highway) || highway == "steps" {
+        return false;
+    }
+    match tag("bicycle") {
+        Some("no") | Some("private") | Some("use_sidepath") => false,
+        Some("yes") | Some("designated") | Some("permissive") | Some("dismount") => true,
+        _ => match highway {
+            "footway" | "pedestrian" | "corridor" => false,
+            _ => !matches!(tag("access"), Some("no") | Some("private")),
+        },
+    }
+}
+
+/// Which ways you may go along a way, relative to the order of its nodes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Directions {
+    pub forward: bool,
+    pub backward: bool,
+}
+
+impl Directions {
+    pub const BOTH: Directions = Directions {
+        forward: true,
+        backward: true,
+    };
+
+    pub fn reversed(self) -> Self {
+        Directions {
+            forward: self.backward,
+            backward: self.forward,
+        }
+    }
+}
+
+/// Which ways a bike may go. One-way streets count, unless they're open to bikes going the other
+/// way, e.g. with `oneway:bicycle=no` or a contraflow lane like `cycleway=opposite_lane`.
+pub fn bike_directions(tags: &HashMap<String, String>) -> Directions {
+    let tag = |key: &str| tags.get(key).map(String::as_str);
+    let contraflow = [
+        "cycleway",
+        "cycleway:left",
+        "cycleway:right",
+        "cycleway:both",
+    ]
+    .iter()
+    .any(|key| {
+        matches!(
+            tag(key),
+            Some("opposite") | Some("opposite_lane") | Some("opposite_track")
+        )
+    });
+    if contraflow {
+        return Directions::BOTH;
+    }
+    let forward_only = Directions {
+        forward: true,
+        backward: false,
+    };
+    match tag("oneway:bicycle").or_else(|| tag("oneway")) {
+        Some("no") => Directions::BOTH,
+        Some("yes") | Some("true") | Some("1") => forward_only,
+        Some("-1") | Some("reverse") => forward_only.reversed(),
+        _ if tag("junction") == Some("roundabout") => forward_only,
+        _ => Directions::BOTH,
+    }
+}
+
+/// Snapping searches this far around a point first, in meters, and twice as far each time it
+/// comes up empty
+const FIRST_SNAP_RADIUS: f64 = 50.0;
+
+pub fn polyline_length(polyline: &[Point2DData]) -> f64 {
+    polyline
+        .windows(2)
+        .map(|segment| (segment[1] - segment[0]).length())
+        .sum()
+}
+
+/// The closest point on the polyline to this point, and how far along the polyline it is
+pub fn project_onto_polyline(point: Point2DData, polyline: &[Point2DData]) -> (Point2DData, f64) {
+    let mut best = (polyline[0], 0.0, (point - polyline[0]).length());
+    let mut along = 0.0;
+    for segment in polyline.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let ab = b - a;
+        let length = ab.length();
+        let t = if length == 0.0 {
+            0.0
+        } else {
+            ((point - a).dot(ab) / (length * length)).clamp(0.0, 1.0)
+        };
+        let projected = a + ab * t;
+        let distance = (point - projected).length();
+        if distance < best.2 {
+            best = (projected, along + t * length, distance);
+        }
+        along += length;
+    }
+    (best.0, best.1)
+}
+
+/// A walkable stretch between two vertices. The geometry includes both ends.
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub to: usize,
+    pub length: f64,
+    /// How long it takes to go along the whole edge, which can be different each way
+    pub minutes: f64,
+    pub geometry: Vec<Point2DData>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Vertex {
+    i: usize,
+    location: Point2DData,
+}
+
+impl Located for Vertex {
+    fn location(&self) -> Point2DData {
+        self.location
+    }
+}
+
+/// Where an arbitrary point, e.g. the center of a grid cell, joins the network
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snap {
+    /// The edge is `edges[from][edge]`
+    pub from: usize,
+    pub edge: usize,
+    /// The closest point on the edge
+    pub point: Point2DData,
+    /// How far along the edge the closest point is, in meters
+    pub along: f64,
+    /// How far it is to walk from the point to the edge, in meters
+    pub distance: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    /// In meters
+    pub length: f64,
+    pub vertices: Vec<usize>,
+}
+
+/// For the priority queue, which pops the lowest cost first
+#[derive(PartialEq)]
+struct State {
+    priority: f64,
+    cost: f64,
+    vertex: usize,
+}
+
+impl Eq for State {}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.partial_cmp(&self.priority).unwrap()
+    }
+}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Which way a search runs over the edges
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heading {
+    /// Away from the sources, e.g. the minutes to walk from a place to everywhere else
+    Outbound,
+    /// Toward the sources, following edges backward, e.g. the minutes to walk from everywhere to
+    /// a station
+    Inbound,
+}
+
+/// Vertices are the OSM nodes where usable ways end or meet. For walking, edges go both ways,
+/// since pedestrians aren't bound by one-way streets.
+///
+/// Edges point the way you travel along them, and their minutes are for going that way, e.g.
+/// uphill. Searches say which way they go with a `Heading`, so the same graph gives the minutes
+/// both to and from a place.
+pub struct StreetGraph {
+    pub nodes: Vec<MyNode>,
+    pub edges: Vec<Vec<Edge>>,
+    /// For each vertex, the edges that end there, as (from, edge) like in `Snap`
+    incoming: Vec<Vec<(usize, usize)>>,
+    /// How fast you go along the edges
+    pub meters_per_minute: f64,
+    index: PointIndex<Vertex>,
+    /// Each edge in one direction only, as (from, edge) like in `Snap`
+    edge_index: BoxIndex<(usize, usize)>,
+}
+
+impl StreetGraph {
+    /// Build the graph from ways' tags and nodes, skipping ways that aren't walkable
+    pub fn new<'a, I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<MyNode>)>>(
+        ways: I,
+    ) -> Self {
+        StreetGraph::with_rules(ways, WALKING_METERS_PER_MINUTE, is_walkable, |_| {
+            Directions::BOTH
+        })
+    }
+
+    /// Build a graph for some other way of getting around, e.g. `is_bikeable` and
+    /// `bike_directions` for biking, at a constant speed
+    pub fn with_rules<'a, I, K, D>(ways: I, meters_per_minute: f64, keep: K, directions: D) -> Self
+    where
+        I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<MyNode>)>,
+        K: Fn(&HashMap<String, String>) -> bool,
+        D: Fn(&HashMap<String, String>) -> Directions,
+    {
+        StreetGraph::with_costs(
+            ways,
+            meters_per_minute,
+            keep,
+            directions,
+            |_, geometry, _| polyline_length(geometry) / meters_per_minute,
+        )
+    }
+
+    /// Like `with_rules`, with `cost` giving the minutes to go along a piece of a way in the
+    /// order of its geometry, and what share of the way's length the piece is, e.g.
+    /// `WalkCost::minutes`. The speed is still used to get on and off the network.
+    pub fn with_costs<'a, I, K, D, C>(
+        ways: I,
+        meters_per_minute: f64,
+        keep: K,
+        directions: D,
+        cost: C,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (&'a HashMap<String, String>, Vec<MyNode>)>,
+        K: Fn(&HashMap<String, String>) -> bool,
+        D: Fn(&HashMap<String, String>) -> Directions,
+        C: Fn(&HashMap<String, String>, &[Point2DData], f64) -> f64,
+    {
+        let ways: Vec<(&HashMap<String, String>, Vec<MyNode>, Directions)> = ways
+            .into_iter()
+            .filter(|(tags, nodes)| nodes.len() >= 2 && keep(tags))
+            .map(|(tags, nodes)| (tags, nodes, directions(tags)))
+            .collect();
+
+        // A node is a vertex if it's the end of a way or it's used more than once
+        let mut uses: HashMap<i64, usize> = HashMap::new();
+        for (_, nodes, _) in &ways {
+            for node in nodes {
+                *uses.entry(node.node_id).or_insert(0) += 1;
+            }
+            *uses.get_mut(&nodes[0].node_id).unwrap() += 1;
+            *uses.get_mut(&nodes[nodes.len() - 1].node_id).unwrap() += 1;
+        }
+
+        let mut vertices: HashMap<i64, usize> = HashMap::new();
+        let mut graph_nodes: Vec<MyNode> = vec![];
+        let mut edges: Vec<Vec<Edge>> = vec![];
+        let mut vertex = |node: &MyNode| -> usize {
+            *vertices.entry(node.node_id).or_insert_with(|| {
+                graph_nodes.push(*node);
+                edges.push(vec![]);
+                graph_nodes.len() - 1
+            })
+        };
+
+        // Each piece of a way, with the way's tags, what share of its length the piece is, and
+        // which ways you can go along it
+        let mut new_edges = vec![];
+        for &(tags, ref nodes, directions) in &ways {
+            let way_length = polyline_length(
+                &nodes
+                    .iter()
+                    .map(|node| node.to_point2d())
+                    .collect::<Vec<_>>(),
+            );
+            let mut from = vertex(&nodes[0]);
+            let mut geometry = vec![nodes[0].to_point2d()];
+            for node in &nodes[1..] {
+                geometry.push(node.to_point2d());
+                if uses[&node.node_id] >= 2 {
+                    let to = vertex(node);
+                    let share = if way_length > 0.0 {
+                        polyline_length(&geometry) / way_length
+                    } else {
+                        1.0
+                    };
+                    new_edges.push((from, to, geometry, tags, share, directions));
+                    from = to;
+                    geometry = vec![node.to_point2d()];
+                }
+            }
+        }
+
+        let mut edge_boxes = vec![];
+        for (from, to, geometry, tags, share, directions) in new_edges {
+            // Index whichever direction there is, once
+            let edge_box = bounds(&geometry).expect("edges start at a node");
+            if directions.forward {
+                edge_boxes.push((edge_box, (from, edges[from].len())));
+            } else if directions.backward {
+                edge_boxes.push((edge_box, (to, edges[to].len())));
+            }
+            let length = polyline_length(&geometry);
+            let mut reversed = geometry.clone();
+            reversed.reverse();
+            if directions.forward {
+                edges[from].push(Edge {
+                    to,
+                    length,
+                    minutes: cost(tags, &geometry, share),
+                    geometry,
+                });
+            }
+            if directions.backward {
+                edges[to].push(Edge {
+                    to: from,
+                    length,
+                    minutes: cost(tags, &reversed, share),
+                    geometry: reversed,
+                });
+            }
+        }
+
+        let mut incoming = vec![vec![]; edges.len()];
+        for (from, edges) in edges.iter().enumerate() {
+            for (i, edge) in edges.iter().enumerate() {
+                incoming[edge.to].push((from, i));
+            }
+        }
+
+        let index = PointIndex::new(
+            graph_nodes
+                .iter()
+                .enumerate()
+                .map(|(i, node)| Vertex {
+                    i,
+                    location: node.to_point2d(),
+                })
+                .collect(),
+        );
+
+        StreetGraph {
+            nodes: graph_nodes,
+            edges,
+            incoming,
+            meters_per_minute,
+            index,
+            edge_index: BoxIndex::new(edge_boxes),
+        }
+    }
+
+    pub fn location(&self, vertex: usize) -> Point2DData {
+        self.nodes[vertex].to_point2d()
+    }
+
+    /// The vertex closest to this point, as the crow flies
+    pub fn nearest_vertex(&self, point: Point2DData) -> Option<usize> {
+        self.index.nearest(point).map(|vertex| vertex.i)
+    }
+
+    /// The nearest point on any edge, or None if there are no edges at all
+    pub fn snap(&self, point: Point2DData) -> Option<Snap> {
+        if self.edge_index.is_empty() {
+            return None;
+        }
+        // Any edge within the radius has a bounding box that overlaps the search box, so once the
+        // best candidate is within the radius it's the best of all
+        let mut radius = FIRST_SNAP_RADIUS;
+        loop {
+            let candidates = self
+                .edge_index
+                .intersecting(&Box2DData::new(point, point).inflate(radius, radius));
+            let searched_everything = candidates.len() == self.edge_index.len();
+            let best = candidates
+                .into_iter()
+                .map(|&(from, edge)| {
+                    let (projected, along) =
+                        project_onto_polyline(point, &self.edges[from][edge].geometry);
+                    Snap {
+                        from,
+                        edge,
+                        point: projected,
+                        along,
+                        distance: (point - projected).length(),
+                    }
+                })
+                .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+            match best {
+                Some(snap) if snap.distance <= radius || searched_everything => return Some(snap),
+                _ => radius *= 2.0,
+            }
+        }
+    }
+
+    pub fn edge(&self, snap: &Snap) -> &Edge {
+        &self.edges[snap.from][snap.edge]
+    }
+
+    /// The same stretch going the other way, unless it's one-way
+    pub fn reverse_edge(&self, snap: &Snap) -> Option<&Edge> {
+        let edge = self.edge(snap);
+        self.edges[edge.to]
+            .iter()
+            .find(|back| back.to == snap.from && back.length == edge.length)
+    }
+
+    /// Whether you can also go the other way along the snapped edge, i.e. it's not one-way
+    pub fn is_two_way(&self, snap: &Snap) -> bool {
+        self.reverse_edge(snap).is_some()
+    }
+
+    /// How far this point is from any walkable street, e.g. to leave out grid cells in the
+    /// harbor or in rail yards. None if there are no streets at all.
+    pub fn distance_to_street(&self, point: Point2DData) -> Option<f64> {
+        self.snap(point).map(|snap| snap.distance)
+    }
+
+    /// Dijkstra's algorithm from any of the sources, each starting with its own cost. Returns the
+    /// lowest cost to reach each vertex, which is infinite if it can't be reached, and the previous
+    /// vertex on that path.
+    ///
+    /// With a target and a heuristic, this is A* and stops once the target is reached. The
+    /// heuristic must never overestimate the remaining cost. Vertices that cost more than
+    /// `max_cost` are left infinite. Going `Inbound`, the previous vertex is the next one on the
+    /// way to a source.
+    fn search<C: Fn(&Edge) -> f64, H: Fn(usize) -> f64>(
+        &self,
+        sources: &[(usize, f64)],
+        target: Option<usize>,
+        heading: Heading,
+        max_cost: f64,
+        edge_cost: C,
+        heuristic: H,
+    ) -> (Vec<f64>, Vec<Option<usize>>) {
+        let mut costs = vec![f64::INFINITY; self.nodes.len()];
+        let mut previous = vec![None; self.nodes.len()];
+        let mut heap = BinaryHeap::new();
+
+        for &(source, cost) in sources {
+            if cost < costs[source] && cost <= max_cost {
+                costs[source] = cost;
+                heap.push(State {
+                    priority: cost + heuristic(source),
+                    cost,
+                    vertex: source,
+                });
+            }
+        }
+
+        while let Some(State { cost, vertex, .. }) = heap.pop() {
+            if Some(vertex) == target {
+                break;
+            }
+            if cost > costs[vertex] {
+                // We already found a better way here
+                continue;
+            }
+            let neighbors: Vec<(&Edge, usize)> = match heading {
+                Heading::Outbound => self.edges[vertex]
+                    .iter()
+                    .map(|edge| (edge, edge.to))
+                    .collect(),
+                Heading::Inbound => self.incoming[vertex]
+                    .iter()
+                    .map(|&(from, i)| (&self.edges[from][i], from))
+                    .collect(),
+            };
+            for (edge, next) in neighbors {
+                let next_cost = cost + edge_cost(edge);
+                if next_cost < costs[next] && next_cost <= max_cost {
+                    costs[next] = next_cost;
+                    previous[next] = Some(vertex);
+                    heap.push(State {
+                        priority: next_cost + heuristic(next),
+                        cost: next_cost,
+                        vertex: next,
+                    });
+                }
+            }
+        }
+
+        (costs, previous)
+    }
+
+    /// The shortest walk between two vertices with A*, or None if they aren't connected
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Path> {
+        let goal = self.location(to);
+        let (lengths, previous) = self.search(
+            &[(from, 0.0)],
+            Some(to),
+            Heading::Outbound,
+            f64::INFINITY,
+            |edge| edge.length,
+            // Straight-line distance never overestimates, since edges are made of straight lines
+            |vertex| (goal - self.location(vertex)).length(),
+        );
+        if lengths[to].is_infinite() {
+            return None;
+        }
+
+        let mut vertices = vec![to];
+        while let Some(vertex) = previous[*vertices.last().unwrap()] {
+            vertices.push(vertex);
+        }
+        vertices.reverse();
+        Some(Path {
+            length: lengths[to],
+            vertices,
+        })
+    }
+
+    /// Lengths of the shortest walks from one vertex to every vertex, with Dijkstra's algorithm
+    pub fn shortest_lengths(&self, from: usize) -> Vec<f64> {
+        self.search(
+            &[(from, 0.0)],
+            None,
+            Heading::Outbound,
+            f64::INFINITY,
+            |edge| edge.length,
+            |_| 0.0,
+        )
+        .0
+    }
+
+    /// The lowest cost to reach every vertex from any of the sources, each of which starts with
+    /// its own cost. This is one Dijkstra run no matter how many sources there are.
+    pub fn multi_source_costs<C: Fn(&Edge) -> f64>(
+        &self,
+        sources: &[(usize, f64)],
+        edge_cost: C,
+    ) -> Vec<f64> {
+        self.search(
+            sources,
+            None,
+            Heading::Outbound,
+            f64::INFINITY,
+            edge_cost,
+            |_| 0.0,
+        )
+        .0
+    }
+
+    /// Like `multi_source_costs`, along with which source each vertex's lowest cost comes from,
+    /// as an index into the sources. None where no source can reach. The search goes whichever
+    /// way `heading` says, and stops at `max_cost`, past which vertices are left unreached, which
+    /// is much faster when only the neighborhood matters.
+    pub fn multi_source_origins<C: Fn(&Edge) -> f64>(
+        &self,
+        sources: &[(usize, f64)],
+        heading: Heading,
+        max_cost: f64,
+        edge_cost: C,
+    ) -> (Vec<f64>, Vec<Option<usize>>) {
+        let (costs, previous) = self.search(sources, None, heading, max_cost, edge_cost, |_| 0.0);
+        let mut origins = vec![None; costs.len()];
+        // Paths start wherever the first source with the lowest cost there wasn't beaten
+        for (i, &(source, cost)) in sources.iter().enumerate() {
+            if previous[source].is_none() && cost == costs[source] && origins[source].is_none() {
+                origins[source] = Some(i);
+            }
+        }
+        // Everything else comes from the same source as the start of its path
+        for vertex in 0..costs.len() {
+            let mut path = vec![];
+            let mut at = vertex;
+            while origins[at].is_none() {
+                match previous[at] {
+                    Some(before) => {
+                        path.push(at);
+                        at = before;
+                    }
+                    None => break,
+                }
+            }
+            for on_path in path {
+                origins[on_path] = origins[at];
+            }
+        }
+        (costs, origins)
+    }
+
+    /// The shortest walk between two arbitrary points, including getting onto the network at the
+    /// nearest vertex and back off it at the end
+    pub fn shortest_path_between(&self, from: Point2DData, to: Point2DData) -> Option<Path> {
+        let from_vertex = self.nearest_vertex(from)?;
+        let to_vertex = self.nearest_vertex(to)?;
+        self.shortest_path(from_vertex, to_vertex).map(|path| Path {
+            length: path.length
+                + (self.location(from_vertex) - from).length()
+                + (to - self.location(to_vertex)).length(),
+            vertices: path.vertices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tags;
+
+    fn node(node_id: i64, x: f64, y: f64) -> MyNode {
+        MyNode {
+            x_y_meters: [x, y],
+            node_id,
+        }
+    }
+
+    #[test]
+    fn test_is_walkable() {
+        assert!(is_walkable(&tags(&[("highway", "residential")])));
+        assert!(is_walkable(&tags(&[
+            ("highway", "footway"),
+            ("footway", "crossing")
+        ])));
+        assert!(!is_walkable(&tags(&[("building", "yes")])));
+        assert!(!is_walkable(&tags(&[("highway", "motorway")])));
+        assert!(!is_walkable(&tags(&[
+            ("highway", "primary"),
+            ("foot", "no")
+        ])));
+        assert!(!is_walkable(&tags(&[
+            ("highway", "service"),
+            ("access", "private")
+        ])));
+        assert!(is_walkable(&tags(&[
+            ("highway", "service"),
+            ("access", "private"),
+            ("foot", "yes")
+        ])));
+    }
+
+    #[test]
+    fn test_is_bikeable() {
+        assert!(is_bikeable(&tags(&[("highway", "residential")])));
+        assert!(is_bikeable(&tags(&[("highway", "cycleway")])));
+        assert!(!is_bikeable(&tags(&[("highway", "footway")])));
+        assert!(is_bikeable(&tags(&[
+            ("highway", "footway"),
+            ("bicycle", "yes")
+        ])));
+        assert!(!is_bikeable(&tags(&[("highway", "steps")])));
+        assert!(!is_bikeable(&tags(&[
+            ("highway", "primary"),
+            ("bicycle", "use_sidepath")
+        ])));
+    }
+
+    #[test]
+    fn test_bike_directions() {
+        let forward_only = Directions {
+            forward: true,
+            backward: false,
+        };
+        assert_eq!(
+            bike_directions(&tags(&[("highway", "residential")])),
+            Directions::BOTH
+        );
+        assert_eq!(
+            bike_directions(&tags(&[("highway", "residential"), ("oneway", "yes")])),
+            forward_only
+        );
+        assert_eq!(
+            bike_directions(&tags(&[("highway", "residential"), ("oneway", "-1")])),
+            forward_only.reversed()
+        );
+        assert_eq!(
+            bike_directions(&tags(&[
+                ("highway", "residential"),
+                ("oneway", "yes"),
+                ("oneway:bicycle", "no")
+            ])),
+            Directions::BOTH
+        );
+        assert_eq!(
+            bike_directions(&tags(&[
+                ("highway", "residential"),
+                ("oneway", "yes"),
+                ("cycleway:left", "opposite_lane")
+            ])),
+            Directions::BOTH
+        );
+        assert_eq!(
+            bike_directions(&tags(&[("highway", "primary"), ("junction", "roundabout")])),
+            forward_only
+        );
+    }
+
+    #[test]
+    fn test_one_way() {
+        let residential = tags(&[("highway", "residential"), ("oneway", "yes")]);
+        let graph = StreetGraph::with_rules(
+            vec![(&residential, vec![node(1, 0.0, 0.0), node(2, 100.0, 0.0)])],
+            WALKING_METERS_PER_MINUTE * 3.0,
+            is_bikeable,
+            bike_directions,
+        );
+        assert_eq!(graph.edges[0].len(), 1);
+        assert!(graph.edges[1].is_empty());
+        assert!(graph.shortest_path(0, 1).is_some());
+        assert!(graph.shortest_path(1, 0).is_none());
+
+        let snap = graph.snap(Point2DData::new(50.0, 5.0)).unwrap();
+        assert!(!graph.is_two_way(&snap));
+    }
+
+    #[test]
+    fn test_with_costs() {
+        // A crosswalk, split where it meets the road. It costs a minute to wait at plus a minute
+        // per 100 meters, and twice that going west.
+        let crossing = tags(&[("highway", "footway"), ("footway", "crossing")]);
+        let road = tags(&[("highway", "primary")]);
+        let graph = StreetGraph::with_costs(
+            vec![
+                (
+                    &crossing,
+                    vec![node(1, 0.0, 0.0), node(2, 100.0, 0.0), node(3, 300.0, 0.0)],
+                ),
+                (&road, vec![node(4, 100.0, -50.0), node(2, 100.0, 0.0)]),
+            ],
+            WALKING_METERS_PER_MINUTE,
+            is_walkable,
+            |_| Directions::BOTH,
+            |_, geometry, share| {
+                let east = geometry[geometry.len() - 1].x > geometry[0].x;
+                let per_meter = if east { 0.01 } else { 0.02 };
+                share + polyline_length(geometry) * per_meter
+            },
+        );
+        let west_end = graph.nearest_vertex(Point2DData::new(0.0, 0.0)).unwrap();
+        let east_end = graph.nearest_vertex(Point2DData::new(300.0, 0.0)).unwrap();
+        let across = |from: usize, to: usize| {
+            graph.multi_source_costs(&[(from, 0.0)], |edge| edge.minutes)[to]
+        };
+        assert!((across(west_end, east_end) - (1.0 + 3.0)).abs() < 1e-9);
+        assert!((across(east_end, west_end) - (1.0 + 6.0)).abs() < 1e-9);
+
+        // Going inbound is the same trip, searched from the other end
+        let (to_east_end, _) = graph.multi_source_origins(
+            &[(east_end, 0.0)],
+            Heading::Inbound,
+            f64::INFINITY,
+            |edge| edge.minutes,
+        );
+        assert!((to_east_end[west_end] - (1.0 + 3.0)).abs() < 1e-9);
+    }
+
+    /// A street with a highway running right next to it, which would be a shortcut
+    ///
+    /// ```text
+    /// 1 --- 2 --- 3
+    /// |           |
+    /// 4 --------- 5   (motorway from 4 to 5 would be shorter, but we can't walk there)
+    /// ```
+    fn example_graph() -> StreetGraph {
+        let residential = tags(&[("highway", "residential")]);
+        let motorway = tags(&[("highway", "motorway")]);
+        let private = tags(&[("highway", "service"), ("access", "private")]);
+        StreetGraph::new(vec![
+            (
+                &residential,
+                vec![
+                    node(4, 0.0, 100.0),
+                    node(1, 0.0, 0.0),
+                    node(2, 100.0, 0.0),
+                    node(3, 200.0, 0.0),
+                    node(5, 200.0, 100.0),
+                ],
+            ),
+            (&motorway, vec![node(4, 0.0, 100.0), node(5, 200.0, 100.0)]),
+            (&private, vec![node(2, 100.0, 0.0), node(6, 100.0, 100.0)]),
+        ])
+    }
+
+    #[test]
+    fn test_graph_splits_at_shared_nodes() {
+        let graph = example_graph();
+        // Only the ends of the street are vertices: 1 and 3 are just bends, and the private
+        // driveway doesn't count as meeting the street at 2
+        assert_eq!(graph.nodes.len(), 2);
+        let total_length: f64 = graph.edges.iter().flatten().map(|edge| edge.length).sum();
+        assert_eq!(total_length, 2.0 * 400.0);
+    }
+
+    #[test]
+    fn test_shortest_path_avoids_motorway() {
+        let graph = example_graph();
+        let path = graph
+            .shortest_path_between(
+                Point2DData::new(-10.0, 100.0),
+                Point2DData::new(210.0, 100.0),
+            )
+            .unwrap();
+        assert_eq!(path.length, 10.0 + 400.0 + 10.0);
+        assert_eq!(path.vertices.len(), 2);
+    }
+
+    #[test]
+    fn test_a_star_matches_dijkstra() {
+        let graph = example_graph();
+        let from = graph.nearest_vertex(Point2DData::new(0.0, 100.0)).unwrap();
+        let lengths = graph.shortest_lengths(from);
+        for (to, &length) in lengths.iter().enumerate() {
+            assert_eq!(graph.shortest_path(from, to).unwrap().length, length);
+        }
+    }
+
+    #[test]
+    fn test_project_onto_polyline() {
+        let polyline = vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(10.0, 0.0),
+            Point2DData::new(10.0, 10.0),
+        ];
+        assert_eq!(
+            project_onto_polyline(Point2DData::new(12.0, 4.0), &polyline),
+            (Point2DData::new(10.0, 4.0), 14.0)
+        );
+        assert_eq!(
+            project_onto_polyline(Point2DData::new(-5.0, -5.0), &polyline),
+            (Point2DData::new(0.0, 0.0), 0.0)
+        );
+    }
+
+    #[test]
+    fn test_snap() {
+        let graph = example_graph();
+
+        // In the middle of the block, closer to the street than to either vertex
+        let snap = graph.snap(Point2DData::new(100.0, 30.0)).unwrap();
+        assert_eq!(snap.point, Point2DData::new(100.0, 0.0));
+        assert_eq!(snap.distance, 30.0);
+        // Halfway along the street, whichever way the edge goes
+        assert_eq!(snap.along, 200.0);
+        assert_eq!(graph.edge(&snap).length, 400.0);
+
+        // Far beyond the first search radius, e.g. out in the harbor
+        let far = graph
+            .distance_to_street(Point2DData::new(100.0, -1000.0))
+            .unwrap();
+        assert_eq!(far, 1000.0);
+
+        let empty = StreetGraph::new(vec![]);
+        assert!(empty.snap(Point2DData::new(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let residential = tags(&[("highway", "residential")]);
+        let graph = StreetGraph::new(vec![
+            (&residential, vec![node(1, 0.0, 0.0), node(2, 10.0, 0.0)]),
+            (&residential, vec![node(3, 50.0, 0.0), node(4, 60.0, 0.0)]),
+        ]);
+        assert!(graph.shortest_path(0, 3).is_none());
+    }
+}
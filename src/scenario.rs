@@ -0,0 +1,378 @@
+//! Scenarios are versions of the transit network, e.g. before and after the GLX, which we compare
+//! by their travel times at every point on the map.
+use crate::destination::{Aggregate, Destination};
+use crate::graphics::{Box2DData, Point2DData};
+use crate::gtfs::{Date, Feed, Seconds};
+use crate::isochrone::TravelTimeField;
+use crate::routing::{Heading, StreetGraph};
+use crate::transit::{DoorToDoor, StationWalks, Timetable};
+use geo_types::Point;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Minutes to the destination from any point, or None if there's no way to get there from that
+/// point
+pub type TravelMinutes = Arc<dyn Fn(Point2DData) -> Option<f64> + Send + Sync>;
+
+/// How people get around in a scenario, e.g. walking to the subway, biking to the subway, or
+/// walking to buses and the subway
+#[derive(Clone)]
+pub struct Modes {
+    pub name: String,
+    /// Streets for getting to the first stop and from the last one, e.g. for walking or biking
+    pub access: Arc<StreetGraph>,
+    /// Same as `Route::route_type`, e.g. 0 and 1 for light rail and subway, and 3 for buses
+    pub route_types: Vec<u16>,
+}
+
+/// What to route over when a scenario comes from a schedule
+#[derive(Clone, Debug)]
+pub struct TransitOptions {
+    /// The centroid that data space is projected around
+    pub centroid: Point<f64>,
+    pub date: Date,
+    pub departure: Seconds,
+    pub destinations: Vec<Destination>,
+    /// Only stations in here are worth walking to
+    pub area: Box2DData,
+}
+
+/// What a version of the transit network is made of, so that versions can be listed and compared
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Network {
+    /// Sorted, without duplicates
+    pub stations: Vec<String>,
+    /// Line or route IDs, sorted, without duplicates
+    pub routes: Vec<String>,
+}
+
+fn sorted<I: IntoIterator<Item = String>>(names: I) -> Vec<String> {
+    let mut names: Vec<String> = names.into_iter().collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+impl Network {
+    pub fn new<S, R>(stations: S, routes: R) -> Self
+    where
+        S: IntoIterator<Item = String>,
+        R: IntoIterator<Item = String>,
+    {
+        Network {
+            stations: sorted(stations),
+            routes: sorted(routes),
+        }
+    }
+
+    /// What this network has that the other doesn't, e.g. the new stations after the GLX
+    pub fn minus(&self, other: &Network) -> Network {
+        let minus = |mine: &[String], theirs: &[String]| -> Vec<String> {
+            mine.iter()
+                .filter(|name| theirs.binary_search(name).is_err())
+                .cloned()
+                .collect()
+        };
+        Network {
+            stations: minus(&self.stations, &other.stations),
+            routes: minus(&self.routes, &other.routes),
+        }
+    }
+}
+
+/// A named version of the transit network, and the travel times to each destination that come
+/// with it
+#[derive(Clone)]
+pub struct Scenario {
+    pub name: String,
+    /// For maps of which scenario wins
+    pub color: [f32; 4],
+    /// The stations and routes, which are empty unless the scenario says what they are
+    pub network: Network,
+    /// How `minutes` combines the destinations
+    pub aggregate: Aggregate,
+    destinations: Vec<Destination>,
+    travel_minutes: Vec<TravelMinutes>,
+}
+
+impl Scenario {
+    pub fn new<F: Fn(Point2DData) -> Option<f64> + Send + Sync + 'static>(
+        name: &str,
+        color: [f32; 4],
+        destination: Destination,
+        travel_minutes: F,
+    ) -> Self {
+        Scenario {
+            name: name.to_string(),
+            color,
+            network: Network::default(),
+            aggregate: Aggregate::Min,
+            destinations: vec![destination],
+            travel_minutes: vec![Arc::new(travel_minutes)],
+        }
+    }
+
+    /// Another destination, e.g. one that the spreadsheet doesn't have times for
+    pub fn with_destination<F: Fn(Point2DData) -> Option<f64> + Send + Sync + 'static>(
+        mut self,
+        destination: Destination,
+        travel_minutes: F,
+    ) -> Self {
+        self.destinations.push(destination);
+        self.travel_minutes.push(Arc::new(travel_minutes));
+        self
+    }
+
+    pub fn with_aggregate(self, aggregate: Aggregate) -> Self {
+        Scenario { aggregate, ..self }
+    }
+
+    pub fn with_network(self, network: Network) -> Self {
+        Scenario { network, ..self }
+    }
+
+    /// Walking to whichever station is best, where each station takes a fixed number of minutes
+    /// to get to the destination
+    pub fn from_stations(
+        name: &str,
+        color: [f32; 4],
+        graph: Arc<StreetGraph>,
+        stations: &[(Point2DData, f64)],
+        destination: Destination,
+    ) -> Self {
+        let field = TravelTimeField::new(graph, stations, Heading::Inbound);
+        Scenario::new(name, color, destination, move |point| field.sample(point))
+    }
+
+    /// Door to door over a schedule, e.g. a published feed with proposed lines added to it. The
+    /// same access streets are used at both ends, so biking means taking the bike along.
+    pub fn from_feed(
+        name: &str,
+        color: [f32; 4],
+        feed: &Feed,
+        options: &TransitOptions,
+        modes: &Modes,
+    ) -> Self {
+        let timetable = Timetable::from_feed(feed, options.date, &modes.route_types);
+        let stations: Vec<_> = feed
+            .stations(&modes.route_types, &options.centroid)
+            .into_iter()
+            .filter(|station| options.area.contains(&station.location_x_y))
+            .collect();
+        let walks = Arc::new(StationWalks::new(
+            modes.access.clone(),
+            &timetable,
+            &stations,
+        ));
+        let travel_minutes = options
+            .destinations
+            .iter()
+            .map(|destination| -> TravelMinutes {
+                let door_to_door = DoorToDoor::new(
+                    walks.clone(),
+                    &timetable,
+                    &destination.points,
+                    options.departure,
+                );
+                Arc::new(move |point| door_to_door.sample(point))
+            })
+            .collect();
+        let network = Network::new(
+            stations.iter().map(|station| station.name.clone()),
+            stations
+                .iter()
+                .flat_map(|station| station.route_ids.iter().cloned()),
+        );
+        Scenario {
+            name: name.to_string(),
+            color,
+            network,
+            aggregate: Aggregate::Min,
+            destinations: options.destinations.clone(),
+            travel_minutes,
+        }
+    }
+
+    pub fn destinations(&self) -> &[Destination] {
+        &self.destinations
+    }
+
+    /// Minutes to one destination, by its index in `destinations`
+    pub fn minutes_to(&self, destination: usize, point: Point2DData) -> Option<f64> {
+        (self.travel_minutes[destination])(point)
+    }
+
+    /// Minutes to all the destinations, combined by `aggregate`
+    pub fn minutes(&self, point: Point2DData) -> Option<f64> {
+        let minutes: Vec<Option<f64>> = self
+            .travel_minutes
+            .iter()
+            .map(|travel_minutes| travel_minutes(point))
+            .collect();
+        self.aggregate.apply(&self.destinations, &minutes)
+    }
+}
+
+/// Every station and route in each scenario, one per row
+pub fn write_networks<W: Write>(scenarios: &[Scenario], writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["scenario", "kind", "name"])?;
+    for scenario in scenarios {
+        for (kind, names) in &[
+            ("station", &scenario.network.stations),
+            ("route", &scenario.network.routes),
+        ] {
+            for name in names.iter() {
+                writer.write_record([&scenario.name, *kind, name])?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Travel times at one point in two scenarios
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delta {
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+impl Delta {
+    pub fn new(before: &Scenario, after: &Scenario, point: Point2DData) -> Self {
+        Delta {
+            before: before.minutes(point),
+            after: after.minutes(point),
+        }
+    }
+
+    /// Minutes saved, so positive is better. None unless both scenarios can get there.
+    pub fn improvement(&self) -> Option<f64> {
+        Some(self.before? - self.after?)
+    }
+
+    /// Minutes saved as a percentage of the time before
+    pub fn percent_improvement(&self) -> Option<f64> {
+        let before = self.before?;
+        if before == 0.0 {
+            return None;
+        }
+        Some(self.improvement()? / before * 100.0)
+    }
+}
+
+/// The index of the fastest scenario at this point, for categorical maps. Ties go to the first
+/// scenario, so list the status quo first to only count real improvements.
+pub fn winner(scenarios: &[Scenario], point: Point2DData) -> Option<usize> {
+    let mut best: Option<(usize, f64)> = None;
+    for (i, scenario) in scenarios.iter().enumerate() {
+        if let Some(minutes) = scenario.minutes(point) {
+            match best {
+                Some((_, best_minutes)) if best_minutes <= minutes => {}
+                _ => best = Some((i, minutes)),
+            }
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    fn downtown() -> Destination {
+        Destination::point("Downtown", 1.0, Point2DData::new(0.0, 0.0))
+    }
+
+    /// Before, everything is 30 minutes away. After, a new station makes the east side faster.
+    fn before_and_after() -> (Scenario, Scenario) {
+        let before = Scenario::new("Before", BLACK, downtown(), |_| Some(30.0));
+        let after = Scenario::new("After", BLACK, downtown(), |point: Point2DData| {
+            if point.x > 1000.0 {
+                None
+            } else if point.x > 0.0 {
+                Some(20.0)
+            } else {
+                Some(30.0)
+            }
+        });
+        (before, after)
+    }
+
+    #[test]
+    fn test_delta() {
+        let (before, after) = before_and_after();
+
+        let east = Delta::new(&before, &after, Point2DData::new(500.0, 0.0));
+        assert_eq!(east.improvement(), Some(10.0));
+        assert!((east.percent_improvement().unwrap() - 100.0 / 3.0).abs() < 1e-9);
+
+        let west = Delta::new(&before, &after, Point2DData::new(-500.0, 0.0));
+        assert_eq!(west.improvement(), Some(0.0));
+
+        let far_east = Delta::new(&before, &after, Point2DData::new(1500.0, 0.0));
+        assert_eq!(far_east.after, None);
+        assert_eq!(far_east.improvement(), None);
+    }
+
+    #[test]
+    fn test_winner() {
+        let (before, after) = before_and_after();
+        let scenarios = [before, after];
+        assert_eq!(winner(&scenarios, Point2DData::new(500.0, 0.0)), Some(1));
+        // A tie goes to the status quo
+        assert_eq!(winner(&scenarios, Point2DData::new(-500.0, 0.0)), Some(0));
+        assert_eq!(winner(&scenarios, Point2DData::new(1500.0, 0.0)), Some(0));
+
+        let nowhere = Scenario::new("Nowhere", BLACK, downtown(), |_| None);
+        assert_eq!(winner(&[nowhere], Point2DData::new(0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn test_network() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        let before = Network::new(names(&["Lechmere", "Park Street"]), names(&["Green-E"]));
+        let after = Network::new(
+            names(&["Union", "Park Street", "Lechmere", "Union"]),
+            names(&["Green-E", "Green-GLX-Union"]),
+        );
+        assert_eq!(after.stations, names(&["Lechmere", "Park Street", "Union"]));
+        assert_eq!(
+            after.minus(&before),
+            Network::new(names(&["Union"]), names(&["Green-GLX-Union"]))
+        );
+        assert_eq!(before.minus(&after), Network::default());
+
+        let (before, after) = before_and_after();
+        let mut csv = vec![];
+        write_networks(
+            &[
+                before.with_network(Network::new(names(&["Lechmere"]), vec![])),
+                after,
+            ],
+            &mut csv,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "scenario,kind,name\nBefore,station,Lechmere\n"
+        );
+    }
+
+    #[test]
+    fn test_destinations() {
+        let harvard = Destination::point("Harvard", 1.0, Point2DData::new(-1000.0, 0.0));
+        let scenario = Scenario::new("Both", BLACK, downtown(), |_| Some(30.0))
+            .with_destination(harvard, |_| Some(10.0));
+        let point = Point2DData::new(0.0, 0.0);
+        assert_eq!(scenario.destinations().len(), 2);
+        assert_eq!(scenario.minutes_to(1, point), Some(10.0));
+        assert_eq!(scenario.minutes(point), Some(10.0));
+
+        let scenario = scenario.with_aggregate(Aggregate::WeightedAverage);
+        assert_eq!(scenario.minutes(point), Some(20.0));
+    }
+}
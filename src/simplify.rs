@@ -0,0 +1,365 @@
+//! Simplifying geometry down to what's visible at a given scale, so that we don't tessellate
+//! millions of sub-pixel vertices.
+use crate::graphics::{Box2DData, Geom, Point2DData};
+use std::collections::{HashMap, HashSet};
+
+/// How much of data space one pixel covers when this viewport is captured at this size
+pub fn meters_per_pixel(viewport: &Box2DData, size: u32) -> f64 {
+    let size = f64::from(size);
+    ((viewport.max.x - viewport.min.x) / size).max((viewport.max.y - viewport.min.y) / size)
+}
+
+fn distance_to_segment(point: Point2DData, a: Point2DData, b: Point2DData) -> f64 {
+    let ab = b - a;
+    let length_2 = ab.square_length();
+    if length_2 == 0.0 {
+        return (point - a).length();
+    }
+    let t = ((point - a).dot(ab) / length_2).clamp(0.0, 1.0);
+    (point - (a + ab * t)).length()
+}
+
+/// Douglas–Peucker: keep the first and last points, and any point that's further than the
+/// tolerance from the simplified line
+pub fn douglas_peucker(points: &[Point2DData], tolerance: f64) -> Vec<Point2DData> {
+    if points.len() <= 2 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // Avoid recursion since ways can be long
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut farthest = (first, 0.0);
+        for i in first + 1..last {
+            let distance = distance_to_segment(points[i], points[first], points[last]);
+            if distance > farthest.1 {
+                farthest = (i, distance);
+            }
+        }
+        if farthest.1 > tolerance {
+            keep[farthest.0] = true;
+            stack.push((first, farthest.0));
+            stack.push((farthest.0, last));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(&point, _)| point)
+        .collect()
+}
+
+/// Points are shared when they come from the same OSM node, so the bits are identical
+fn point_key(point: Point2DData) -> (u64, u64) {
+    (point.x.to_bits(), point.y.to_bits())
+}
+
+/// Simplifies geometry with Douglas–Peucker while keeping shared edges shared. Points where
+/// geoms start or stop sharing an edge are always kept, and the run of points between two kept
+/// points is simplified the same way no matter which geom it's in, so neighboring buildings don't
+/// pull apart.
+pub struct Simplifier {
+    tolerance: f64,
+    /// For points used by more than one geom, which geoms use them
+    shared: HashMap<(u64, u64), Vec<usize>>,
+}
+
+impl Simplifier {
+    /// The tolerance is in data space; `meters_per_pixel` is a good choice
+    pub fn new<'a, I: IntoIterator<Item = &'a Geom>>(geoms: I, tolerance: f64) -> Self {
+        let mut users: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+        for (i, geom) in geoms.into_iter().enumerate() {
+            let rings: Vec<&[Point2DData]> = match geom {
+                Geom::Point(_) => continue,
+                Geom::Lines { points, .. } => vec![points],
+                Geom::Polygon(points) => vec![points],
+                Geom::MultiPolygon(rings) => rings.iter().map(Vec::as_slice).collect(),
+            };
+            let keys: HashSet<(u64, u64)> = rings
+                .iter()
+                .flat_map(|points| points.iter())
+                .map(|&point| point_key(point))
+                .collect();
+            for key in keys {
+                users.entry(key).or_default().push(i);
+            }
+        }
+        users.retain(|_, users| users.len() > 1);
+
+        Simplifier {
+            tolerance,
+            shared: users,
+        }
+    }
+
+    /// A point is an anchor if it's shared, and the points on either side of it aren't shared by
+    /// exactly the same geoms
+    fn is_anchor(&self, previous: Point2DData, point: Point2DData, next: Point2DData) -> bool {
+        match self.shared.get(&point_key(point)) {
+            Some(users) => {
+                self.shared.get(&point_key(previous)) != Some(users)
+                    || self.shared.get(&point_key(next)) != Some(users)
+            }
+            None => false,
+        }
+    }
+
+    /// Simplify a run between two kept points the same way regardless of direction
+    fn simplify_run(&self, run: &[Point2DData]) -> Vec<Point2DData> {
+        let first = run[0];
+        let last = run[run.len() - 1];
+        if (last.x, last.y) < (first.x, first.y) {
+            let mut reversed = run.to_vec();
+            reversed.reverse();
+            let mut simplified = douglas_peucker(&reversed, self.tolerance);
+            simplified.reverse();
+            simplified
+        } else {
+            douglas_peucker(run, self.tolerance)
+        }
+    }
+
+    /// Split at the anchors, which must include the first and last points, and simplify each run
+    fn simplify_anchored(&self, points: &[Point2DData], anchors: &[usize]) -> Vec<Point2DData> {
+        let mut simplified = vec![points[anchors[0]]];
+        for pair in anchors.windows(2) {
+            simplified.extend(
+                self.simplify_run(&points[pair[0]..=pair[1]])
+                    .into_iter()
+                    .skip(1),
+            );
+        }
+        simplified
+    }
+
+    /// Lines are drawn `width` wide, so that counts towards their size too
+    fn is_smaller_than_tolerance(&self, points: &[Point2DData], width: f64) -> bool {
+        let size = Box2DData::from_points(points).size();
+        size.width + width < self.tolerance && size.height + width < self.tolerance
+    }
+
+    /// Returns None if the geom is too small to see at all
+    pub fn simplify(&self, geom: &Geom) -> Option<Geom> {
+        match geom {
+            Geom::Point(_) => Some(geom.clone()),
+            Geom::Lines { points, width } => {
+                if points.len() < 2 || self.is_smaller_than_tolerance(points, *width) {
+                    return None;
+                }
+                let anchors: Vec<usize> = (0..points.len())
+                    .filter(|&i| {
+                        i == 0
+                            || i == points.len() - 1
+                            || self.is_anchor(points[i - 1], points[i], points[i + 1])
+                    })
+                    .collect();
+                Some(Geom::Lines {
+                    points: self.simplify_anchored(points, &anchors),
+                    width: *width,
+                })
+            }
+            Geom::Polygon(points) => self.simplify_ring(points).map(Geom::Polygon),
+            Geom::MultiPolygon(rings) => {
+                let rings: Vec<Vec<Point2DData>> = rings
+                    .iter()
+                    .filter_map(|ring| self.simplify_ring(ring))
+                    .collect();
+                if rings.is_empty() {
+                    None
+                } else {
+                    Some(Geom::MultiPolygon(rings))
+                }
+            }
+        }
+    }
+
+    /// Returns None if the ring is too small to see at all
+    fn simplify_ring(&self, points: &[Point2DData]) -> Option<Vec<Point2DData>> {
+        if points.len() < 3 || self.is_smaller_than_tolerance(points, 0.0) {
+            return None;
+        }
+        // Close the ring so that the runs wrap around
+        let mut ring = points.to_vec();
+        ring.push(points[0]);
+
+        let n = points.len();
+        let mut anchors: Vec<usize> = (0..n)
+            .filter(|&i| self.is_anchor(points[(i + n - 1) % n], points[i], points[(i + 1) % n]))
+            .collect();
+        if anchors.len() < 2 {
+            // Not enough shared points, so also anchor on the point farthest away
+            let base = anchors.first().cloned().unwrap_or(0);
+            let farthest = (0..n)
+                .max_by(|&i, &j| {
+                    let d_i = (points[i] - points[base]).square_length();
+                    let d_j = (points[j] - points[base]).square_length();
+                    d_i.partial_cmp(&d_j).unwrap()
+                })
+                .unwrap();
+            anchors = vec![base.min(farthest), base.max(farthest)];
+        }
+        // Rotate the ring to start at the first anchor
+        let start = anchors[0];
+        let ring: Vec<Point2DData> = ring[start..points.len()]
+            .iter()
+            .chain(&ring[..=start])
+            .cloned()
+            .collect();
+        let mut anchors: Vec<usize> = anchors.iter().map(|i| i - start).collect();
+        anchors.push(points.len());
+
+        let mut simplified = self.simplify_anchored(&ring, &anchors);
+        simplified.pop();
+        if simplified.len() >= 3 {
+            Some(simplified)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meters_per_pixel() {
+        let viewport = Box2DData::new(
+            Point2DData::new(-3000.0, -3000.0),
+            Point2DData::new(3000.0, 3000.0),
+        );
+        assert_eq!(meters_per_pixel(&viewport, 4096), 6000.0 / 4096.0);
+    }
+
+    #[test]
+    fn test_douglas_peucker() {
+        let points = vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(1.0, 0.1),
+            Point2DData::new(2.0, -0.1),
+            Point2DData::new(3.0, 5.0),
+            Point2DData::new(4.0, 6.0),
+            Point2DData::new(5.0, 7.0),
+        ];
+        assert_eq!(
+            douglas_peucker(&points, 0.5),
+            vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(2.0, -0.1),
+                Point2DData::new(3.0, 5.0),
+                Point2DData::new(5.0, 7.0),
+            ]
+        );
+        assert_eq!(douglas_peucker(&points, 100.0).len(), 2);
+    }
+
+    #[test]
+    fn test_drops_small_features() {
+        let tiny = Geom::Polygon(vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(0.1, 0.0),
+            Point2DData::new(0.1, 0.1),
+        ]);
+        let simplifier = Simplifier::new(vec![&tiny], 1.0);
+        assert!(simplifier.simplify(&tiny).is_none());
+
+        let point = Geom::Point(Point2DData::new(0.0, 0.0));
+        assert!(simplifier.simplify(&point).is_some());
+
+        // A short line is still visible if it's drawn wide enough
+        let short = |width| Geom::Lines {
+            points: vec![Point2DData::new(0.0, 0.0), Point2DData::new(0.5, 0.0)],
+            width,
+        };
+        assert!(simplifier.simplify(&short(0.1)).is_none());
+        assert!(simplifier.simplify(&short(2.0)).is_some());
+
+        // A hole too small to see is dropped, and the outer ring is kept
+        let holey = Geom::MultiPolygon(vec![
+            vec![
+                Point2DData::new(0.0, 0.0),
+                Point2DData::new(10.0, 0.0),
+                Point2DData::new(10.0, 10.0),
+                Point2DData::new(0.0, 10.0),
+            ],
+            vec![
+                Point2DData::new(5.0, 5.0),
+                Point2DData::new(5.1, 5.0),
+                Point2DData::new(5.1, 5.1),
+            ],
+        ]);
+        match simplifier.simplify(&holey).unwrap() {
+            Geom::MultiPolygon(rings) => assert_eq!(rings.len(), 1),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_polygon_keeps_corners() {
+        // A square with a slightly wobbly top edge
+        let square = Geom::Polygon(vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(10.0, 0.0),
+            Point2DData::new(10.0, 10.0),
+            Point2DData::new(5.0, 10.1),
+            Point2DData::new(0.0, 10.0),
+        ]);
+        let simplifier = Simplifier::new(vec![&square], 0.5);
+        match simplifier.simplify(&square).unwrap() {
+            Geom::Polygon(points) => assert_eq!(
+                points,
+                vec![
+                    Point2DData::new(0.0, 0.0),
+                    Point2DData::new(10.0, 0.0),
+                    Point2DData::new(10.0, 10.0),
+                    Point2DData::new(0.0, 10.0),
+                ]
+            ),
+            _ => panic!(),
+        }
+    }
+
+    /// Two buildings share a wiggly wall, traversed in opposite directions. After simplifying, the
+    /// wall should still be identical in both.
+    #[test]
+    fn test_shared_edges_stay_shared() {
+        let wall = [
+            Point2DData::new(10.0, 0.0),
+            Point2DData::new(10.3, 2.0),
+            Point2DData::new(9.8, 4.0),
+            Point2DData::new(10.4, 6.0),
+            Point2DData::new(10.0, 10.0),
+        ];
+        let mut left = vec![Point2DData::new(0.0, 10.0), Point2DData::new(0.0, 0.0)];
+        left.extend(wall.iter().cloned());
+        let mut right = vec![Point2DData::new(20.0, 0.0), Point2DData::new(20.0, 10.0)];
+        right.extend(wall.iter().rev().cloned());
+        let left = Geom::Polygon(left);
+        let right = Geom::Polygon(right);
+
+        let simplifier = Simplifier::new(vec![&left, &right], 0.5);
+        let wall_points = |geom: Geom| -> Vec<Point2DData> {
+            match geom {
+                Geom::Polygon(points) => {
+                    let mut wall_points: Vec<Point2DData> = points
+                        .into_iter()
+                        .filter(|point| point.x > 5.0 && point.x < 15.0)
+                        .collect();
+                    wall_points.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
+                    wall_points
+                }
+                _ => panic!(),
+            }
+        };
+        let left_wall = wall_points(simplifier.simplify(&left).unwrap());
+        let right_wall = wall_points(simplifier.simplify(&right).unwrap());
+        assert_eq!(left_wall, right_wall);
+        assert!(left_wall.len() < wall.len());
+    }
+}
@@ -0,0 +1,316 @@
+//! R-tree indexes over data space, so that viewport culling and nearest-neighbor lookups don't
+//! have to scan every node and way.
+use crate::graphics::{Box2DData, Point2DData};
+use crate::MyNode;
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+
+/// Something with a single location in data space, e.g. a node or a station entrance
+pub trait Located {
+    fn location(&self) -> Point2DData;
+}
+
+impl Located for MyNode {
+    fn location(&self) -> Point2DData {
+        self.to_point2d()
+    }
+}
+
+struct PointEntry<T> {
+    location: [f64; 2],
+    t: T,
+}
+
+impl<T> RTreeObject for PointEntry<T> {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.location)
+    }
+}
+
+impl<T> PointDistance for PointEntry<T> {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        let dx = self.location[0] - point[0];
+        let dy = self.location[1] - point[1];
+        dx * dx + dy * dy
+    }
+}
+
+struct BoxEntry<T> {
+    envelope: AABB<[f64; 2]>,
+    t: T,
+}
+
+impl<T> RTreeObject for BoxEntry<T> {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+fn to_array(point: Point2DData) -> [f64; 2] {
+    [point.x, point.y]
+}
+
+fn to_aabb(box2d: &Box2DData) -> AABB<[f64; 2]> {
+    AABB::from_corners(to_array(box2d.min), to_array(box2d.max))
+}
+
+/// Answers bounding box and nearest neighbor queries over located things
+pub struct PointIndex<T> {
+    tree: RTree<PointEntry<T>>,
+}
+
+impl<T: Located> PointIndex<T> {
+    pub fn new(ts: Vec<T>) -> Self {
+        PointIndex {
+            tree: RTree::bulk_load(
+                ts.into_iter()
+                    .map(|t| PointEntry {
+                        location: to_array(t.location()),
+                        t,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<T> PointIndex<T> {
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Everything inside the box, including its edges
+    pub fn in_box(&self, box2d: &Box2DData) -> Vec<&T> {
+        self.tree
+            .locate_in_envelope(&to_aabb(box2d))
+            .map(|entry| &entry.t)
+            .collect()
+    }
+
+    pub fn nearest(&self, point: Point2DData) -> Option<&T> {
+        self.tree
+            .nearest_neighbor(&to_array(point))
+            .map(|entry| &entry.t)
+    }
+
+    /// Up to k things, closest first
+    pub fn k_nearest(&self, point: Point2DData, k: usize) -> Vec<&T> {
+        self.tree
+            .nearest_neighbor_iter(&to_array(point))
+            .take(k)
+            .map(|entry| &entry.t)
+            .collect()
+    }
+}
+
+/// Answers bounding box queries over things with extent, e.g. ways
+pub struct BoxIndex<T> {
+    tree: RTree<BoxEntry<T>>,
+}
+
+impl<T> BoxIndex<T> {
+    pub fn new(ts: Vec<(Box2DData, T)>) -> Self {
+        BoxIndex {
+            tree: RTree::bulk_load(
+                ts.into_iter()
+                    .map(|(box2d, t)| BoxEntry {
+                        envelope: to_aabb(&box2d),
+                        t,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Everything whose bounding box overlaps this box at all. Unlike checking whether any node is
+    /// inside, this also catches ways that cross the box without stopping in it.
+    pub fn intersecting(&self, box2d: &Box2DData) -> Vec<&T> {
+        self.tree
+            .locate_in_envelope_intersecting(&to_aabb(box2d))
+            .map(|entry| &entry.t)
+            .collect()
+    }
+
+    /// Everything whose bounding box contains this point. These are the candidates for a
+    /// point-in-polygon test.
+    pub fn containing(&self, point: Point2DData) -> Vec<&T> {
+        self.intersecting(&Box2DData::new(point, point))
+    }
+}
+
+/// The smallest box containing all of these points, or None if there aren't any
+pub fn bounds(points: &[Point2DData]) -> Option<Box2DData> {
+    if points.is_empty() {
+        None
+    } else {
+        Some(Box2DData::from_points(points))
+    }
+}
+
+/// The average of the points, e.g. roughly the middle of a building's outline. A closed ring's
+/// last point, which repeats the first, only counts once. None if there aren't any points.
+pub fn mean_point(points: &[Point2DData]) -> Option<Point2DData> {
+    let points = if points.len() > 2 && points.first() == points.last() {
+        &points[1..]
+    } else {
+        points
+    };
+    if points.is_empty() {
+        return None;
+    }
+    let sum = points
+        .iter()
+        .fold(Point2DData::zero(), |sum, &point| sum + point.to_vector());
+    Some(sum / points.len() as f64)
+}
+
+/// Even-odd rule, so holes work if they are included as part of the ring. Like `Geom::Polygon`,
+/// the first point should not be repeated at the end.
+pub fn polygon_contains(polygon: &[Point2DData], point: Point2DData) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// The area inside a ring, in square meters, whether or not it repeats its first point
+pub fn polygon_area(ring: &[Point2DData]) -> f64 {
+    let twice_area: f64 = ring
+        .iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    twice_area.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_nodes() -> Vec<MyNode> {
+        (0..10)
+            .flat_map(|x| {
+                (0..10).map(move |y| MyNode {
+                    x_y_meters: [x as f64 * 100.0, y as f64 * 100.0],
+                    node_id: x * 10 + y,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_in_box() {
+        let index = PointIndex::new(grid_nodes());
+        assert_eq!(index.len(), 100);
+
+        let found = index.in_box(&Box2DData::new(
+            Point2DData::new(150.0, 150.0),
+            Point2DData::new(350.0, 250.0),
+        ));
+        let mut ids: Vec<i64> = found.iter().map(|node| node.node_id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![22, 32]);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let index = PointIndex::new(grid_nodes());
+        assert_eq!(
+            index
+                .nearest(Point2DData::new(310.0, 690.0))
+                .unwrap()
+                .node_id,
+            37
+        );
+
+        let k_nearest = index.k_nearest(Point2DData::new(0.0, 0.0), 3);
+        assert_eq!(k_nearest.len(), 3);
+        assert_eq!(k_nearest[0].node_id, 0);
+        let mut rest: Vec<i64> = k_nearest[1..].iter().map(|node| node.node_id).collect();
+        rest.sort();
+        assert_eq!(rest, vec![1, 10]);
+    }
+
+    #[test]
+    fn test_box_index_crossing() {
+        // A long way that crosses the viewport without any node inside it
+        let way = vec![
+            Point2DData::new(-1000.0, 0.0),
+            Point2DData::new(1000.0, 0.0),
+        ];
+        let far_away = vec![
+            Point2DData::new(5000.0, 5000.0),
+            Point2DData::new(5100.0, 5100.0),
+        ];
+        let index = BoxIndex::new(vec![
+            (bounds(&way).unwrap(), "crossing"),
+            (bounds(&far_away).unwrap(), "far"),
+        ]);
+
+        let viewport = Box2DData::new(Point2DData::new(-10.0, -10.0), Point2DData::new(10.0, 10.0));
+        assert_eq!(index.intersecting(&viewport), vec![&"crossing"]);
+        assert_eq!(bounds(&[]), None);
+
+        let closed = [
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(2.0, 0.0),
+            Point2DData::new(2.0, 2.0),
+            Point2DData::new(0.0, 2.0),
+            Point2DData::new(0.0, 0.0),
+        ];
+        assert_eq!(mean_point(&closed), Some(Point2DData::new(1.0, 1.0)));
+        assert_eq!(mean_point(&[]), None);
+    }
+
+    #[test]
+    fn test_point_in_polygon_candidates() {
+        let square = vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(10.0, 0.0),
+            Point2DData::new(10.0, 10.0),
+            Point2DData::new(0.0, 10.0),
+        ];
+        let triangle = vec![
+            Point2DData::new(0.0, 0.0),
+            Point2DData::new(10.0, 0.0),
+            Point2DData::new(0.0, 10.0),
+        ];
+        let index = BoxIndex::new(vec![
+            (bounds(&square).unwrap(), square.clone()),
+            (bounds(&triangle).unwrap(), triangle),
+        ]);
+
+        let point = Point2DData::new(8.0, 8.0);
+        let candidates = index.containing(point);
+        assert_eq!(candidates.len(), 2);
+
+        let containing: Vec<_> = candidates
+            .into_iter()
+            .filter(|polygon| polygon_contains(polygon, point))
+            .collect();
+        assert_eq!(containing, vec![&square]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Stations from the project spreadsheet, e.g. "GLX Project MBTA Data - Stations.csv". Columns
+//! are found by their headers, so they can be reordered and new ones added without breaking
+//! anything:
+//!
+//! | Column                       | Required | Meaning                                            |
+//! |------------------------------|----------|----------------------------------------------------|
+//! | `Name`                       | yes      | E.g. "Gilman"                                      |
+//! | `Lat`, `Long`                | yes      | Where the station is                               |
+//! | `Total Min. to Park St./DTX` | yes      | Minutes to Park Street or Downtown Crossing, including the expected wait |
+//! | `Headway (?)`                | no       | Minutes between trains. Blank means the total is all riding. |
+//! | `Proposed?`                  | no       | 1 for a station that doesn't exist yet             |
+//! | `<line ID>?`                 | no       | 1 if the station is on that line, e.g. `Green?`    |
+//! | `Branches`                   | no       | Branches of its lines the station is on, separated by semicolons, e.g. `GLX-Medford`. Blank for a trunk station. |
+//!
+//! Spreadsheets from before the `Proposed?` column marked proposed stations with `#N/A` in
+//! `Min. to Park St./DTX`, which still works if there's no `Proposed?` column. Other columns are
+//! ignored.
+use crate::line::Line;
+use crate::table::{Columns, TableError};
+use csv::StringRecord;
+use std::io::Read;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StationRecord {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// Waiting plus riding to Park Street or Downtown Crossing
+    pub total_minutes: f64,
+    pub headway_minutes: Option<f64>,
+    pub proposed: bool,
+    /// Line IDs, e.g. Park Street would be on both "Green" and "Red"
+    pub lines: Vec<String>,
+    /// Branches of those lines, e.g. Gilman would be on "GLX-Medford"
+    pub branches: Vec<String>,
+}
+
+/// Rows that could be read, and what was wrong with the rest. A malformed row is skipped instead
+/// of failing the whole file, so one typo in the spreadsheet only loses one station.
+#[derive(Debug, Default)]
+pub struct LoadedStations {
+    pub stations: Vec<StationRecord>,
+    pub errors: Vec<TableError>,
+}
+
+/// Only fails if the file can't be read at all or a required column is missing
+pub fn load_stations<R: Read>(reader: R, lines: &[Line]) -> Result<LoadedStations, TableError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let columns = Columns::new(reader.headers()?);
+    let schema = Schema {
+        name: columns.required("Name")?,
+        lat: columns.required("Lat")?,
+        lon: columns.required("Long")?,
+        total_minutes: columns.required("Total Min. to Park St./DTX")?,
+        headway_minutes: columns.optional("Headway (?)"),
+        proposed: columns.optional("Proposed?"),
+        legacy_proposed: columns.optional("Min. to Park St./DTX"),
+        lines: lines
+            .iter()
+            .filter_map(|line| {
+                columns
+                    .optional(&format!("{}?", line.id))
+                    .map(|i| (i, line))
+            })
+            .collect(),
+        branches: columns.optional("Branches"),
+    };
+
+    let mut loaded = LoadedStations::default();
+    for record in reader.records() {
+        match record
+            .map_err(TableError::from)
+            .and_then(|record| schema.read(&columns, &record))
+        {
+            Ok(station) => loaded.stations.push(station),
+            Err(error) => loaded.errors.push(error),
+        }
+    }
+    Ok(loaded)
+}
+
+/// Column indexes
+struct Schema<'a> {
+    name: usize,
+    lat: usize,
+    lon: usize,
+    total_minutes: usize,
+    headway_minutes: Option<usize>,
+    proposed: Option<usize>,
+    legacy_proposed: Option<usize>,
+    lines: Vec<(usize, &'a Line)>,
+    branches: Option<usize>,
+}
+
+impl<'a> Schema<'a> {
+    fn read(&self, columns: &Columns, record: &StringRecord) -> Result<StationRecord, TableError> {
+        let proposed = match (self.proposed, self.legacy_proposed) {
+            (Some(i), _) => parse_flag(columns, record, i)?,
+            (None, Some(i)) => record[i].trim() == "#N/A",
+            (None, None) => false,
+        };
+        let mut lines = vec![];
+        for (i, line) in &self.lines {
+            if parse_flag(columns, record, *i)? {
+                lines.push(*line);
+            }
+        }
+        // Only branches of the station's own lines, so a typo doesn't quietly leave it off
+        let branches = columns.list(record, self.branches);
+        let on_lines = |branch: &String| lines.iter().any(|line| line.branches.contains(branch));
+        if let Some(i) = self.branches.filter(|_| !branches.iter().all(on_lines)) {
+            return Err(columns.error(record, i));
+        }
+        Ok(StationRecord {
+            name: record[self.name].trim().to_string(),
+            lat: columns.parse(record, self.lat)?,
+            lon: columns.parse(record, self.lon)?,
+            total_minutes: columns.parse(record, self.total_minutes)?,
+            headway_minutes: columns.parse_optional(record, self.headway_minutes)?,
+            proposed,
+            lines: lines.iter().map(|line| line.id.clone()).collect(),
+            branches,
+        })
+    }
+}
+
+/// 1 or 0, or blank for 0, since that's how checkboxes export
+fn parse_flag(columns: &Columns, record: &StringRecord, i: usize) -> Result<bool, TableError> {
+    match record[i].trim().to_lowercase().as_str() {
+        "1" | "yes" | "true" => Ok(true),
+        "" | "0" | "no" | "false" => Ok(false),
+        _ => Err(columns.error(record, i)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::green_line;
+
+    #[test]
+    fn test_load_stations() {
+        let csv = "Long,Lat,Name,Total Min. to Park St./DTX,Proposed?,Green?,Branches\n\
+                   -71.09615,42.387969,Gilman,19,1,1,GLX-Medford\n\
+                   -71.077001,42.371526,Lechmere,12,,TRUE,\n";
+        let loaded = load_stations(csv.as_bytes(), &[green_line()]).unwrap();
+        assert!(loaded.errors.is_empty());
+        assert_eq!(loaded.stations.len(), 2);
+        assert_eq!(loaded.stations[0].name, "Gilman");
+        assert_eq!(loaded.stations[0].lat, 42.387969);
+        assert_eq!(loaded.stations[0].headway_minutes, None);
+        assert!(loaded.stations[0].proposed);
+        assert!(!loaded.stations[1].proposed);
+        assert_eq!(loaded.stations[1].lines, vec!["Green"]);
+        assert_eq!(loaded.stations[0].branches, vec!["GLX-Medford"]);
+        assert!(loaded.stations[1].branches.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_proposed() {
+        let csv = "Name,Min. to Park St./DTX,Headway (?),Total Min. to Park St./DTX,Lat,Long\n\
+                   Gilman,#N/A,8,19,42.387969,-71.09615\n\
+                   Lechmere,10,4,12,42.371526,-71.077001\n";
+        let loaded = load_stations(csv.as_bytes(), &[]).unwrap();
+        assert_eq!(loaded.stations[0].headway_minutes, Some(8.0));
+        assert!(loaded.stations[0].proposed);
+        assert!(!loaded.stations[1].proposed);
+    }
+
+    #[test]
+    fn test_malformed_rows() {
+        let csv = "Name,Total Min. to Park St./DTX,Lat,Long,Green?\n\
+                   Gilman,19,42.387969,-71.09615,1\n\
+                   Magoun,twenty,42.393367,-71.105978,1\n\
+                   Ball,23,42.400086,-71.111111,maybe\n";
+        let loaded = load_stations(csv.as_bytes(), &[green_line()]).unwrap();
+        assert_eq!(loaded.stations.len(), 1);
+        let errors: Vec<(u64, &str)> = loaded
+            .errors
+            .iter()
+            .map(|error| match error {
+                TableError::Parse { line, column, .. } => (*line, column.as_str()),
+                other => panic!("expected a parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(3, "Total Min. to Park St./DTX"), (4, "Green?")]
+        );
+
+        // A branch of a line the station isn't on
+        let csv = "Name,Total Min. to Park St./DTX,Lat,Long,Green?,Branches\n\
+                   Gilman,19,42.387969,-71.09615,0,GLX-Medford\n";
+        let loaded = load_stations(csv.as_bytes(), &[green_line()]).unwrap();
+        assert!(matches!(
+            loaded.errors.as_slice(),
+            [TableError::Parse { line: 2, .. }]
+        ));
+
+        assert!(matches!(
+            load_stations("Name,Lat,Long\n".as_bytes(), &[]),
+            Err(TableError::MissingColumn(_))
+        ));
+    }
+}
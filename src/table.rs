@@ -0,0 +1,158 @@
+//! Reading CSVs by their headers instead of by position, with errors that say which line and
+//! column something went wrong in
+use csv::StringRecord;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum TableError {
+    Csv(csv::Error),
+    MissingColumn(String),
+    /// The line is 1-based and counts the header, like in a spreadsheet
+    Parse {
+        line: u64,
+        column: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::Csv(error) => write!(f, "{}", error),
+            TableError::MissingColumn(column) => write!(f, "missing column {}", column),
+            TableError::Parse {
+                line,
+                column,
+                value,
+            } => write!(f, "line {}: can't parse {} {:?}", line, column, value),
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+impl From<csv::Error> for TableError {
+    fn from(error: csv::Error) -> Self {
+        TableError::Csv(error)
+    }
+}
+
+/// Where each column is, by its header
+pub struct Columns {
+    headers: Vec<String>,
+}
+
+impl Columns {
+    /// Surrounding whitespace in headers doesn't count, since it's invisible in a spreadsheet, and
+    /// neither does the byte order mark that some files start with
+    pub fn new(headers: &StringRecord) -> Self {
+        Columns {
+            headers: headers
+                .iter()
+                .map(|header| header.trim_start_matches('\u{feff}').trim().to_string())
+                .collect(),
+        }
+    }
+
+    pub fn optional(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|header| header == name)
+    }
+
+    pub fn required(&self, name: &str) -> Result<usize, TableError> {
+        self.optional(name)
+            .ok_or_else(|| TableError::MissingColumn(name.to_string()))
+    }
+
+    /// The cell, which is empty if the record is too short to reach it
+    pub fn value<'r>(&self, record: &'r StringRecord, i: usize) -> &'r str {
+        record.get(i).unwrap_or("")
+    }
+
+    /// An error pointing at this column of this record
+    pub fn error(&self, record: &StringRecord, i: usize) -> TableError {
+        TableError::Parse {
+            line: record.position().map_or(0, |position| position.line()),
+            column: self.headers[i].clone(),
+            value: self.value(record, i).to_string(),
+        }
+    }
+
+    pub fn parse<T: FromStr>(&self, record: &StringRecord, i: usize) -> Result<T, TableError> {
+        self.value(record, i)
+            .trim()
+            .parse()
+            .map_err(|_| self.error(record, i))
+    }
+
+    /// None if the column is missing or the cell is blank
+    pub fn parse_optional<T: FromStr>(
+        &self,
+        record: &StringRecord,
+        i: Option<usize>,
+    ) -> Result<Option<T>, TableError> {
+        match i {
+            Some(i) if !self.value(record, i).trim().is_empty() => self.parse(record, i).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Values separated by semicolons, e.g. "B;C;D;E". Empty if the column is missing or the
+    /// cell is blank.
+    pub fn list(&self, record: &StringRecord, i: Option<usize>) -> Vec<String> {
+        match i {
+            Some(i) => self
+                .value(record, i)
+                .split(';')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(String::from)
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader("\u{feff}a, b ,c,d\n1,x,\n".as_bytes());
+        let columns = Columns::new(reader.headers().unwrap());
+        assert_eq!(columns.optional("a"), Some(0));
+        assert_eq!(columns.optional("b"), Some(1));
+        assert!(matches!(
+            columns.required("e"),
+            Err(TableError::MissingColumn(_))
+        ));
+
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(columns.parse::<u32>(&record, 0).unwrap(), 1);
+        assert_eq!(
+            columns.parse_optional::<u32>(&record, Some(2)).unwrap(),
+            None
+        );
+        assert_eq!(
+            columns
+                .parse_optional::<u32>(&record, columns.optional("e"))
+                .unwrap(),
+            None
+        );
+        // Past the end of a short row
+        assert_eq!(columns.value(&record, 3), "");
+        assert_eq!(columns.list(&record, Some(1)), vec!["x"]);
+        assert!(columns.list(&record, Some(2)).is_empty());
+        match columns.parse::<u32>(&record, 1) {
+            Err(TableError::Parse {
+                line,
+                column,
+                value,
+            }) => assert_eq!((line, column.as_str(), value.as_str()), (2, "b", "x")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
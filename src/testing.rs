@@ -0,0 +1,51 @@
+//! Fixtures that the tests of several modules share
+use crate::gtfs::{Feed, Seconds};
+use crate::line::{Line, Mode};
+use crate::proposed::{ProposedLine, ProposedStation};
+use std::collections::HashMap;
+
+/// OSM-style tags, e.g. `tags(&[("highway", "footway")])`
+pub fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// With the GLX branches, which are all that the stations in tests are on
+pub fn green_line() -> Line {
+    Line {
+        id: String::from("Green"),
+        name: String::from("Green Line"),
+        color: [0.0, 0.5, 0.2, 1.0],
+        mode: Mode::LightRail,
+        branches: vec![String::from("GLX-Union"), String::from("GLX-Medford")],
+    }
+}
+
+/// A train every 10 minutes between stops "a" and "b", which are about a kilometer apart, from
+/// `start` until `end` every day
+pub fn two_stop_feed(start: Seconds, end: Seconds) -> Feed {
+    let station = |id: &str, lat: f64| ProposedStation {
+        id: id.to_string(),
+        name: id.to_string(),
+        lat,
+        lon: -71.08,
+    };
+    let line = ProposedLine {
+        route_id: String::from("Green-GLX"),
+        name: String::from("GLX"),
+        route_type: 0,
+        color: None,
+        stations: vec![station("a", 42.37), station("b", 42.38)],
+        meters_per_second: 10.0,
+        dwell: 30,
+        headway: 600,
+        start,
+        end,
+        through_route_id: None,
+    };
+    let mut feed = Feed::default();
+    line.add_to(&mut feed);
+    feed
+}
@@ -0,0 +1,728 @@
+//! Time-dependent transit routing over a GTFS schedule with the Connection Scan Algorithm:
+//! https://arxiv.org/abs/1703.05997
+//!
+//! Unlike a fixed number of minutes per station, this accounts for waiting for the next train,
+//! transferring, and what time of day it is.
+use crate::graphics::Point2DData;
+use crate::gtfs::{Date, Feed, Seconds, Station};
+use crate::isochrone::TravelTimeField;
+use crate::routing::{Heading, Snap, StreetGraph};
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::iter;
+use std::sync::Arc;
+
+/// Never reached
+pub const NEVER: Seconds = Seconds::MAX;
+
+/// How long it takes to change platforms within a station, when the feed doesn't say
+pub const DEFAULT_TRANSFER_SECONDS: Seconds = 120;
+
+/// A vehicle going from one stop to the next without stopping in between
+#[derive(Clone, Debug, PartialEq)]
+pub struct Connection {
+    pub from: usize,
+    pub to: usize,
+    pub departure: Seconds,
+    pub arrival: Seconds,
+    /// Each run of a frequency-based trip is a separate trip here
+    pub trip: usize,
+}
+
+/// Every connection running on one day, sorted by departure, plus how to walk between stops
+pub struct Timetable {
+    pub stop_ids: Vec<String>,
+    stop_index: HashMap<String, usize>,
+    pub connections: Vec<Connection>,
+    n_trips: usize,
+    /// For each stop, the stops you can walk to and how long it takes
+    footpaths: Vec<Vec<(usize, Seconds)>>,
+}
+
+impl Timetable {
+    /// The trips on these route types that run on this date, e.g. `&[0, 1]` for light rail and
+    /// subway. Transfers come from the feed and between platforms of the same station.
+    pub fn from_feed(feed: &Feed, date: Date, route_types: &[u16]) -> Self {
+        let mut stop_ids: Vec<String> = feed.stops.keys().cloned().collect();
+        stop_ids.sort();
+        let stop_index: HashMap<String, usize> = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(i, stop_id)| (stop_id.clone(), i))
+            .collect();
+
+        let services: HashSet<&str> = feed.services_on(date).into_iter().collect();
+        let mut frequencies: HashMap<&str, Vec<_>> = HashMap::new();
+        for frequency in &feed.frequencies {
+            frequencies
+                .entry(frequency.trip_id.as_str())
+                .or_default()
+                .push(frequency);
+        }
+
+        let mut trips: Vec<_> = feed
+            .trips
+            .values()
+            .filter(|trip| {
+                services.contains(trip.service_id.as_str())
+                    && trip.stop_times.len() >= 2
+                    && matches!(
+                        feed.routes.get(&trip.route_id),
+                        Some(route) if route_types.contains(&route.route_type)
+                    )
+            })
+            .collect();
+        trips.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut connections = vec![];
+        let mut n_trips = 0;
+        for trip in trips {
+            // How much to shift each run of the trip from its own stop times
+            let first_departure = i64::from(trip.stop_times[0].departure);
+            let offsets: Vec<i64> = match frequencies.get(trip.id.as_str()) {
+                Some(frequencies) => frequencies
+                    .iter()
+                    .flat_map(|frequency| {
+                        (frequency.start..frequency.end)
+                            .step_by(frequency.headway.max(1) as usize)
+                            .map(|start| i64::from(start) - first_departure)
+                    })
+                    .collect(),
+                None => vec![0],
+            };
+            for offset in offsets {
+                for pair in trip.stop_times.windows(2) {
+                    let (from, to) = match (
+                        stop_index.get(&pair[0].stop_id),
+                        stop_index.get(&pair[1].stop_id),
+                    ) {
+                        (Some(&from), Some(&to)) => (from, to),
+                        _ => continue,
+                    };
+                    connections.push(Connection {
+                        from,
+                        to,
+                        departure: (i64::from(pair[0].departure) + offset) as Seconds,
+                        arrival: (i64::from(pair[1].arrival) + offset) as Seconds,
+                        trip: n_trips,
+                    });
+                }
+                n_trips += 1;
+            }
+        }
+        connections.sort_by_key(|connection| (connection.departure, connection.arrival));
+
+        // Platforms of the same station, then the feed's transfers, which take precedence
+        let mut footpaths: HashMap<(usize, usize), Seconds> = HashMap::new();
+        let mut platforms: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, stop_id) in stop_ids.iter().enumerate() {
+            platforms
+                .entry(feed.station_id(stop_id))
+                .or_default()
+                .push(i);
+        }
+        for stops in platforms.values() {
+            for &from in stops {
+                for &to in stops {
+                    if from != to {
+                        footpaths.insert((from, to), DEFAULT_TRANSFER_SECONDS);
+                    }
+                }
+            }
+        }
+        for transfer in &feed.transfers {
+            if let (Some(&from), Some(&to)) = (
+                stop_index.get(&transfer.from_stop_id),
+                stop_index.get(&transfer.to_stop_id),
+            ) {
+                if from == to {
+                    continue;
+                }
+                if transfer.transfer_type == 3 {
+                    footpaths.remove(&(from, to));
+                } else {
+                    footpaths.insert(
+                        (from, to),
+                        transfer
+                            .min_transfer_time
+                            .unwrap_or(DEFAULT_TRANSFER_SECONDS),
+                    );
+                }
+            }
+        }
+        let mut footpaths_from = vec![vec![]; stop_ids.len()];
+        for ((from, to), seconds) in footpaths {
+            footpaths_from[from].push((to, seconds));
+        }
+
+        Timetable {
+            stop_ids,
+            stop_index,
+            connections,
+            n_trips,
+            footpaths: transitive_footpaths(&footpaths_from),
+        }
+    }
+
+    pub fn stop(&self, stop_id: &str) -> Option<usize> {
+        self.stop_index.get(stop_id).cloned()
+    }
+
+    /// The earliest time you can get to the destination, starting at any of the access stops at
+    /// its time and finishing with a walk from any of the egress stops
+    pub fn earliest_arrival(
+        &self,
+        access: &[(usize, Seconds)],
+        egress: &[(usize, Seconds)],
+    ) -> Option<Seconds> {
+        let mut walk_to_destination = vec![None; self.stop_ids.len()];
+        for &(stop, seconds) in egress {
+            walk_to_destination[stop] = Some(seconds);
+        }
+
+        let mut earliest = vec![NEVER; self.stop_ids.len()];
+        let mut best = NEVER;
+        // Get to a stop, and from there to anywhere in walking distance
+        let reach = |stop: usize, time: Seconds, earliest: &mut [Seconds], best: &mut Seconds| {
+            for (stop, time) in iter::once((stop, time)).chain(
+                self.footpaths[stop]
+                    .iter()
+                    .map(|&(to, seconds)| (to, time.saturating_add(seconds))),
+            ) {
+                if time < earliest[stop] {
+                    earliest[stop] = time;
+                    if let Some(seconds) = walk_to_destination[stop] {
+                        *best = (*best).min(time.saturating_add(seconds));
+                    }
+                }
+            }
+        };
+
+        for &(stop, time) in access {
+            reach(stop, time, &mut earliest, &mut best);
+        }
+        let start_time = access.iter().map(|&(_, time)| time).min()?;
+        let start = self
+            .connections
+            .partition_point(|connection| connection.departure < start_time);
+
+        let mut boarded = vec![false; self.n_trips];
+        for connection in &self.connections[start..] {
+            if connection.departure >= best {
+                break;
+            }
+            if boarded[connection.trip] || earliest[connection.from] <= connection.departure {
+                boarded[connection.trip] = true;
+                reach(connection.to, connection.arrival, &mut earliest, &mut best);
+            }
+        }
+
+        if best == NEVER {
+            None
+        } else {
+            Some(best)
+        }
+    }
+
+    /// The earliest time you can get to every stop, starting at any of the access stops at its
+    /// time. Connections leaving after `until` aren't taken, so a stop that can only be reached
+    /// later is `NEVER`.
+    pub fn earliest_arrivals(&self, access: &[(usize, Seconds)], until: Seconds) -> Vec<Seconds> {
+        let mut earliest = vec![NEVER; self.stop_ids.len()];
+        // Get to a stop, and from there to anywhere in walking distance
+        let reach = |stop: usize, time: Seconds, earliest: &mut [Seconds]| {
+            for (stop, time) in iter::once((stop, time)).chain(
+                self.footpaths[stop]
+                    .iter()
+                    .map(|&(to, seconds)| (to, time.saturating_add(seconds))),
+            ) {
+                earliest[stop] = earliest[stop].min(time);
+            }
+        };
+
+        for &(stop, time) in access {
+            reach(stop, time, &mut earliest);
+        }
+        let start_time = match access.iter().map(|&(_, time)| time).min() {
+            Some(start_time) => start_time,
+            None => return earliest,
+        };
+        let start = self
+            .connections
+            .partition_point(|connection| connection.departure < start_time);
+
+        let mut boarded = vec![false; self.n_trips];
+        for connection in &self.connections[start..] {
+            if connection.departure > until {
+                break;
+            }
+            if boarded[connection.trip] || earliest[connection.from] <= connection.departure {
+                boarded[connection.trip] = true;
+                reach(connection.to, connection.arrival, &mut earliest);
+            }
+        }
+        earliest
+    }
+
+    /// Arrival times at the destination from every stop at every time of day, in one backward
+    /// scan
+    pub fn profiles(&self, egress: &[(usize, Seconds)]) -> Profiles {
+        let mut footpaths_to = vec![vec![]; self.stop_ids.len()];
+        for (from, footpaths) in self.footpaths.iter().enumerate() {
+            for &(to, seconds) in footpaths {
+                footpaths_to[to].push((from, seconds));
+            }
+        }
+
+        // You can also walk to an egress stop and keep walking
+        let mut walk_to_destination = vec![NEVER; self.stop_ids.len()];
+        for &(stop, seconds) in egress {
+            walk_to_destination[stop] = walk_to_destination[stop].min(seconds);
+            for &(from, footpath_seconds) in &footpaths_to[stop] {
+                walk_to_destination[from] =
+                    walk_to_destination[from].min(footpath_seconds + seconds);
+            }
+        }
+        let walk_to_destination = walk_to_destination
+            .into_iter()
+            .map(|seconds| {
+                if seconds == NEVER {
+                    None
+                } else {
+                    Some(seconds)
+                }
+            })
+            .collect();
+
+        let mut profiles = Profiles {
+            pairs: vec![vec![]; self.stop_ids.len()],
+            walk_to_destination,
+        };
+        let mut trip_arrival = vec![NEVER; self.n_trips];
+        for connection in self.connections.iter().rev() {
+            let arrival = trip_arrival[connection.trip]
+                .min(profiles.arrival(connection.to, connection.arrival));
+            if arrival == NEVER {
+                continue;
+            }
+            trip_arrival[connection.trip] = arrival;
+            insert_pair(
+                &mut profiles.pairs[connection.from],
+                connection.departure,
+                arrival,
+            );
+            for &(from, seconds) in &footpaths_to[connection.from] {
+                if let Some(departure) = connection.departure.checked_sub(seconds) {
+                    insert_pair(&mut profiles.pairs[from], departure, arrival);
+                }
+            }
+        }
+        profiles
+    }
+}
+
+/// Connection scan only follows one footpath at a time, so if you can walk from a to b and from b
+/// to c, this adds a way to walk straight from a to c
+fn transitive_footpaths(footpaths: &[Vec<(usize, Seconds)>]) -> Vec<Vec<(usize, Seconds)>> {
+    footpaths
+        .iter()
+        .enumerate()
+        .map(|(from, direct)| {
+            if direct.is_empty() {
+                return vec![];
+            }
+            // Dijkstra's algorithm over the footpaths, which are few
+            let mut seconds: HashMap<usize, Seconds> = HashMap::new();
+            let mut heap = BinaryHeap::new();
+            heap.push(Reverse((0, from)));
+            while let Some(Reverse((time, stop))) = heap.pop() {
+                if seconds.contains_key(&stop) {
+                    continue;
+                }
+                seconds.insert(stop, time);
+                for &(to, footpath_seconds) in &footpaths[stop] {
+                    if !seconds.contains_key(&to) {
+                        heap.push(Reverse((time + footpath_seconds, to)));
+                    }
+                }
+            }
+            let mut reachable: Vec<(usize, Seconds)> =
+                seconds.into_iter().filter(|&(to, _)| to != from).collect();
+            reachable.sort();
+            reachable
+        })
+        .collect()
+}
+
+/// Add a (departure, arrival) pair to a profile, keeping only the pairs that aren't beaten by
+/// leaving later and arriving no later. The pairs stay sorted by departure, which makes their
+/// arrivals sorted too.
+fn insert_pair(pairs: &mut Vec<(Seconds, Seconds)>, departure: Seconds, arrival: Seconds) {
+    let i = pairs.partition_point(|&(d, _)| d < departure);
+    let mut end = i;
+    if let Some(&(d, a)) = pairs.get(i) {
+        if a <= arrival {
+            return;
+        }
+        if d == departure {
+            end += 1;
+        }
+    }
+    let mut start = i;
+    while start > 0 && pairs[start - 1].1 >= arrival {
+        start -= 1;
+    }
+    pairs.splice(start..end, iter::once((departure, arrival)));
+}
+
+/// For each stop, when you'd get to the destination depending on when you leave
+pub struct Profiles {
+    pairs: Vec<Vec<(Seconds, Seconds)>>,
+    walk_to_destination: Vec<Option<Seconds>>,
+}
+
+impl Profiles {
+    /// The earliest arrival at the destination when you're at this stop at this time. `NEVER`
+    /// if you can't get there from here.
+    fn arrival(&self, stop: usize, time: Seconds) -> Seconds {
+        let pairs = &self.pairs[stop];
+        let by_transit = pairs
+            .get(pairs.partition_point(|&(departure, _)| departure < time))
+            .map_or(NEVER, |&(_, arrival)| arrival);
+        let by_walking = self.walk_to_destination[stop].map_or(NEVER, |seconds| time + seconds);
+        by_transit.min(by_walking)
+    }
+
+    pub fn earliest_arrival(&self, stop: usize, time: Seconds) -> Option<Seconds> {
+        match self.arrival(stop, time) {
+            NEVER => None,
+            arrival => Some(arrival),
+        }
+    }
+}
+
+/// How long it takes to walk to each station from anywhere, and back. This only depends on the
+/// stations, so it's shared between destinations.
+pub struct StationWalks {
+    graph: Arc<StreetGraph>,
+    stations: Vec<StationWalk>,
+}
+
+/// A station's stops, and the walking fields around it. Hills and one-way streets make the walk
+/// there different from the walk back.
+struct StationWalk {
+    stops: Vec<usize>,
+    to: TravelTimeField,
+    from: TravelTimeField,
+}
+
+impl StationWalks {
+    /// Two walking Dijkstras per station, one each way, which is the slow part
+    pub fn new(graph: Arc<StreetGraph>, timetable: &Timetable, stations: &[Station]) -> Self {
+        let stations = stations
+            .par_iter()
+            .map(|station| {
+                let stops = station
+                    .stop_ids
+                    .iter()
+                    .filter_map(|stop_id| timetable.stop(stop_id))
+                    .collect();
+                let field = |heading| {
+                    TravelTimeField::new(graph.clone(), &[(station.location_x_y, 0.0)], heading)
+                };
+                StationWalk {
+                    stops,
+                    to: field(Heading::Inbound),
+                    from: field(Heading::Outbound),
+                }
+            })
+            .collect();
+        StationWalks { graph, stations }
+    }
+
+    /// Each stop and the minutes to walk between it and this point, for the stations that can be
+    /// walked to at all. `Inbound` is for walking from the point to the stations, and `Outbound`
+    /// for walking from the stations to the point.
+    pub fn stops_near(&self, snap: &Snap, heading: Heading) -> Vec<(usize, f64)> {
+        self.stations
+            .iter()
+            .filter_map(|station| {
+                let walk = match heading {
+                    Heading::Inbound => &station.to,
+                    Heading::Outbound => &station.from,
+                };
+                let minutes = walk.sample_snap(snap)?;
+                Some(station.stops.iter().map(move |&stop| (stop, minutes)))
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// Door-to-door minutes to a destination, leaving at one time: walk to any station, ride and
+/// transfer, then walk from the last station. Walking the whole way counts too.
+pub struct DoorToDoor {
+    departure: Seconds,
+    profiles: Profiles,
+    walks: Arc<StationWalks>,
+    walk_to_destination: TravelTimeField,
+}
+
+impl DoorToDoor {
+    /// The destination is any of the given points, e.g. Park Street or Downtown Crossing, or the
+    /// corners of a campus. The last leg is a walk from whichever station is best.
+    pub fn new(
+        walks: Arc<StationWalks>,
+        timetable: &Timetable,
+        destination: &[Point2DData],
+        departure: Seconds,
+    ) -> Self {
+        let egress: Vec<(usize, Seconds)> = walks
+            .stations
+            .iter()
+            .filter_map(|station| {
+                let minutes = destination
+                    .iter()
+                    .filter_map(|&point| station.from.sample(point))
+                    .fold(None, |best: Option<f64>, minutes| {
+                        Some(best.map_or(minutes, |best| best.min(minutes)))
+                    })?;
+                let seconds = (minutes * 60.0).round() as Seconds;
+                Some(station.stops.iter().map(move |&stop| (stop, seconds)))
+            })
+            .flatten()
+            .collect();
+        let walk_to_destination = TravelTimeField::new(
+            walks.graph.clone(),
+            &destination
+                .iter()
+                .map(|&point| (point, 0.0))
+                .collect::<Vec<_>>(),
+            Heading::Inbound,
+        );
+
+        DoorToDoor {
+            departure,
+            profiles: timetable.profiles(&egress),
+            walks,
+            walk_to_destination,
+        }
+    }
+
+    /// None if there's no street nearby, or no way to get to the destination
+    pub fn sample(&self, point: Point2DData) -> Option<f64> {
+        let snap = self.walk_to_destination.graph().snap(point)?;
+        let leave_at = |minutes: f64| self.departure + (minutes * 60.0).round() as Seconds;
+
+        let mut arrival = self
+            .walk_to_destination
+            .sample_snap(&snap)
+            .map_or(NEVER, leave_at);
+        for (stop, minutes) in self.walks.stops_near(&snap, Heading::Inbound) {
+            arrival = arrival.min(self.profiles.arrival(stop, leave_at(minutes)));
+        }
+        if arrival == NEVER {
+            None
+        } else {
+            Some(f64::from(arrival - self.departure) / 60.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gtfs::{Calendar, Route, Stop, StopTime, Transfer, Trip};
+
+    fn stop(id: &str, parent_station: Option<&str>) -> Stop {
+        Stop {
+            id: id.to_string(),
+            name: id.to_string(),
+            lat: 0.0,
+            lon: 0.0,
+            location_type: 0,
+            parent_station: parent_station.map(String::from),
+        }
+    }
+
+    fn trip(id: &str, route_id: &str, stop_times: &[(&str, Seconds)]) -> Trip {
+        Trip {
+            id: id.to_string(),
+            route_id: route_id.to_string(),
+            service_id: String::from("weekday"),
+            direction_id: None,
+            stop_times: stop_times
+                .iter()
+                .enumerate()
+                .map(|(i, &(stop_id, time))| StopTime {
+                    stop_id: stop_id.to_string(),
+                    arrival: time,
+                    departure: time,
+                    stop_sequence: i as u32,
+                })
+                .collect(),
+        }
+    }
+
+    /// A fast line from a to b, a slow line from a to c, and a transfer from b to c
+    ///
+    /// ```text
+    /// a --(fast, every 10 minutes)--> b ~~(2 minutes)~~> c
+    ///  \--(slow, once at 8:00)-------------------------->/
+    /// ```
+    fn example_feed() -> Feed {
+        let mut feed = Feed::default();
+        for stop in [
+            stop("a", None),
+            stop("b", None),
+            stop("c-1", Some("c")),
+            stop("c-2", Some("c")),
+            stop("c", None),
+        ] {
+            feed.stops.insert(stop.id.clone(), stop);
+        }
+        for id in &["fast", "slow"] {
+            feed.routes.insert(
+                id.to_string(),
+                Route {
+                    id: id.to_string(),
+                    short_name: id.to_string(),
+                    long_name: id.to_string(),
+                    route_type: 1,
+                    color: None,
+                },
+            );
+        }
+        let hour = 3600;
+        for trip in [
+            trip("fast-1", "fast", &[("a", 8 * hour), ("b", 8 * hour + 300)]),
+            trip(
+                "slow-1",
+                "slow",
+                &[("a", 8 * hour), ("c-2", 8 * hour + 1500)],
+            ),
+        ] {
+            feed.trips.insert(trip.id.clone(), trip);
+        }
+        feed.frequencies.push(crate::gtfs::Frequency {
+            trip_id: String::from("fast-1"),
+            start: 8 * hour,
+            end: 9 * hour,
+            headway: 600,
+        });
+        feed.transfers.push(Transfer {
+            from_stop_id: String::from("b"),
+            to_stop_id: String::from("c-1"),
+            transfer_type: 2,
+            min_transfer_time: Some(120),
+        });
+        feed.calendars.insert(
+            String::from("weekday"),
+            Calendar {
+                service_id: String::from("weekday"),
+                days: [true, true, true, true, true, false, false],
+                start_date: 20190101,
+                end_date: 20191231,
+            },
+        );
+        feed
+    }
+
+    #[test]
+    fn test_timetable() {
+        let feed = example_feed();
+        let timetable = Timetable::from_feed(&feed, 20191104, &[1]);
+        // Six runs of the fast trip, and one of the slow trip
+        assert_eq!(timetable.connections.len(), 7);
+        assert_eq!(timetable.connections[0].departure, 8 * 3600);
+        assert!(timetable
+            .connections
+            .windows(2)
+            .all(|pair| pair[0].departure <= pair[1].departure));
+
+        // Nothing runs on Saturday, or on buses
+        assert!(Timetable::from_feed(&feed, 20191109, &[1])
+            .connections
+            .is_empty());
+        assert!(Timetable::from_feed(&feed, 20191104, &[3])
+            .connections
+            .is_empty());
+    }
+
+    #[test]
+    fn test_earliest_arrival() {
+        let timetable = Timetable::from_feed(&example_feed(), 20191104, &[1]);
+        let a = timetable.stop("a").unwrap();
+        let c = timetable.stop("c-1").unwrap();
+        let hour = 3600;
+
+        // The fast train plus the transfer beats the slow train
+        assert_eq!(
+            timetable.earliest_arrival(&[(a, 8 * hour)], &[(c, 0)]),
+            Some(8 * hour + 300 + 120)
+        );
+        // The other platform of c is one more transfer away
+        let c_2 = timetable.stop("c-2").unwrap();
+        assert_eq!(
+            timetable.earliest_arrival(&[(a, 8 * hour)], &[(c_2, 0)]),
+            Some(8 * hour + 300 + 120 + DEFAULT_TRANSFER_SECONDS)
+        );
+        // Just missed one, so wait for the next
+        assert_eq!(
+            timetable.earliest_arrival(&[(a, 8 * hour + 1)], &[(c, 60)]),
+            Some(8 * hour + 900 + 120 + 60)
+        );
+        // After the last train
+        assert_eq!(
+            timetable.earliest_arrival(&[(a, 10 * hour)], &[(c, 0)]),
+            None
+        );
+
+        // Everywhere at once
+        let arrivals = timetable.earliest_arrivals(&[(a, 8 * hour)], 9 * hour);
+        assert_eq!(arrivals[a], 8 * hour);
+        assert_eq!(arrivals[c], 8 * hour + 300 + 120);
+        assert_eq!(
+            arrivals[c_2],
+            8 * hour + 300 + 120 + DEFAULT_TRANSFER_SECONDS
+        );
+        // Unless the trains leave too late
+        let b = timetable.stop("b").unwrap();
+        let too_soon = timetable.earliest_arrivals(&[(a, 8 * hour)], 8 * hour - 1);
+        assert_eq!((too_soon[a], too_soon[b]), (8 * hour, NEVER));
+    }
+
+    /// The backward profile scan should agree with running forward from each stop and time
+    #[test]
+    fn test_profiles_match_earliest_arrival() {
+        let timetable = Timetable::from_feed(&example_feed(), 20191104, &[1]);
+        let egress = [(timetable.stop("c-1").unwrap(), 30)];
+        let profiles = timetable.profiles(&egress);
+        for stop in 0..timetable.stop_ids.len() {
+            for time in (7 * 3600..10 * 3600).step_by(97) {
+                assert_eq!(
+                    profiles.earliest_arrival(stop, time),
+                    timetable.earliest_arrival(&[(stop, time)], &egress),
+                    "from {} at {}",
+                    timetable.stop_ids[stop],
+                    time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_pair() {
+        let mut pairs = vec![];
+        insert_pair(&mut pairs, 100, 200);
+        insert_pair(&mut pairs, 50, 150);
+        // Leaves earlier and arrives later, so it's no use
+        insert_pair(&mut pairs, 40, 160);
+        // Leaves later and arrives earlier than both
+        insert_pair(&mut pairs, 120, 140);
+        assert_eq!(pairs, vec![(120, 140)]);
+        insert_pair(&mut pairs, 10, 20);
+        assert_eq!(pairs, vec![(10, 20), (120, 140)]);
+    }
+}
@@ -0,0 +1,172 @@
+//! How long it takes to walk along a stretch of street, instead of a flat 5 km/h everywhere.
+//! Busy roads are slower than side streets, crossings mean waiting for the light, stairs are slow,
+//! and hills like Prospect Hill are slow going up.
+use crate::graphics::Point2DData;
+use crate::routing::WALKING_METERS_PER_MINUTE;
+use std::collections::HashMap;
+
+/// Tobler's hiking function, relative to flat ground: https://en.wikipedia.org/wiki/Tobler%27s_hiking_function
+/// It's fastest going slightly downhill.
+pub fn slope_factor(slope: f64) -> f64 {
+    (-3.5 * (slope + 0.05).abs()).exp() / (-3.5 * 0.05_f64).exp()
+}
+
+/// Multiplies walking speed along a way with this `highway` tag. Sidewalks along big roads get
+/// interrupted by driveways and side streets.
+pub fn highway_factor(highway: &str) -> f64 {
+    match highway {
+        "footway" | "pedestrian" | "path" | "living_street" | "residential" | "service" => 1.0,
+        "primary" | "secondary" | "trunk" | "primary_link" | "secondary_link" | "trunk_link" => 0.9,
+        "track" | "bridleway" => 0.85,
+        _ => 0.95,
+    }
+}
+
+pub struct WalkCost {
+    pub meters_per_minute: f64,
+    /// Waiting for the walk signal, on average
+    pub signal_minutes: f64,
+    /// Waiting for a gap in traffic, or for cars to stop
+    pub crossing_minutes: f64,
+    /// Multiplies the speed on `highway=steps`, by the length along the ground
+    pub steps_factor: f64,
+    /// Meters above sea level at a point, if there's a DEM. Without one, everywhere is flat.
+    pub elevation: Option<Box<dyn Fn(Point2DData) -> Option<f64> + Send + Sync>>,
+}
+
+impl Default for WalkCost {
+    fn default() -> Self {
+        WalkCost {
+            meters_per_minute: WALKING_METERS_PER_MINUTE,
+            // Half of a typical 90 second cycle
+            signal_minutes: 0.75,
+            crossing_minutes: 0.25,
+            steps_factor: 0.5,
+            elevation: None,
+        }
+    }
+}
+
+impl WalkCost {
+    /// Minutes to walk along the geometry, in order, on a way with these tags. The geometry can be
+    /// part of the way, e.g. a crosswalk split where it meets the road, and `share` is how much of
+    /// the way's length it is, so the wait to cross only counts once.
+    pub fn minutes(
+        &self,
+        tags: &HashMap<String, String>,
+        geometry: &[Point2DData],
+        share: f64,
+    ) -> f64 {
+        let tag = |key: &str| tags.get(key).map(String::as_str);
+        let highway = tag("highway").unwrap_or("");
+        let penalty = if tag("footway") == Some("crossing") || highway == "crossing" {
+            match tag("crossing") {
+                Some("traffic_signals") => self.signal_minutes,
+                _ => self.crossing_minutes,
+            }
+        } else {
+            0.0
+        };
+
+        let speed = if highway == "steps" {
+            self.meters_per_minute * self.steps_factor
+        } else {
+            self.meters_per_minute * highway_factor(highway)
+        };
+        // The steps factor already counts the climb
+        let elevations: Option<Vec<Option<f64>>> = self
+            .elevation
+            .as_ref()
+            .filter(|_| highway != "steps")
+            .map(|elevation| geometry.iter().map(|&point| elevation(point)).collect());
+
+        let walking: f64 = geometry
+            .windows(2)
+            .enumerate()
+            .map(|(i, segment)| {
+                let length = (segment[1] - segment[0]).length();
+                let slope = match &elevations {
+                    Some(elevations) if length > 0.0 => match (elevations[i], elevations[i + 1]) {
+                        (Some(from), Some(to)) => (to - from) / length,
+                        _ => 0.0,
+                    },
+                    _ => 0.0,
+                };
+                // Flat where the elevation is junk, since a NaN cost breaks the search
+                let slope = if slope.is_finite() { slope } else { 0.0 };
+                length / (speed * slope_factor(slope))
+            })
+            .sum();
+        walking + penalty * share
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::tags;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} isn't close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_slope_factor() {
+        assert_close(slope_factor(-0.05), (3.5 * 0.05_f64).exp());
+        assert_close(slope_factor(0.0), 1.0);
+        assert!(slope_factor(0.1) < slope_factor(-0.1));
+    }
+
+    #[test]
+    fn test_minutes() {
+        let geometry = [Point2DData::new(0.0, 0.0), Point2DData::new(100.0, 0.0)];
+        let flat = 100.0 / WALKING_METERS_PER_MINUTE;
+        let cost = WalkCost::default();
+        assert_close(
+            cost.minutes(&tags(&[("highway", "footway")]), &geometry, 1.0),
+            flat,
+        );
+        assert_close(
+            cost.minutes(&tags(&[("highway", "steps")]), &geometry, 1.0),
+            flat * 2.0,
+        );
+        assert_close(
+            cost.minutes(
+                &tags(&[
+                    ("highway", "footway"),
+                    ("footway", "crossing"),
+                    ("crossing", "traffic_signals"),
+                ]),
+                &geometry,
+                0.5,
+            ),
+            flat + 0.75 / 2.0,
+        );
+
+        // Up a 5% grade, going east
+        let hill = WalkCost {
+            elevation: Some(Box::new(|point: Point2DData| Some(point.x * 0.05))),
+            ..WalkCost::default()
+        };
+        let uphill = hill.minutes(&tags(&[("highway", "residential")]), &geometry, 1.0);
+        let mut reversed = geometry.to_vec();
+        reversed.reverse();
+        let downhill = hill.minutes(&tags(&[("highway", "residential")]), &reversed, 1.0);
+        assert_close(uphill, flat / slope_factor(0.05));
+        assert!(uphill > flat && flat > downhill);
+
+        let junk = WalkCost {
+            elevation: Some(Box::new(|point: Point2DData| Some(point.x * f64::NAN))),
+            ..WalkCost::default()
+        };
+        assert_close(
+            junk.minutes(&tags(&[("highway", "residential")]), &geometry, 1.0),
+            flat,
+        );
+    }
+}